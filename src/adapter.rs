@@ -1,9 +1,15 @@
+use crate::auth::Credentials;
+use crate::cover::CoverColours;
+use crate::identity::IssueIdentity;
 use crate::progress::Progress;
-use scraper::Html;
+use crate::validation::detect_source;
+use scraper::{Html, Selector};
+use url::Url;
 
 pub struct IssueData {
     pub links: Vec<String>,
     pub title: String,
+    pub identity: IssueIdentity,
     pub css: String,
     pub cover_image_uri: String,
     pub publication_name: String,
@@ -11,10 +17,105 @@ pub struct IssueData {
 
 pub struct ArticleData {
     pub title: String,
+    pub author: Option<String>,
     pub body: String,
 }
 
+/// A credentials form to POST when logging in to a subscriber site.
+pub struct LoginForm {
+    pub url: String,
+    pub fields: Vec<(String, String)>,
+}
+
+/// What a subscriber-only page says about the current session.
+pub struct SessionStatus {
+    pub logged_in: bool,
+    pub account: Option<String>,
+}
+
+/// The `<meta name="author">` of a page, when present and non-empty.
+pub fn meta_author(doc: &Html) -> Option<String> {
+    let selector = Selector::parse(r#"meta[name="author"]"#).unwrap();
+    doc.select(&selector)
+        .filter_map(|el| el.value().attr("content"))
+        .map(|a| a.trim().to_string())
+        .find(|a| !a.is_empty())
+}
+
 pub trait MagazineAdapter {
+    /// Short identifier used for config sections, env vars and session files.
+    fn key(&self) -> &'static str;
+
+    fn publication_name(&self) -> &'static str;
+
+    /// Language of the publication's text as a BCP 47 tag, e.g. `en-GB`.
+    fn language(&self) -> &'static str;
+
+    /// Host whose cookies authenticate requests for this publication.
+    fn cookie_domain(&self) -> &'static str;
+
+    /// Name prefix of the cookie that marks a logged-in subscriber session.
+    fn login_cookie(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Builds the login form for adapters that support username/password login.
+    fn login_form(&self, _credentials: &Credentials) -> Option<LoginForm> {
+        None
+    }
+
+    /// A subscriber-only page used to verify that a session is still valid.
+    fn session_check_url(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Inspects the page fetched from `session_check_url`.
+    fn session_status(&self, _doc: &Html) -> SessionStatus {
+        SessionStatus {
+            logged_in: false,
+            account: None,
+        }
+    }
+
+    /// Page that links to the current issue, used by `magaziner latest`.
+    fn latest_issue_page(&self) -> &'static str;
+
+    /// Finds the current issue's URL on the page from `latest_issue_page`.
+    fn find_latest_issue(&self, doc: &Html) -> Option<String> {
+        newest_issue_link(doc, self.latest_issue_page())
+    }
+
+    /// Archive URL of the issue with the given identity, used to list
+    /// missing issues. `None` when the identity lacks the needed fields.
+    fn issue_url(&self, _identity: &IssueIdentity) -> Option<String> {
+        None
+    }
+
+    /// Issues in each volume, for publications numbered by volume and issue.
+    /// Lets `library missing` report the issues after the last one held in
+    /// a volume.
+    fn issues_per_volume(&self) -> Option<u32> {
+        None
+    }
+
+    /// Colours for the cover drawn when an issue has none.
+    fn cover_colours(&self) -> CoverColours {
+        CoverColours::default()
+    }
+
     fn extract_issue(&self, doc: &Html, progress: &Progress) -> IssueData;
     fn extract_article(&self, doc: &Html, progress: &Progress) -> ArticleData;
 }
+
+/// Picks the newest issue linked from `doc`, which was fetched from `base`.
+/// Issue URLs sort chronologically (`v47/n06`, `2026/02`), so the greatest wins.
+pub fn newest_issue_link(doc: &Html, base: &str) -> Option<String> {
+    let base = Url::parse(base).ok()?;
+    let link_selector = Selector::parse("a[href]").unwrap();
+    doc.select(&link_selector)
+        .filter_map(|el| base.join(el.value().attr("href")?).ok())
+        .filter(|url| url.host_str() == base.host_str())
+        .map(|url| url.as_str().trim_end_matches('/').to_string())
+        .filter(|url| detect_source(url).is_some())
+        .max()
+}
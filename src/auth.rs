@@ -0,0 +1,487 @@
+use crate::adapter::{LoginForm, MagazineAdapter};
+use crate::browser;
+use crate::config::Config;
+use crate::cookies::{self, Cookie, CookieSource};
+use crate::fetch::{fetch_html_body, make_client};
+use crate::progress::Progress;
+use anyhow::{Context, Result, anyhow};
+use reqwest::blocking::Client;
+use reqwest::cookie::Jar;
+use reqwest::header::SET_COOKIE;
+use reqwest::redirect::Policy;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+// Keep the password out of any `{:?}` output.
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+impl Credentials {
+    /// Reads `<KEY>_USERNAME` / `<KEY>_PASSWORD`, e.g. `HARPERS_USERNAME`.
+    pub fn from_env(key: &str) -> Option<Self> {
+        let prefix = key.to_uppercase();
+        let username = std::env::var(format!("{}_USERNAME", prefix)).ok()?;
+        let password = std::env::var(format!("{}_PASSWORD", prefix)).ok()?;
+        Some(Self { username, password })
+    }
+
+    pub fn from_config(config: &Config, key: &str) -> Option<Self> {
+        let publication = config.publication(key)?;
+        Some(Self {
+            username: publication.username.clone()?,
+            password: publication.password.clone()?,
+        })
+    }
+
+    /// Environment variables win over the config file.
+    pub fn resolve(config: &Config, key: &str) -> Option<Self> {
+        Self::from_env(key).or_else(|| Self::from_config(config, key))
+    }
+}
+
+/// Where the session for `username` at the publication `key` is saved. The
+/// name carries a hash of the username, so each account keeps its own
+/// session without the address appearing on disk.
+pub fn session_path(key: &str, username: &str) -> Option<PathBuf> {
+    let account: String = Sha256::digest(username.as_bytes())[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    dirs::cache_dir().map(|dir| {
+        dir.join("magaziner")
+            .join("sessions")
+            .join(format!("{}-{}.txt", key, account))
+    })
+}
+
+/// Posts `form` and returns the cookies the server set in response. The login
+/// page is fetched first so any test or CSRF cookies it sets are sent back.
+pub fn login(
+    form: &LoginForm,
+    host: &str,
+    login_cookie: Option<&str>,
+    progress: &Progress,
+) -> Result<Vec<Cookie>> {
+    progress.verbose(&format!("Logging in to {}", host));
+    let jar = Arc::new(Jar::default());
+    // Redirects are not followed so the session cookies on the 302 are visible.
+    let client = Client::builder()
+        .cookie_provider(jar)
+        .redirect(Policy::none())
+        .build()?;
+
+    client.get(&form.url).send()?;
+    let response = client
+        .post(&form.url)
+        .form(&form.fields)
+        .send()
+        .with_context(|| format!("Login request to {} failed", host))?;
+
+    let cookies: Vec<Cookie> = response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(|header| cookies::parse_set_cookie(header, host))
+        .collect();
+
+    if let Some(prefix) = login_cookie
+        && !cookies.iter().any(|c| c.name.starts_with(prefix))
+    {
+        return Err(anyhow!(
+            "Login to {} failed (HTTP {}); check your username and password",
+            host,
+            response.status()
+        ));
+    }
+
+    progress.verbose(&format!("Logged in; received {} cookies", cookies.len()));
+    Ok(cookies)
+}
+
+pub fn save_session(path: &Path, cookies: &[Cookie]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    // Created private, so the session is never readable by other users.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to save session to {}", path.display()))?;
+    // An existing file keeps its old mode, so tighten it as well.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(cookies::to_netscape(cookies).as_bytes())
+        .with_context(|| format!("Failed to save session to {}", path.display()))?;
+    Ok(())
+}
+
+/// Session cookies for a publication and a description of where they came from.
+pub struct Session {
+    pub cookies: Vec<Cookie>,
+    pub source: String,
+}
+
+/// Resolves the session cookies for `adapter`, in order of preference: an
+/// exported cookies.txt, a browser profile, a raw `<KEY>_COOKIE` header, then
+/// a username/password login (reusing the saved session while it is valid).
+/// The cookies.txt and profile come from the command line or config file.
+pub fn resolve_session(
+    adapter: &dyn MagazineAdapter,
+    cookies_file: Option<&Path>,
+    browser_profile: Option<&Path>,
+    config: &Config,
+    progress: &Progress,
+) -> Result<Option<Session>> {
+    let host = adapter.cookie_domain();
+
+    // e.g. HARPERS_COOKIE="wordpress_logged_in_xxx=abc123; other_cookie=value",
+    // copied from the Cookie request header of an authenticated browser session.
+    let env_var = format!("{}_COOKIE", adapter.key().to_uppercase());
+    let header = std::env::var(&env_var)
+        .ok()
+        .filter(|header| !header.trim().is_empty());
+    let warn_ignored = |source: &Path| {
+        if header.is_some() {
+            eprintln!(
+                "Warning: {} is set but ignored; using {}",
+                env_var,
+                source.display()
+            );
+        }
+    };
+
+    if let Some(path) = cookies_file {
+        warn_ignored(path);
+        return Ok(Some(Session {
+            cookies: cookies::load_session_cookies(
+                path,
+                host,
+                adapter.login_cookie(),
+                CookieSource::File,
+            )?,
+            source: path.display().to_string(),
+        }));
+    }
+
+    if let Some(profile) = browser_profile {
+        warn_ignored(profile);
+        let source = profile.display().to_string();
+        return Ok(Some(Session {
+            cookies: cookies::filter_session_cookies(
+                browser::load_browser_cookies(profile, host)?,
+                host,
+                adapter.login_cookie(),
+                &source,
+                CookieSource::Browser,
+            )?,
+            source,
+        }));
+    }
+
+    if let Some(header) = header {
+        return Ok(Some(Session {
+            cookies: cookies::filter_session_cookies(
+                cookies::parse_cookie_header(&header, host),
+                host,
+                adapter.login_cookie(),
+                &env_var,
+                CookieSource::Header,
+            )?,
+            source: env_var,
+        }));
+    }
+
+    let Some(credentials) = Credentials::resolve(config, adapter.key()) else {
+        return Ok(None);
+    };
+    let Some(form) = adapter.login_form(&credentials) else {
+        return Ok(None);
+    };
+    let saved = session_path(adapter.key(), &credentials.username);
+
+    if let Some(path) = &saved
+        && path.exists()
+    {
+        match cookies::load_session_cookies(
+            path,
+            host,
+            adapter.login_cookie(),
+            CookieSource::SavedSession,
+        ) {
+            Ok(existing) => {
+                return Ok(Some(Session {
+                    cookies: existing,
+                    source: path.display().to_string(),
+                }));
+            }
+            Err(e) => progress.verbose(&format!("Saved session unusable: {}", e)),
+        }
+    }
+
+    let fresh = login(&form, host, adapter.login_cookie(), progress)?;
+    if let Some(path) = &saved {
+        save_session(path, &fresh)?;
+    }
+    Ok(Some(Session {
+        cookies: fresh,
+        source: format!("login to {}", host),
+    }))
+}
+
+pub fn client_for(adapter: &dyn MagazineAdapter, session: Option<&Session>) -> Result<Client> {
+    let jar = match session {
+        Some(session) => Some(Arc::new(cookies::to_jar(
+            &session.cookies,
+            adapter.cookie_domain(),
+        )?)),
+        None => None,
+    };
+    make_client(jar)
+}
+
+/// Fetches the adapter's subscriber-only page with the configured session and
+/// prints whether it is valid. Returns `false` when the session is not usable.
+pub fn check_session(
+    adapter: &dyn MagazineAdapter,
+    cookies_file: Option<&Path>,
+    browser_profile: Option<&Path>,
+    config: &Config,
+    progress: &Progress,
+) -> Result<bool> {
+    println!("{}", adapter.publication_name());
+
+    let Some(check_url) = adapter.session_check_url() else {
+        println!("  No subscriber session needed");
+        return Ok(true);
+    };
+
+    let session = match resolve_session(adapter, cookies_file, browser_profile, config, progress) {
+        Ok(Some(session)) => session,
+        Ok(None) => {
+            println!("  Credentials: none configured");
+            return Ok(false);
+        }
+        Err(e) => {
+            println!("  Credentials: {}", e);
+            return Ok(false);
+        }
+    };
+    println!("  Credentials: {}", session.source);
+
+    let client = client_for(adapter, Some(&session))?;
+    let doc = match fetch_html_body(&client, check_url, &0, progress) {
+        Ok(doc) => doc,
+        Err(e) => {
+            println!("  Session:     {}", e);
+            return Ok(false);
+        }
+    };
+    let status = adapter.session_status(&doc);
+
+    println!(
+        "  Session:     {}",
+        if status.logged_in {
+            "valid"
+        } else {
+            "NOT logged in"
+        }
+    );
+    if let Some(account) = &status.account {
+        println!("  Account:     {}", account);
+    }
+
+    let expires = adapter.login_cookie().and_then(|prefix| {
+        session
+            .cookies
+            .iter()
+            .filter(|c| c.name.starts_with(prefix))
+            .filter_map(|c| c.expires)
+            .max()
+    });
+    match expires {
+        Some(at) => println!(
+            "  Expires:     in {}",
+            cookies::describe_duration(at.saturating_sub(cookies::now_unix()))
+        ),
+        None => println!("  Expires:     unknown (session cookie)"),
+    }
+
+    Ok(status.logged_in)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::Verbosity;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    // Minimal stand-in for a WordPress login endpoint: GET sets a test cookie,
+    // POST with the right credentials redirects with a session cookie.
+    fn serve_login(requests: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                let mut has_test_cookie = false;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    let lower = line.to_ascii_lowercase();
+                    if let Some(v) = lower.strip_prefix("content-length:") {
+                        content_length = v.trim().parse().unwrap();
+                    }
+                    if lower.starts_with("cookie:") && lower.contains("wordpress_test_cookie") {
+                        has_test_cookie = true;
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                let body = String::from_utf8(body).unwrap();
+
+                let response = if request_line.starts_with("GET") {
+                    "HTTP/1.1 200 OK\r\nSet-Cookie: wordpress_test_cookie=WP%20Cookie%20check; Path=/\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+                } else if has_test_cookie
+                    && body.contains("log=reader")
+                    && body.contains("pwd=s3cret")
+                {
+                    "HTTP/1.1 302 Found\r\nLocation: /\r\nSet-Cookie: wordpress_logged_in_abc=reader%7C1; Max-Age=3600; Path=/; HttpOnly\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+                } else {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        format!("http://{}/wp-login.php", addr)
+    }
+
+    fn form(url: String, password: &str) -> LoginForm {
+        LoginForm {
+            url,
+            fields: vec![
+                ("log".into(), "reader".into()),
+                ("pwd".into(), password.into()),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_login_captures_session_cookie() {
+        let url = serve_login(2);
+        let progress = Progress::new(Verbosity::Quiet);
+        let cookies = login(
+            &form(url, "s3cret"),
+            "127.0.0.1",
+            Some("wordpress_logged_in_"),
+            &progress,
+        )
+        .unwrap();
+
+        let session = cookies
+            .iter()
+            .find(|c| c.name == "wordpress_logged_in_abc")
+            .expect("session cookie captured");
+        assert_eq!(session.value, "reader%7C1");
+        assert!(session.expires.is_some());
+    }
+
+    #[test]
+    fn test_login_with_wrong_password_fails() {
+        let url = serve_login(2);
+        let progress = Progress::new(Verbosity::Quiet);
+        let err = login(
+            &form(url, "wrong"),
+            "127.0.0.1",
+            Some("wordpress_logged_in_"),
+            &progress,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Login to 127.0.0.1 failed"));
+        assert!(!err.to_string().contains("wrong"));
+    }
+
+    #[test]
+    fn test_saved_session_round_trips() {
+        let path =
+            std::env::temp_dir().join(format!("magaziner-session-{}.txt", std::process::id()));
+        let cookie = cookies::parse_set_cookie(
+            "wordpress_logged_in_abc=reader; Max-Age=3600; Domain=harpers.org",
+            "harpers.org",
+        )
+        .unwrap();
+        fs::write(&path, "stale").unwrap();
+        save_session(&path, std::slice::from_ref(&cookie)).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let loaded = cookies::load_session_cookies(
+            &path,
+            "harpers.org",
+            Some("wordpress_logged_in_"),
+            CookieSource::SavedSession,
+        )
+        .unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(loaded, vec![cookie]);
+    }
+
+    #[test]
+    fn test_each_account_has_its_own_session_file() {
+        let first = session_path("harpers", "reader@example.com").unwrap();
+        let second = session_path("harpers", "other@example.com").unwrap();
+        assert_ne!(first, second);
+        assert_eq!(
+            first,
+            session_path("harpers", "reader@example.com").unwrap()
+        );
+        let name = first.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("harpers-") && name.ends_with(".txt"));
+        assert!(!name.contains("reader"));
+    }
+
+    #[test]
+    fn test_credentials_debug_hides_password() {
+        let credentials = Credentials {
+            username: "reader".into(),
+            password: "s3cret".into(),
+        };
+        assert!(!format!("{:?}", credentials).contains("s3cret"));
+    }
+}
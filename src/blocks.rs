@@ -0,0 +1,438 @@
+use ego_tree::NodeId;
+use scraper::{ElementRef, Html, Selector};
+use std::collections::{HashMap, HashSet};
+
+/// Font variant for a run of text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Style {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+}
+
+impl Style {
+    pub fn new(bold: bool, italic: bool) -> Style {
+        match (bold, italic) {
+            (false, false) => Style::Regular,
+            (true, false) => Style::Bold,
+            (false, true) => Style::Italic,
+            (true, true) => Style::BoldItalic,
+        }
+    }
+}
+
+/// A piece of inline text, or a `<br>`.
+#[derive(Debug, PartialEq)]
+pub enum Inline {
+    Text {
+        text: String,
+        style: Style,
+        /// Whether whitespace separates this piece from the one before.
+        space: bool,
+    },
+    Break,
+}
+
+/// How a paragraph is set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockKind {
+    Body,
+    Heading,
+    Quote,
+    Item,
+    Caption,
+}
+
+/// A paragraph of an article, or a horizontal rule.
+#[derive(Debug, PartialEq)]
+pub enum Block {
+    Text {
+        kind: BlockKind,
+        inlines: Vec<Inline>,
+    },
+    Rule,
+}
+
+/// Splits article HTML into paragraphs of styled text. Images, scripts and
+/// embeds are dropped.
+pub fn blocks(html: &str, notes: Notes) -> Vec<Block> {
+    let fragment = Html::parse_fragment(html);
+    let mut walker = Walker {
+        footnotes: (notes == Notes::Inline).then(|| footnotes(&fragment)),
+        ..Walker::default()
+    };
+    walker.children(fragment.root_element(), false, false);
+    walker.flush();
+    walker.blocks
+}
+
+#[derive(Default)]
+struct Walker {
+    blocks: Vec<Block>,
+    inlines: Vec<Inline>,
+    kinds: Vec<BlockKind>,
+    lists: Vec<Option<usize>>,
+    pending_space: bool,
+    footnotes: Option<Footnotes>,
+}
+
+impl Walker {
+    fn kind(&self) -> BlockKind {
+        self.kinds.last().copied().unwrap_or(BlockKind::Body)
+    }
+
+    fn flush(&mut self) {
+        while matches!(self.inlines.last(), Some(Inline::Break)) {
+            self.inlines.pop();
+        }
+        if !self.inlines.is_empty() {
+            self.blocks.push(Block::Text {
+                kind: self.kind(),
+                inlines: std::mem::take(&mut self.inlines),
+            });
+        }
+        self.pending_space = false;
+    }
+
+    fn text(&mut self, text: &str, style: Style) {
+        let mut space = self.pending_space || text.starts_with(char::is_whitespace);
+        for word in text.split_whitespace() {
+            let after_break = matches!(self.inlines.last(), None | Some(Inline::Break));
+            self.inlines.push(Inline::Text {
+                text: word.to_string(),
+                style,
+                space: space && !after_break,
+            });
+            space = true;
+        }
+        if text.ends_with(char::is_whitespace) {
+            self.pending_space = true;
+        } else if !text.is_empty() {
+            self.pending_space = false;
+        }
+    }
+
+    fn children(&mut self, element: ElementRef, bold: bool, italic: bool) {
+        for child in element.children() {
+            if let Some(text) = child.value().as_text() {
+                self.text(text, Style::new(bold, italic));
+            } else if let Some(child) = ElementRef::wrap(child) {
+                self.visit(child, bold, italic);
+            }
+        }
+    }
+
+    fn visit(&mut self, element: ElementRef, bold: bool, italic: bool) {
+        if let Some(footnotes) = &self.footnotes {
+            if footnotes.notes.contains(&element.id()) {
+                return;
+            }
+            if let Some(note) = footnotes.markers.get(&element.id()) {
+                let note = format!(" [Note: {}]", note);
+                self.text(&note, Style::Regular);
+                return;
+            }
+        }
+
+        let name = element.value().name();
+        let kind = match name {
+            "p" | "div" | "section" | "article" | "header" | "footer" | "figure" | "ul" | "ol" => {
+                None
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => Some(BlockKind::Heading),
+            "blockquote" => Some(BlockKind::Quote),
+            "li" => Some(BlockKind::Item),
+            "figcaption" => Some(BlockKind::Caption),
+            "br" => {
+                self.inlines.push(Inline::Break);
+                self.pending_space = false;
+                return;
+            }
+            "hr" => {
+                self.flush();
+                self.blocks.push(Block::Rule);
+                return;
+            }
+            "img" | "script" | "style" | "iframe" | "svg" | "noscript" | "picture" => return,
+            _ => {
+                let bold = bold || matches!(name, "b" | "strong");
+                let italic = italic || matches!(name, "i" | "em" | "cite");
+                self.children(element, bold, italic);
+                return;
+            }
+        };
+
+        self.flush();
+        match name {
+            "ul" => self.lists.push(None),
+            "ol" => self.lists.push(Some(0)),
+            _ => {}
+        }
+        if let Some(kind) = kind {
+            self.kinds.push(kind);
+        }
+        if name == "li" {
+            let marker = match self.lists.last_mut() {
+                Some(Some(n)) => {
+                    *n += 1;
+                    format!("{}.", n)
+                }
+                _ => "•".to_string(),
+            };
+            self.inlines.push(Inline::Text {
+                text: marker,
+                style: Style::Regular,
+                space: false,
+            });
+            self.pending_space = true;
+        }
+        let bold = bold || kind == Some(BlockKind::Heading);
+        self.children(element, bold, italic);
+        self.flush();
+        if kind.is_some() {
+            self.kinds.pop();
+        }
+        if matches!(name, "ul" | "ol") {
+            self.lists.pop();
+        }
+    }
+}
+
+/// What to do with footnotes: short in-page links like `<a href="#fn1">1</a>`
+/// pointing at a note further down the same article.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Notes {
+    /// Leave the markers and the notes where the article has them.
+    Keep,
+    /// Put each note's text in brackets at its marker and drop the note itself.
+    Inline,
+}
+
+struct Footnotes {
+    /// Note text for each marker link.
+    markers: HashMap<NodeId, String>,
+    /// The note paragraphs, skipped when walking the article.
+    notes: HashSet<NodeId>,
+}
+
+/// Elements that can hold a single note.
+const NOTE_BLOCKS: &[&str] = &["p", "li", "aside", "dd"];
+
+/// Larger blocks. A link target inside one of these before any note block,
+/// such as a section heading, is a cross-reference rather than a note.
+const CONTAINER_BLOCKS: &[&str] = &[
+    "div",
+    "section",
+    "article",
+    "blockquote",
+    "figure",
+    "table",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+];
+
+fn footnotes(fragment: &Html) -> Footnotes {
+    let mut footnotes = Footnotes {
+        markers: HashMap::new(),
+        notes: HashSet::new(),
+    };
+    let mut order = HashMap::new();
+    let mut ids = HashMap::new();
+    for (i, element) in fragment
+        .root_element()
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .enumerate()
+    {
+        order.insert(element.id(), i);
+        if let Some(id) = element.value().id() {
+            ids.insert(id, element);
+        }
+    }
+
+    let links = Selector::parse(r##"a[href^="#"]"##).unwrap();
+    for link in fragment.select(&links) {
+        let marker = link.text().collect::<String>().trim().to_string();
+        let href = link.value().attr("href").unwrap_or_default();
+        let Some(target) = ids.get(&href[1..]) else {
+            continue;
+        };
+        // Markers are a few characters long and come before their note;
+        // links from a note back to its marker come after it.
+        if marker.is_empty()
+            || marker.chars().count() > 4
+            || order[&target.id()] < order[&link.id()]
+        {
+            continue;
+        }
+        let Some(note) = std::iter::once(*target)
+            .chain(target.ancestors().filter_map(ElementRef::wrap))
+            .find(|e| {
+                NOTE_BLOCKS.contains(&e.value().name())
+                    || CONTAINER_BLOCKS.contains(&e.value().name())
+            })
+            .filter(|e| NOTE_BLOCKS.contains(&e.value().name()))
+        else {
+            continue;
+        };
+        if link.ancestors().any(|a| a.id() == note.id()) {
+            continue;
+        }
+
+        let text = note_text(note);
+        let text = text
+            .strip_prefix(marker.as_str())
+            .unwrap_or(&text)
+            .trim_start_matches(['.', ')', ' '])
+            .to_string();
+        if !text.is_empty() {
+            footnotes.markers.insert(link.id(), text);
+            footnotes.notes.insert(note.id());
+        }
+    }
+    footnotes
+}
+
+/// A note's text without its in-page links, which are the backlink and marker.
+fn note_text(note: ElementRef) -> String {
+    let mut words = Vec::new();
+    for node in note.descendants() {
+        let Some(text) = node.value().as_text() else {
+            continue;
+        };
+        let in_link = node
+            .ancestors()
+            .take_while(|a| a.id() != note.id())
+            .any(|a| {
+                a.value().as_element().is_some_and(|e| {
+                    e.name() == "a" && e.attr("href").is_some_and(|h| h.starts_with('#'))
+                })
+            });
+        if !in_link {
+            words.extend(text.split_whitespace());
+        }
+    }
+    words.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks_keep_inline_styles_and_spacing() {
+        let blocks = blocks(
+            "<h2>Reviewed:</h2><ul><li><em>Emma</em> by Jane Austen<br>Faber, 265 pp.</li></ul>\
+             <p>One <em>two</em>, three.</p><img src=\"x.jpg\"><hr>",
+            Notes::Keep,
+        );
+        assert_eq!(blocks.len(), 4);
+        assert_eq!(
+            blocks[0],
+            Block::Text {
+                kind: BlockKind::Heading,
+                inlines: vec![Inline::Text {
+                    text: "Reviewed:".into(),
+                    style: Style::Bold,
+                    space: false
+                }],
+            }
+        );
+        let Block::Text { kind, inlines } = &blocks[1] else {
+            panic!("expected a list item");
+        };
+        assert_eq!(*kind, BlockKind::Item);
+        assert_eq!(
+            inlines[..2],
+            [
+                Inline::Text {
+                    text: "•".into(),
+                    style: Style::Regular,
+                    space: false
+                },
+                Inline::Text {
+                    text: "Emma".into(),
+                    style: Style::Italic,
+                    space: true
+                },
+            ]
+        );
+        assert!(inlines.contains(&Inline::Break));
+        let Block::Text { inlines, .. } = &blocks[2] else {
+            panic!("expected a paragraph");
+        };
+        let spaces: Vec<bool> = inlines
+            .iter()
+            .map(|i| matches!(i, Inline::Text { space: true, .. }))
+            .collect();
+        // "One", "two", ",", "three."
+        assert_eq!(spaces, [false, true, false, true]);
+        assert_eq!(blocks[3], Block::Rule);
+    }
+
+    fn text(block: &Block) -> String {
+        let Block::Text { inlines, .. } = block else {
+            return String::new();
+        };
+        let mut out = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text { text, space, .. } => {
+                    if *space {
+                        out.push(' ');
+                    }
+                    out.push_str(text);
+                }
+                Inline::Break => out.push('\n'),
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_inline_notes_replace_markers_and_notes() {
+        let html = r##"<p>A claim.<sup><a href="#fn1" id="ref1">1</a></sup> More text.</p>
+            <p>See <a href="#later">the end</a>.</p>
+            <p id="later">The end.</p>
+            <div class="footnotes"><p id="fn1"><a href="#ref1">1</a>. Smith, <em>Claims</em> (1999).</p></div>"##;
+
+        let inline = blocks(html, Notes::Inline);
+        let texts: Vec<String> = inline.iter().map(text).collect();
+        assert_eq!(
+            texts,
+            [
+                "A claim. [Note: Smith, Claims (1999).] More text.",
+                "See the end.",
+                "The end.",
+            ]
+        );
+
+        let kept = blocks(html, Notes::Keep);
+        assert_eq!(kept.len(), 4);
+        assert_eq!(text(&kept[0]), "A claim.1 More text.");
+    }
+
+    #[test]
+    fn test_links_to_sections_are_not_notes() {
+        let html = r##"<p>Contents: <a href="#part2">II</a></p>
+            <section><h2 id="part2">II</h2><p>The second part.</p></section>
+            <div id="box">Sidebar</div>
+            <p>See <a href="#box">*</a>.</p>"##;
+        let texts: Vec<String> = blocks(html, Notes::Inline).iter().map(text).collect();
+        assert_eq!(
+            texts,
+            [
+                "Contents: II",
+                "II",
+                "The second part.",
+                "Sidebar",
+                "See *."
+            ]
+        );
+    }
+}
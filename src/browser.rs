@@ -0,0 +1,258 @@
+use crate::cookies::Cookie;
+use aes::cipher::{BlockDecryptMut, KeyIvInit, block_padding::Pkcs7};
+use anyhow::{Context, Result, anyhow, bail};
+use rusqlite::{Connection, OpenFlags};
+use std::path::{Path, PathBuf};
+use url::Url;
+
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BrowserKind {
+    Firefox,
+    Chromium,
+}
+
+/// Resolves a Firefox or Chromium profile directory (or a cookie database
+/// file directly) to the cookie store inside it.
+pub fn locate_cookie_store(profile: &Path) -> Result<(BrowserKind, PathBuf)> {
+    if profile.is_file() {
+        return match profile.file_name().and_then(|n| n.to_str()) {
+            Some("cookies.sqlite") => Ok((BrowserKind::Firefox, profile.to_path_buf())),
+            Some("Cookies") => Ok((BrowserKind::Chromium, profile.to_path_buf())),
+            _ => bail!(
+                "Unrecognised cookie store {}; expected Firefox's cookies.sqlite or Chromium's Cookies",
+                profile.display()
+            ),
+        };
+    }
+
+    let candidates = [
+        (BrowserKind::Firefox, profile.join("cookies.sqlite")),
+        (
+            BrowserKind::Chromium,
+            profile.join("Network").join("Cookies"),
+        ),
+        (BrowserKind::Chromium, profile.join("Cookies")),
+    ];
+    candidates
+        .into_iter()
+        .find(|(_, path)| path.is_file())
+        .ok_or_else(|| {
+            anyhow!(
+                "No cookie store found in browser profile {}",
+                profile.display()
+            )
+        })
+}
+
+/// Reads every cookie for `host` from the profile's cookie store, including
+/// expired ones so callers can report a stale login.
+pub fn load_browser_cookies(profile: &Path, host: &str) -> Result<Vec<Cookie>> {
+    let (kind, path) = locate_cookie_store(profile)?;
+    // immutable=1 lets us read the database while the browser holds its lock.
+    // The path is percent-encoded so `?`, `#` or `%` in it can't end it early.
+    let mut uri = std::path::absolute(&path)
+        .ok()
+        .and_then(|absolute| Url::from_file_path(absolute).ok())
+        .ok_or_else(|| anyhow!("Invalid cookie store path {}", path.display()))?;
+    uri.set_query(Some("immutable=1"));
+    let conn = Connection::open_with_flags(
+        uri.as_str(),
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI,
+    )
+    .with_context(|| format!("Failed to open cookie store {}", path.display()))?;
+
+    let cookies = match kind {
+        BrowserKind::Firefox => read_firefox(&conn, host),
+        BrowserKind::Chromium => read_chromium(&conn, host),
+    }
+    .with_context(|| format!("Failed to read cookies from {}", path.display()))?;
+
+    Ok(cookies
+        .into_iter()
+        .filter(|c| c.matches_host(host))
+        .collect())
+}
+
+fn read_firefox(conn: &Connection, host: &str) -> Result<Vec<Cookie>> {
+    let mut stmt = conn.prepare(
+        "SELECT host, path, isSecure, expiry, name, value FROM moz_cookies
+         WHERE host LIKE '%' || ?1",
+    )?;
+    let rows = stmt.query_map([registrable_suffix(host)], |row| {
+        let domain: String = row.get(0)?;
+        let expiry: i64 = row.get(3)?;
+        Ok(Cookie {
+            include_subdomains: domain.starts_with('.'),
+            domain,
+            path: row.get(1)?,
+            secure: row.get::<_, i64>(2)? != 0,
+            expires: firefox_expiry(expiry),
+            name: row.get(4)?,
+            value: row.get(5)?,
+        })
+    })?;
+    Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+}
+
+// Firefox stores expiry in seconds, but recent releases moved to milliseconds.
+fn firefox_expiry(expiry: i64) -> Option<u64> {
+    match expiry {
+        e if e <= 0 => None,
+        e if e > 100_000_000_000 => Some(e as u64 / 1000),
+        e => Some(e as u64),
+    }
+}
+
+fn read_chromium(conn: &Connection, host: &str) -> Result<Vec<Cookie>> {
+    let version: i64 = conn
+        .query_row("SELECT value FROM meta WHERE key = 'version'", [], |row| {
+            row.get::<_, String>(0)
+        })
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+
+    let mut stmt = conn.prepare(
+        "SELECT host_key, path, is_secure, expires_utc, name, value, encrypted_value
+         FROM cookies WHERE host_key LIKE '%' || ?1",
+    )?;
+    let rows = stmt.query_map([registrable_suffix(host)], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, i64>(2)?,
+            row.get::<_, i64>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, String>(5)?,
+            row.get::<_, Vec<u8>>(6)?,
+        ))
+    })?;
+
+    let mut cookies = Vec::new();
+    for row in rows {
+        let (domain, path, secure, expires_utc, name, value, encrypted) = row?;
+        let value = if !value.is_empty() || encrypted.is_empty() {
+            value
+        } else {
+            decrypt_chromium_value(&encrypted, version)
+                .with_context(|| format!("Failed to decrypt cookie '{}'", name))?
+        };
+        cookies.push(Cookie {
+            include_subdomains: domain.starts_with('.'),
+            domain,
+            path,
+            secure: secure != 0,
+            expires: chromium_expiry(expires_utc),
+            name,
+            value,
+        });
+    }
+    Ok(cookies)
+}
+
+// Chromium timestamps are microseconds since 1601-01-01.
+fn chromium_expiry(expires_utc: i64) -> Option<u64> {
+    const EPOCH_DELTA_SECS: i64 = 11_644_473_600;
+    if expires_utc <= 0 {
+        return None;
+    }
+    Some((expires_utc / 1_000_000 - EPOCH_DELTA_SECS).max(0) as u64)
+}
+
+/// Decrypts a Linux Chromium `v10` value, which uses a fixed built-in key when
+/// no system keyring is configured. Keyring-protected `v11` values are refused.
+fn decrypt_chromium_value(encrypted: &[u8], db_version: i64) -> Result<String> {
+    let ciphertext = match encrypted.get(..3) {
+        Some(b"v10") => &encrypted[3..],
+        Some(b"v11") => bail!(
+            "value is protected by the system keyring; export a cookies.txt with --cookies instead"
+        ),
+        _ => bail!("unsupported cookie encryption scheme"),
+    };
+
+    let mut key = [0u8; 16];
+    pbkdf2::pbkdf2_hmac::<sha1::Sha1>(b"peanuts", b"saltysalt", 1, &mut key);
+    let iv = [b' '; 16];
+    let mut plaintext = Aes128CbcDec::new(&key.into(), &iv.into())
+        .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+        .map_err(|_| anyhow!("bad padding"))?;
+
+    // Since schema version 24 the plaintext is prefixed with a SHA-256 of the host.
+    if db_version >= 24 && plaintext.len() >= 32 {
+        plaintext.drain(..32);
+    }
+    Ok(String::from_utf8(plaintext)?)
+}
+
+// Narrows the SQL query; exact domain matching happens in `Cookie::matches_host`.
+fn registrable_suffix(host: &str) -> String {
+    let parts: Vec<&str> = host.split('.').collect();
+    let keep = if parts.len() > 2 && parts[parts.len() - 2].len() <= 3 {
+        3
+    } else {
+        2
+    };
+    parts[parts.len().saturating_sub(keep)..].join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_firefox_profile_cookies() {
+        let cookies =
+            load_browser_cookies(Path::new("src/test/browser/firefox"), "harpers.org").unwrap();
+        let login = cookies
+            .iter()
+            .find(|c| c.name.starts_with("wordpress_logged_in_"))
+            .expect("login cookie present");
+        assert_eq!(login.value, "subscriber%7C1893456000%7Cabc");
+        assert_eq!(login.expires, Some(4_102_444_800));
+        assert!(cookies.iter().all(|c| c.matches_host("harpers.org")));
+    }
+
+    #[test]
+    fn test_load_chromium_profile_decrypts_v10_values() {
+        let cookies =
+            load_browser_cookies(Path::new("src/test/browser/chromium"), "harpers.org").unwrap();
+        let login = cookies
+            .iter()
+            .find(|c| c.name.starts_with("wordpress_logged_in_"))
+            .expect("login cookie present");
+        assert_eq!(login.value, "subscriber%7C1893456000%7Cabc");
+        assert_eq!(login.expires, Some(4_102_444_800));
+
+        let plain = cookies.iter().find(|c| c.name == "plain").unwrap();
+        assert_eq!(plain.value, "unencrypted");
+        assert_eq!(plain.expires, None);
+        assert!(!cookies.iter().any(|c| c.domain.contains("lrb")));
+    }
+
+    #[test]
+    fn test_profile_path_with_uri_characters() {
+        let profile =
+            std::env::temp_dir().join(format!("magaziner-profile #1?100%-{}", std::process::id()));
+        std::fs::create_dir_all(&profile).unwrap();
+        std::fs::copy(
+            "src/test/browser/firefox/cookies.sqlite",
+            profile.join("cookies.sqlite"),
+        )
+        .unwrap();
+        let cookies = load_browser_cookies(&profile, "harpers.org");
+        std::fs::remove_dir_all(&profile).unwrap();
+        assert!(
+            cookies
+                .unwrap()
+                .iter()
+                .any(|c| c.name.starts_with("wordpress_logged_in_"))
+        );
+    }
+
+    #[test]
+    fn test_unknown_profile_is_rejected() {
+        assert!(load_browser_cookies(Path::new("src/test/lrb"), "harpers.org").is_err());
+    }
+}
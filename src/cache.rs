@@ -0,0 +1,194 @@
+use anyhow::{Context, Result, anyhow, bail};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const ISSUE_URL_FILE: &str = "issue.url";
+
+/// Root of the page cache: `<cache dir>/magaziner/pages`.
+pub fn cache_root() -> Result<PathBuf> {
+    dirs::cache_dir()
+        .map(|dir| dir.join("magaziner").join("pages"))
+        .ok_or_else(|| anyhow!("Could not determine the user cache directory"))
+}
+
+/// Every page and image fetched for one issue, stored under
+/// `<root>/<publication key>/<issue>/` so the EPUB can be rebuilt offline.
+pub struct IssueCache {
+    dir: PathBuf,
+}
+
+impl IssueCache {
+    pub fn open(root: &Path, key: &str, issue_url: &str) -> Result<Self> {
+        let dir = issue_dir(root, key, issue_url);
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+        fs::write(dir.join(ISSUE_URL_FILE), issue_url)?;
+        Ok(Self { dir })
+    }
+
+    /// Opens the cache of an issue that has been fetched before.
+    pub fn existing(root: &Path, key: &str, issue_url: &str) -> Result<Self> {
+        let dir = issue_dir(root, key, issue_url);
+        if !dir.join(ISSUE_URL_FILE).exists() {
+            bail!(
+                "No cached pages for {}; run `magaziner fetch --url {}` first",
+                issue_url,
+                issue_url
+            );
+        }
+        Ok(Self { dir })
+    }
+
+    pub fn load(&self, url: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.dir.join(entry_name(url));
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = fs::read(&path)
+            .with_context(|| format!("Failed to read cached page {}", path.display()))?;
+        Ok(Some(bytes))
+    }
+
+    pub fn store(&self, url: &str, bytes: &[u8]) -> Result<()> {
+        let path = self.dir.join(entry_name(url));
+        fs::write(&path, bytes)
+            .with_context(|| format!("Failed to write cached page {}", path.display()))
+    }
+}
+
+pub struct CachedIssue {
+    pub key: String,
+    pub url: String,
+    pub pages: usize,
+    pub bytes: u64,
+}
+
+/// Lists the cached issues under `root`, sorted by publication and URL.
+pub fn list_issues(root: &Path) -> Result<Vec<CachedIssue>> {
+    let mut issues = Vec::new();
+    if !root.exists() {
+        return Ok(issues);
+    }
+    for publication in fs::read_dir(root)? {
+        let publication = publication?;
+        if !publication.file_type()?.is_dir() {
+            continue;
+        }
+        let key = publication.file_name().to_string_lossy().into_owned();
+        for issue in fs::read_dir(publication.path())? {
+            let dir = issue?.path();
+            let Ok(url) = fs::read_to_string(dir.join(ISSUE_URL_FILE)) else {
+                continue;
+            };
+            let mut pages = 0;
+            let mut bytes = 0;
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+                if entry.file_name() != ISSUE_URL_FILE {
+                    pages += 1;
+                    bytes += entry.metadata()?.len();
+                }
+            }
+            issues.push(CachedIssue {
+                key: key.clone(),
+                url: url.trim().to_string(),
+                pages,
+                bytes,
+            });
+        }
+    }
+    issues.sort_by(|a, b| (&a.key, &a.url).cmp(&(&b.key, &b.url)));
+    Ok(issues)
+}
+
+/// Deletes the cache for one publication, or all of it. Returns the number
+/// of issues removed.
+pub fn clear(root: &Path, key: Option<&str>) -> Result<usize> {
+    let removed = list_issues(root)?
+        .iter()
+        .filter(|issue| key.is_none_or(|k| issue.key == k))
+        .count();
+    let target = match key {
+        Some(key) => root.join(key),
+        None => root.to_path_buf(),
+    };
+    if target.exists() {
+        fs::remove_dir_all(&target)
+            .with_context(|| format!("Failed to remove {}", target.display()))?;
+    }
+    Ok(removed)
+}
+
+fn issue_dir(root: &Path, key: &str, issue_url: &str) -> PathBuf {
+    root.join(key).join(entry_name(issue_url))
+}
+
+// "https://www.lrb.co.uk/the-paper/v47/n06" -> "www.lrb.co.uk_the-paper_v47_n06"
+fn entry_name(url: &str) -> String {
+    let trimmed = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_end_matches('/');
+    trimmed
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-._".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("magaziner-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        root
+    }
+
+    #[test]
+    fn test_entry_name_is_flat_and_ignores_trailing_slash() {
+        assert_eq!(
+            entry_name("https://www.lrb.co.uk/the-paper/v47/n06/"),
+            "www.lrb.co.uk_the-paper_v47_n06"
+        );
+        assert_eq!(
+            entry_name("https://harpers.org/archive/2026/02/a?x=1"),
+            "harpers.org_archive_2026_02_a_x_1"
+        );
+    }
+
+    #[test]
+    fn test_store_load_list_and_clear() {
+        let root = temp_root("roundtrip");
+        let issue_url = "https://harpers.org/archive/2026/02";
+        assert!(IssueCache::existing(&root, "harpers", issue_url).is_err());
+        let cache = IssueCache::open(&root, "harpers", issue_url).unwrap();
+        assert!(IssueCache::existing(&root, "harpers", issue_url).is_ok());
+        assert!(cache.load(issue_url).unwrap().is_none());
+
+        cache.store(issue_url, b"<html></html>").unwrap();
+        cache
+            .store("https://harpers.org/archive/2026/02/essay/", b"essay")
+            .unwrap();
+        assert_eq!(cache.load(issue_url).unwrap().unwrap(), b"<html></html>");
+
+        let issues = list_issues(&root).unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].key, "harpers");
+        assert_eq!(issues[0].url, issue_url);
+        assert_eq!(issues[0].pages, 2);
+        assert_eq!(issues[0].bytes, 18);
+
+        assert_eq!(clear(&root, Some("lrb")).unwrap(), 0);
+        assert_eq!(clear(&root, Some("harpers")).unwrap(), 1);
+        assert!(list_issues(&root).unwrap().is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
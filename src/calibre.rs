@@ -0,0 +1,569 @@
+use crate::delivery::DeliveryError;
+use crate::identity::IssueIdentity;
+use crate::progress::Progress;
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::Utc;
+use deunicode::deunicode;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use uuid::Uuid;
+
+/// Calibre's limit on folder and file name components outside Windows.
+const PATH_LIMIT: usize = 100;
+
+/// Settings from the config file's `[calibre]` section.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CalibreConfig {
+    /// Library directory [default: ~/Calibre Library].
+    pub library: Option<PathBuf>,
+    /// Tags added to every issue alongside the publication name [default: Magazines].
+    pub tags: Option<Vec<String>>,
+    /// `calibredb` executable [default: calibredb on the PATH].
+    pub calibredb: Option<PathBuf>,
+    /// Add every built issue without needing `--calibre`.
+    pub add: Option<bool>,
+}
+
+/// What Calibre is told about an issue.
+pub struct BookMetadata<'a> {
+    pub title: &'a str,
+    pub publication: &'a str,
+    /// BCP 47 tag from the publication's adapter.
+    pub language: &'a str,
+    pub url: &'a str,
+    pub identity: &'a IssueIdentity,
+    pub articles: Vec<&'a str>,
+}
+
+impl BookMetadata<'_> {
+    fn tags(&self, extra: &[String]) -> Vec<String> {
+        let mut tags = extra.to_vec();
+        if !tags.iter().any(|t| t == self.publication) {
+            tags.push(self.publication.to_string());
+        }
+        tags
+    }
+
+    fn pubdate(&self) -> Option<String> {
+        self.identity
+            .date()
+            .map(|date| date.format("%Y-%m-%d").to_string())
+    }
+
+    fn comments(&self) -> String {
+        let items: String = self
+            .articles
+            .iter()
+            .map(|title| format!("<li>{}</li>", escape(title)))
+            .collect();
+        format!("<ul>{}</ul>", items)
+    }
+}
+
+/// Adding finished books to a Calibre library.
+#[derive(Debug, Clone)]
+pub struct CalibreExport {
+    pub library: PathBuf,
+    pub tags: Vec<String>,
+    pub calibredb: PathBuf,
+}
+
+impl CalibreExport {
+    /// Adds `path` with its metadata through `calibredb` when it can be run,
+    /// otherwise writes the book into the library's folder structure.
+    pub fn add(
+        &self,
+        path: &Path,
+        book: &BookMetadata,
+        cover: Option<&[u8]>,
+        progress: &Progress,
+    ) -> Result<(), DeliveryError> {
+        self.try_add(path, book, cover, progress)
+            .with_context(|| {
+                format!(
+                    "adding to the Calibre library at {}",
+                    self.library.display()
+                )
+            })
+            .map_err(|source| DeliveryError {
+                path: path.to_path_buf(),
+                source,
+            })
+    }
+
+    fn try_add(
+        &self,
+        path: &Path,
+        book: &BookMetadata,
+        cover: Option<&[u8]>,
+        progress: &Progress,
+    ) -> Result<()> {
+        if !self.library.is_dir() {
+            bail!("the library directory does not exist");
+        }
+        let calibredb_found = Command::new(&self.calibredb)
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| output.status.success());
+        if calibredb_found {
+            let id = self.add_with_calibredb(path, book, cover, progress)?;
+            progress.info(&format!("Added to Calibre as book {}", id));
+        } else {
+            progress.verbose(&format!(
+                "{} not found; writing the library folders directly",
+                self.calibredb.display()
+            ));
+            let dir = add_to_folder(&self.library, path, book, &self.tags, cover)?;
+            progress.info(&format!(
+                "Added to Calibre folder {}; run Library maintenance → Restore database if Calibre doesn't list it",
+                dir.display()
+            ));
+        }
+        Ok(())
+    }
+
+    fn add_with_calibredb(
+        &self,
+        path: &Path,
+        book: &BookMetadata,
+        cover: Option<&[u8]>,
+        progress: &Progress,
+    ) -> Result<u64> {
+        let cover_path = match cover {
+            Some(bytes) => {
+                let path = std::env::temp_dir().join(format!(
+                    "magaziner-calibre-cover-{}.jpg",
+                    std::process::id()
+                ));
+                fs::write(&path, bytes)?;
+                Some(path)
+            }
+            None => None,
+        };
+
+        let mut add = Command::new(&self.calibredb);
+        add.arg("add")
+            .arg("--with-library")
+            .arg(&self.library)
+            // Rebuilding an issue replaces the book's EPUB instead of adding a duplicate.
+            .args(["--automerge", "overwrite"])
+            .args(["--title", book.title])
+            .args(["--authors", book.publication])
+            .args(["--series", book.publication])
+            .args(["--tags", &book.tags(&self.tags).join(",")])
+            .args(["--identifier", &format!("uri:{}", book.url)])
+            .args(["--languages", book.language]);
+        if let Some(index) = book.identity.series_index() {
+            add.args(["--series-index", &index.to_string()]);
+        }
+        if let Some(cover_path) = &cover_path {
+            add.arg("--cover").arg(cover_path);
+        }
+        add.arg(path);
+        let output = run(add, progress);
+        if let Some(cover_path) = cover_path {
+            let _ = fs::remove_file(cover_path);
+        }
+        let output = output?;
+        let id = output
+            .lines()
+            .find_map(|line| line.split_once("ids:"))
+            .and_then(|(_, ids)| ids.split(',').next())
+            .and_then(|id| id.trim().parse::<u64>().ok())
+            .ok_or_else(|| anyhow!("calibredb did not report a book id: {}", output.trim()))?;
+
+        // `calibredb add` has no options for these.
+        let mut set = Command::new(&self.calibredb);
+        set.arg("set_metadata")
+            .arg("--with-library")
+            .arg(&self.library)
+            .arg(id.to_string())
+            .args(["--field", &format!("publisher:{}", book.publication)])
+            .args(["--field", &format!("comments:{}", book.comments())]);
+        if let Some(pubdate) = book.pubdate() {
+            set.args(["--field", &format!("pubdate:{}", pubdate)]);
+        }
+        run(set, progress)?;
+        Ok(id)
+    }
+}
+
+fn run(mut command: Command, progress: &Progress) -> Result<String> {
+    progress.verbose(&format!("Running {:?}", command));
+    let output = command
+        .output()
+        .with_context(|| format!("could not run {}", command.get_program().to_string_lossy()))?;
+    if !output.status.success() {
+        bail!(
+            "calibredb failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Writes `Author/Title (id)/Title - Author.epub`, `metadata.opf` and
+/// `cover.jpg` the way Calibre lays out a library. A rebuilt issue goes back
+/// into its existing folder.
+fn add_to_folder(
+    library: &Path,
+    path: &Path,
+    book: &BookMetadata,
+    tags: &[String],
+    cover: Option<&[u8]>,
+) -> Result<PathBuf> {
+    let author = component(book.publication, PATH_LIMIT);
+    let author_dir = library.join(&author);
+    let existing = fs::read_dir(&author_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|dir| {
+            fs::read_to_string(dir.join("metadata.opf"))
+                .is_ok_and(|opf| opf.contains(&format!(">{}</dc:identifier>", escape(book.url))))
+        });
+    let dir = match existing {
+        Some(dir) => dir,
+        None => {
+            let id = next_book_id(library)?;
+            let suffix = format!(" ({})", id);
+            let title = component(book.title, PATH_LIMIT - suffix.len() / 2 - 2);
+            author_dir.join(format!("{}{}", title, suffix))
+        }
+    };
+    let id: u64 = dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.rsplit_once(" ("))
+        .and_then(|(_, id)| id.trim_end_matches(')').parse().ok())
+        .unwrap_or(0);
+    fs::create_dir_all(&dir)?;
+
+    let file_stem = component(
+        &format!("{} - {}", book.title, book.publication),
+        PATH_LIMIT - 4,
+    );
+    for entry in fs::read_dir(&dir)? {
+        let old = entry?.path();
+        if old.extension().is_some_and(|ext| ext == "epub") {
+            fs::remove_file(old)?;
+        }
+    }
+    fs::copy(path, dir.join(format!("{}.epub", file_stem)))?;
+    if let Some(cover) = cover {
+        fs::write(dir.join("cover.jpg"), cover)?;
+    }
+    fs::write(
+        dir.join("metadata.opf"),
+        metadata_opf(id, book, tags, cover.is_some()),
+    )?;
+    Ok(dir)
+}
+
+/// One more than the highest `(id)` folder suffix anywhere in the library.
+fn next_book_id(library: &Path) -> Result<u64> {
+    let mut highest = 0;
+    for author in fs::read_dir(library)? {
+        let author = author?.path();
+        if !author.is_dir() {
+            continue;
+        }
+        for book in fs::read_dir(&author)?.flatten() {
+            let name = book.file_name().to_string_lossy().into_owned();
+            if let Some(id) = name
+                .rsplit_once(" (")
+                .and_then(|(_, id)| id.strip_suffix(')'))
+                .and_then(|id| id.parse::<u64>().ok())
+            {
+                highest = highest.max(id);
+            }
+        }
+    }
+    Ok(highest + 1)
+}
+
+fn metadata_opf(id: u64, book: &BookMetadata, tags: &[String], has_cover: bool) -> String {
+    let uuid = Uuid::new_v5(&Uuid::NAMESPACE_URL, book.url.as_bytes());
+    let mut opf = String::from(
+        "<?xml version='1.0' encoding='utf-8'?>\n\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" unique-identifier=\"uuid_id\" version=\"2.0\">\n\
+         \x20 <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:opf=\"http://www.idpf.org/2007/opf\">\n",
+    );
+    opf.push_str(&format!(
+        "    <dc:identifier opf:scheme=\"calibre\" id=\"calibre_id\">{}</dc:identifier>\n",
+        id
+    ));
+    opf.push_str(&format!(
+        "    <dc:identifier opf:scheme=\"uuid\" id=\"uuid_id\">{}</dc:identifier>\n",
+        uuid
+    ));
+    opf.push_str(&format!(
+        "    <dc:identifier opf:scheme=\"URI\">{}</dc:identifier>\n",
+        escape(book.url)
+    ));
+    opf.push_str(&format!(
+        "    <dc:title>{}</dc:title>\n",
+        escape(book.title)
+    ));
+    opf.push_str(&format!(
+        "    <dc:creator opf:file-as=\"{0}\" opf:role=\"aut\">{0}</dc:creator>\n",
+        escape(book.publication)
+    ));
+    opf.push_str(&format!(
+        "    <dc:publisher>{}</dc:publisher>\n",
+        escape(book.publication)
+    ));
+    if let Some(pubdate) = book.pubdate() {
+        opf.push_str(&format!(
+            "    <dc:date>{}T12:00:00+00:00</dc:date>\n",
+            pubdate
+        ));
+    }
+    opf.push_str(&format!(
+        "    <dc:description>{}</dc:description>\n",
+        escape(&book.comments())
+    ));
+    opf.push_str(&format!(
+        "    <dc:language>{}</dc:language>\n",
+        escape(book.language)
+    ));
+    for tag in book.tags(tags) {
+        opf.push_str(&format!("    <dc:subject>{}</dc:subject>\n", escape(&tag)));
+    }
+    opf.push_str(&format!(
+        "    <meta name=\"calibre:series\" content=\"{}\"/>\n",
+        escape(book.publication)
+    ));
+    if let Some(index) = book.identity.series_index() {
+        opf.push_str(&format!(
+            "    <meta name=\"calibre:series_index\" content=\"{}\"/>\n",
+            index
+        ));
+    }
+    opf.push_str(&format!(
+        "    <meta name=\"calibre:timestamp\" content=\"{}\"/>\n",
+        Utc::now().format("%Y-%m-%dT%H:%M:%S+00:00")
+    ));
+    opf.push_str("  </metadata>\n");
+    if has_cover {
+        opf.push_str(
+            "  <guide>\n    <reference type=\"cover\" title=\"Cover\" href=\"cover.jpg\"/>\n  </guide>\n",
+        );
+    }
+    opf.push_str("</package>\n");
+    opf
+}
+
+/// An ASCII folder or file name component, as Calibre writes them.
+fn component(value: &str, limit: usize) -> String {
+    let cleaned: String = deunicode(value)
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .take(limit)
+        .collect();
+    cleaned.trim().trim_end_matches('.').to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Decides whether built books are added to Calibre: `--calibre`, or
+/// `add = true` in the config, with the CLI's library path winning.
+pub fn resolve_calibre(
+    calibre: Option<&Option<PathBuf>>,
+    config: Option<&CalibreConfig>,
+) -> Option<CalibreExport> {
+    let add = config.and_then(|c| c.add).unwrap_or(false);
+    let configured = config.and_then(|c| c.library.clone());
+    let library = match calibre {
+        Some(Some(path)) => Some(path.clone()),
+        Some(None) => configured,
+        None if add => configured,
+        None => return None,
+    };
+    Some(CalibreExport {
+        library: library
+            .unwrap_or_else(|| dirs::home_dir().unwrap_or_default().join("Calibre Library")),
+        tags: config
+            .and_then(|c| c.tags.clone())
+            .unwrap_or_else(|| vec!["Magazines".to_string()]),
+        calibredb: config
+            .and_then(|c| c.calibredb.clone())
+            .unwrap_or_else(|| PathBuf::from("calibredb")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::Verbosity;
+
+    const URL: &str = "https://www.lrb.co.uk/the-paper/v47/n06";
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("magaziner-calibre-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("library")).unwrap();
+        root
+    }
+
+    fn identity() -> IssueIdentity {
+        IssueIdentity {
+            volume: Some(47),
+            number: Some(6),
+            year: Some(2025),
+            month: Some(3),
+            day: Some(20),
+            season: None,
+        }
+    }
+
+    fn book(identity: &IssueIdentity) -> BookMetadata<'_> {
+        BookMetadata {
+            title: "LRB Vol. 47 No. 6",
+            publication: "London Review of Books",
+            language: "en-GB",
+            url: URL,
+            identity,
+            articles: vec!["Diary", "Short Cuts & Letters"],
+        }
+    }
+
+    #[test]
+    fn test_writes_library_folder_without_calibredb() {
+        let root = temp_root("folder");
+        let library = root.join("library");
+        let epub = root.join("LRB-v47-n06.epub");
+        fs::write(&epub, "epub").unwrap();
+        fs::create_dir_all(library.join("Someone/Older Book (4)")).unwrap();
+        let export = CalibreExport {
+            library: library.clone(),
+            tags: vec!["Magazines".into()],
+            calibredb: root.join("no-calibredb"),
+        };
+        let identity = identity();
+        let progress = Progress::new(Verbosity::Quiet);
+
+        export
+            .add(&epub, &book(&identity), Some(b"jpeg"), &progress)
+            .unwrap();
+        let dir = library.join("London Review of Books/LRB Vol. 47 No. 6 (5)");
+        assert_eq!(
+            fs::read_to_string(dir.join("LRB Vol. 47 No. 6 - London Review of Books.epub"))
+                .unwrap(),
+            "epub"
+        );
+        assert_eq!(fs::read(dir.join("cover.jpg")).unwrap(), b"jpeg");
+        let opf = fs::read_to_string(dir.join("metadata.opf")).unwrap();
+        assert!(
+            opf.contains(
+                r#"<dc:identifier opf:scheme="calibre" id="calibre_id">5</dc:identifier>"#
+            )
+        );
+        assert!(opf.contains(r#"<meta name="calibre:series" content="London Review of Books"/>"#));
+        assert!(opf.contains(r#"<meta name="calibre:series_index" content="47.06"/>"#));
+        assert!(opf.contains("<dc:date>2025-03-20T12:00:00+00:00</dc:date>"));
+        assert!(opf.contains("<dc:language>en-GB</dc:language>"));
+        assert!(opf.contains("<dc:subject>Magazines</dc:subject>"));
+        assert!(opf.contains("<dc:subject>London Review of Books</dc:subject>"));
+        assert!(opf.contains("Short Cuts &amp;amp; Letters"));
+
+        // A rebuild replaces the book in the same folder.
+        fs::write(&epub, "rebuilt").unwrap();
+        export
+            .add(&epub, &book(&identity), None, &progress)
+            .unwrap();
+        assert_eq!(
+            fs::read_dir(library.join("London Review of Books"))
+                .unwrap()
+                .count(),
+            1
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("LRB Vol. 47 No. 6 - London Review of Books.epub"))
+                .unwrap(),
+            "rebuilt"
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_adds_with_calibredb() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = temp_root("calibredb");
+        let log = root.join("calls.log");
+        let script = root.join("calibredb");
+        fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\necho \"$@\" >> '{}'\n[ \"$1\" = add ] && echo 'Added book ids: 12'\nexit 0\n",
+                log.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let epub = root.join("LRB-v47-n06.epub");
+        fs::write(&epub, "epub").unwrap();
+        let export = CalibreExport {
+            library: root.join("library"),
+            tags: vec!["Magazines".into()],
+            calibredb: script,
+        };
+        let identity = identity();
+
+        export
+            .add(
+                &epub,
+                &book(&identity),
+                None,
+                &Progress::new(Verbosity::Quiet),
+            )
+            .unwrap();
+        let calls = fs::read_to_string(&log).unwrap();
+        let calls: Vec<&str> = calls.lines().collect();
+        assert_eq!(calls[0], "--version");
+        assert!(calls[1].starts_with("add --with-library "));
+        assert!(calls[1].contains("--series London Review of Books"));
+        assert!(calls[1].contains("--series-index 47.06"));
+        assert!(calls[1].contains("--tags Magazines,London Review of Books"));
+        assert!(calls[1].contains(&format!("--identifier uri:{}", URL)));
+        assert!(calls[2].contains("set_metadata --with-library "));
+        assert!(calls[2].contains(" 12 "));
+        assert!(calls[2].contains("--field pubdate:2025-03-20"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_missing_library_is_a_delivery_error() {
+        let export = CalibreExport {
+            library: PathBuf::from("/nonexistent/Calibre Library"),
+            tags: Vec::new(),
+            calibredb: PathBuf::from("calibredb"),
+        };
+        let identity = identity();
+        let err = export
+            .add(
+                Path::new("LRB.epub"),
+                &book(&identity),
+                None,
+                &Progress::new(Verbosity::Quiet),
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("library directory does not exist"));
+    }
+}
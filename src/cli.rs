@@ -0,0 +1,526 @@
+use crate::output::OutputFormat;
+use crate::pdf::PageSize;
+use crate::style::{CssMode, Theme};
+use crate::template::Charset;
+use crate::validation::{MagazineSource, validate_magazine_url};
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "magaziner",
+    version,
+    about = "Generate epub files from Magazine archives",
+    long_about = None,
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true
+)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub global: GlobalArgs,
+
+    // `magaziner --url ...` without a subcommand is shorthand for `magaziner fetch --url ...`.
+    #[command(flatten)]
+    pub fetch: FetchArgs,
+}
+
+/// Options shared by every subcommand.
+#[derive(ClapArgs, Debug)]
+pub struct GlobalArgs {
+    #[arg(
+        long,
+        short,
+        help = "Output directory for generated EPUBs (Ex: ./downloads) [default: .]",
+        global = true
+    )]
+    pub output: Option<PathBuf>,
+
+    #[arg(
+        short,
+        long,
+        help = "Print detailed network and parsing logs",
+        conflicts_with = "quiet",
+        global = true
+    )]
+    pub verbose: bool,
+
+    #[arg(
+        short,
+        long,
+        help = "Suppress all output for script automation",
+        conflicts_with = "verbose",
+        global = true
+    )]
+    pub quiet: bool,
+
+    #[arg(
+        long,
+        help = "Netscape-format cookies.txt exported from a logged-in browser (ex: ./cookies.txt)",
+        global = true
+    )]
+    pub cookies: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Firefox or Chromium profile directory to read session cookies from",
+        conflicts_with = "cookies",
+        global = true
+    )]
+    pub browser_profile: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Config file to read defaults from [default: <config dir>/magaziner/config.toml]",
+        global = true
+    )]
+    pub config: Option<PathBuf>,
+
+    #[arg(
+        short,
+        long,
+        help = "Named profile from the config file to apply (ex: --profile fast)",
+        global = true
+    )]
+    pub profile: Option<String>,
+}
+
+/// Build options for commands that download pages: the request delay plus
+/// everything `rebuild` also takes.
+#[derive(ClapArgs, Debug)]
+pub struct DownloadArgs {
+    #[arg(
+        short,
+        long,
+        help = "Delay between calls to magazine source in milliseconds (ex: 1000 = 1 second) [default: 3000]"
+    )]
+    pub delay: Option<u64>,
+
+    #[command(flatten)]
+    pub build: BuildArgs,
+}
+
+/// Options that control how an issue is turned into an EPUB.
+#[derive(ClapArgs, Debug)]
+pub struct BuildArgs {
+    #[arg(
+        short,
+        long,
+        help = "Overwrite the output file if it already exists",
+        default_value_t = false
+    )]
+    pub force: bool,
+
+    #[arg(
+        long,
+        help = "Exit successfully without downloading if the issue's output file already exists",
+        conflicts_with = "force"
+    )]
+    pub skip_existing: bool,
+
+    #[arg(
+        long,
+        visible_alias = "template",
+        help = "Output path template relative to --output, without extension (ex: \"{prefix}/{year}/{prefix|slug}-v{volume}-n{number}\")"
+    )]
+    pub filename_template: Option<String>,
+
+    #[arg(
+        long,
+        help = "Slugify every template field (lowercase ASCII words joined by dashes)"
+    )]
+    pub slugify: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Characters allowed in output file and directory names [default: unicode]"
+    )]
+    pub charset: Option<Charset>,
+
+    #[arg(long, value_enum, help = "Output format [default: epub]")]
+    pub format: Option<OutputFormat>,
+
+    #[arg(long, value_enum, help = "Paper size for --format pdf [default: a4]")]
+    pub page_size: Option<PageSize>,
+
+    #[arg(
+        long,
+        value_name = "MM",
+        help = "Page margin for --format pdf, in millimetres [default: 20]"
+    )]
+    pub margin: Option<f32>,
+
+    #[arg(
+        long,
+        value_name = "COLUMNS",
+        help = "Line width for --format txt; 0 keeps each paragraph on one line [default: 72]"
+    )]
+    pub wrap: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Write a folder with one .txt file per article for --format txt"
+    )]
+    pub per_article: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Stylesheet to add to the book (ex: ./reader.css)"
+    )]
+    pub css: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Whether --css is added after the book stylesheet or replaces it [default: append]"
+    )]
+    pub css_mode: Option<CssMode>,
+
+    #[arg(long, value_enum, help = "Built-in reading theme")]
+    pub theme: Option<Theme>,
+
+    #[arg(
+        long,
+        help = "Leave out the stylesheet scraped from the publisher's site"
+    )]
+    pub no_publisher_css: bool,
+
+    #[arg(
+        long,
+        help = "Draw a cover with the publication, title and date even when the issue has one"
+    )]
+    pub generated_cover: bool,
+
+    #[arg(
+        long,
+        value_name = "SIZE",
+        help = "Scale the cover down to fit WIDTHxHEIGHT, or a reader: kindle, kobo, kobo-clara (ex: 1264x1680)"
+    )]
+    pub cover_size: Option<String>,
+
+    #[arg(long, help = "Convert the cover to greyscale for e-ink screens")]
+    pub greyscale_cover: bool,
+
+    #[arg(
+        long = "font",
+        value_name = "PATH",
+        help = "Font file to embed in the EPUB: TTF, OTF or WOFF (repeatable)"
+    )]
+    pub fonts: Vec<PathBuf>,
+
+    #[arg(long, help = "Keep only the glyphs the issue uses in embedded fonts")]
+    pub subset_fonts: bool,
+
+    #[arg(
+        long,
+        help = "Email the finished EPUB to the recipients in the config file's [email] section"
+    )]
+    pub email: bool,
+
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        help = "Email the finished EPUB to this address instead (repeatable; ex: me@kindle.com)"
+    )]
+    pub email_to: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        num_args = 0..=1,
+        require_equals = true,
+        help = "Copy the finished EPUB to a mounted Kobo or Kindle, detected unless a mount path is given (ex: --device=/media/KOBOeReader)"
+    )]
+    pub device: Option<Option<PathBuf>>,
+
+    #[arg(
+        long,
+        value_name = "COUNT",
+        help = "Remove the publication's older issues from the device, keeping this many"
+    )]
+    pub keep_on_device: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "LIBRARY",
+        num_args = 0..=1,
+        require_equals = true,
+        help = "Add the finished EPUB to a Calibre library [default: ~/Calibre Library] (ex: --calibre=~/Books/Calibre)"
+    )]
+    pub calibre: Option<Option<PathBuf>>,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct FetchArgs {
+    #[arg(
+        short,
+        long,
+        value_parser = validate_magazine_url,
+        help = "Magazine archive URL (LRB or Harper's)",
+        required = true
+    )]
+    pub url: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        help = "Custom output filename without extension (ex: --name \"My Issue\")"
+    )]
+    pub name: Option<String>,
+
+    #[command(flatten)]
+    pub download: DownloadArgs,
+}
+
+/// `rebuild` works from the page cache, so it takes no request delay.
+#[derive(ClapArgs, Debug)]
+pub struct RebuildArgs {
+    #[arg(
+        short,
+        long,
+        value_parser = validate_magazine_url,
+        help = "Magazine archive URL (LRB or Harper's)"
+    )]
+    pub url: String,
+
+    #[arg(
+        short,
+        long,
+        help = "Custom output filename without extension (ex: --name \"My Issue\")"
+    )]
+    pub name: Option<String>,
+
+    #[command(flatten)]
+    pub build: BuildArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    #[command(about = "Download an issue and build its EPUB")]
+    Fetch(FetchArgs),
+
+    #[command(about = "Show an issue's title, date and article links without downloading it")]
+    List {
+        #[arg(
+            short,
+            long,
+            value_parser = validate_magazine_url,
+            help = "Magazine archive URL (LRB or Harper's)"
+        )]
+        url: String,
+    },
+
+    #[command(about = "Rebuild an issue's EPUB from the page cache without touching the network")]
+    Rebuild(RebuildArgs),
+
+    #[command(about = "Download the newest issue of a publication")]
+    Latest {
+        #[arg(value_enum, help = "Publication to fetch")]
+        publication: MagazineSource,
+
+        #[arg(
+            short,
+            long,
+            help = "Custom output filename without extension (ex: --name \"My Issue\")"
+        )]
+        name: Option<String>,
+
+        #[command(flatten)]
+        download: DownloadArgs,
+    },
+
+    #[command(about = "Download several issues in one run")]
+    Batch {
+        #[arg(
+            value_parser = validate_magazine_url,
+            help = "Magazine archive URLs (LRB or Harper's)"
+        )]
+        urls: Vec<String>,
+
+        #[arg(
+            long,
+            help = "Read issue URLs from a file, one per line; '#' starts a comment and '-' reads stdin"
+        )]
+        from_file: Option<PathBuf>,
+
+        #[arg(long, help = "Carry on with the remaining issues when one fails")]
+        keep_going: bool,
+
+        #[command(flatten)]
+        download: DownloadArgs,
+    },
+
+    #[command(about = "Inspect or clear the page cache used by rebuild")]
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+
+    #[command(about = "Browse the index of issues magaziner has built")]
+    Library {
+        #[command(subcommand)]
+        command: LibraryCommand,
+    },
+
+    #[command(about = "Serve the library as an OPDS catalog for e-readers on the local network")]
+    Serve {
+        #[arg(
+            long,
+            default_value = "0.0.0.0",
+            help = "Address to listen on; use 127.0.0.1 to allow only this machine"
+        )]
+        bind: String,
+
+        #[arg(long, default_value_t = 8080, help = "Port to listen on")]
+        port: u16,
+    },
+
+    #[command(about = "Manage subscriber credentials")]
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+    #[command(about = "List cached issues and their size on disk")]
+    List,
+
+    #[command(about = "Print the cache directory")]
+    Path,
+
+    #[command(about = "Delete cached pages")]
+    Clear {
+        #[arg(
+            long,
+            value_enum,
+            help = "Only clear this publication's pages (default: all)"
+        )]
+        publication: Option<MagazineSource>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum LibraryCommand {
+    #[command(about = "List built issues, oldest first")]
+    List {
+        #[arg(long, value_enum, help = "Only list this publication (default: all)")]
+        publication: Option<MagazineSource>,
+    },
+
+    #[command(about = "Show an issue's source, file, checksum and articles")]
+    Show {
+        #[arg(help = "Library id (from `library list`) or issue URL")]
+        issue: String,
+    },
+
+    #[command(about = "Search article titles, authors and text across the library")]
+    Search {
+        #[arg(help = "Words to find, matched as a phrase (ex: \"Jane Austen\")")]
+        phrase: String,
+
+        #[arg(long, value_enum, help = "Only search this publication (default: all)")]
+        publication: Option<MagazineSource>,
+
+        #[arg(long, default_value_t = 20, help = "Maximum number of results")]
+        limit: usize,
+    },
+
+    #[command(
+        about = "List issues missing between the earliest and latest built, as URLs for `batch --from-file -`"
+    )]
+    Missing {
+        #[arg(long, value_enum, help = "Only check this publication (default: all)")]
+        publication: Option<MagazineSource>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AuthCommand {
+    #[command(about = "Check that the configured session for each publication is still valid")]
+    Check {
+        #[arg(long, value_enum, help = "Only check this publication (default: all)")]
+        publication: Option<MagazineSource>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    const URL: &str = "https://www.lrb.co.uk/the-paper/v47/n06";
+
+    #[test]
+    fn test_cli_definition_is_valid() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_bare_url_is_an_alias_for_fetch() {
+        let args = Args::try_parse_from(["magaziner", "--url", URL, "-f", "-o", "out"]).unwrap();
+        assert!(args.command.is_none());
+        assert_eq!(args.fetch.url.as_deref(), Some(URL));
+        assert!(args.fetch.download.build.force);
+        assert_eq!(args.global.output, Some(PathBuf::from("out")));
+
+        let args = Args::try_parse_from(["magaziner", "fetch", "--url", URL, "-f"]).unwrap();
+        match args.command {
+            Some(Command::Fetch(fetch)) => {
+                assert_eq!(fetch.url.as_deref(), Some(URL));
+                assert!(fetch.download.build.force);
+            }
+            other => panic!("expected fetch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_global_options_follow_subcommands() {
+        let args = Args::try_parse_from(["magaziner", "latest", "lrb", "-q", "-o", "out"]).unwrap();
+        assert!(args.global.quiet);
+        assert_eq!(args.global.output, Some(PathBuf::from("out")));
+        assert!(matches!(
+            args.command,
+            Some(Command::Latest {
+                publication: MagazineSource::LondonReview,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_device_path_is_optional() {
+        let args = Args::try_parse_from(["magaziner", "--url", URL, "--device", "-f"]).unwrap();
+        assert_eq!(args.fetch.download.build.device, Some(None));
+        assert!(args.fetch.download.build.force);
+
+        let args = Args::try_parse_from(["magaziner", "--url", URL, "--device=/media/KOBOeReader"])
+            .unwrap();
+        assert_eq!(
+            args.fetch.download.build.device,
+            Some(Some(PathBuf::from("/media/KOBOeReader")))
+        );
+
+        // A bare --device never swallows the positional that follows it.
+        let args = Args::try_parse_from(["magaziner", "latest", "--device", "lrb"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Latest {
+                publication: MagazineSource::LondonReview,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_url_is_required_without_a_subcommand() {
+        assert!(Args::try_parse_from(["magaziner"]).is_err());
+        assert!(Args::try_parse_from(["magaziner", "cache", "path"]).is_ok());
+        assert!(Args::try_parse_from(["magaziner", "--url", URL, "cache", "path"]).is_err());
+    }
+}
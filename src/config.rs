@@ -0,0 +1,299 @@
+use crate::calibre::CalibreConfig;
+use crate::cover::CoverConfig;
+use crate::delivery::EmailConfig;
+use crate::device::DeviceConfig;
+use crate::output::OutputFormat;
+use crate::pdf::PdfConfig;
+use crate::style::StyleConfig;
+use crate::template::Charset;
+use crate::txt::TextConfig;
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Defaults for the CLI flags, per-publication settings and named profiles.
+/// Command-line flags always win over anything set here.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub output: Option<PathBuf>,
+    pub delay: Option<u64>,
+    pub force: Option<bool>,
+    pub verbose: Option<bool>,
+    pub quiet: Option<bool>,
+    pub name: Option<String>,
+    pub filename_template: Option<String>,
+    pub slugify: Option<bool>,
+    pub charset: Option<Charset>,
+    pub format: Option<OutputFormat>,
+    pub generated_cover: Option<bool>,
+    pub cover: Option<CoverConfig>,
+    pub cookies: Option<PathBuf>,
+    pub browser_profile: Option<PathBuf>,
+    pub library: Option<PathBuf>,
+    pub email: Option<EmailConfig>,
+    pub device: Option<DeviceConfig>,
+    pub calibre: Option<CalibreConfig>,
+    pub pdf: Option<PdfConfig>,
+    pub text: Option<TextConfig>,
+    pub style: Option<StyleConfig>,
+    #[serde(default)]
+    pub publications: HashMap<String, PublicationConfig>,
+    #[serde(default)]
+    pub profiles: HashMap<String, Config>,
+}
+
+/// Settings for one publication, keyed by `MagazineAdapter::key` (e.g. `[publications.harpers]`).
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PublicationConfig {
+    pub delay: Option<u64>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub cookies: Option<PathBuf>,
+    pub browser_profile: Option<PathBuf>,
+    pub output_subdir: Option<PathBuf>,
+    pub filename_template: Option<String>,
+    pub stylesheet: Option<PathBuf>,
+}
+
+impl PublicationConfig {
+    fn merge(&mut self, over: PublicationConfig) {
+        self.delay = over.delay.or(self.delay);
+        self.username = over.username.or(self.username.take());
+        self.password = over.password.or(self.password.take());
+        self.cookies = over.cookies.or(self.cookies.take());
+        self.browser_profile = over.browser_profile.or(self.browser_profile.take());
+        self.output_subdir = over.output_subdir.or(self.output_subdir.take());
+        self.filename_template = over.filename_template.or(self.filename_template.take());
+        self.stylesheet = over.stylesheet.or(self.stylesheet.take());
+    }
+}
+
+impl Config {
+    pub fn publication(&self, key: &str) -> Option<&PublicationConfig> {
+        self.publications.get(key)
+    }
+
+    /// Layers the named profile over the top-level settings. A profile's
+    /// global settings also override the same settings in the base file's
+    /// publication sections, so `--profile fast` means fast everywhere.
+    pub fn with_profile(mut self, name: &str) -> Result<Config> {
+        let mut profile = self.profiles.remove(name).ok_or_else(|| {
+            let mut known: Vec<&String> = self.profiles.keys().collect();
+            known.sort();
+            anyhow!(
+                "Unknown profile '{}'. Profiles in config: {}",
+                name,
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known
+                        .iter()
+                        .map(|k| k.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                }
+            )
+        })?;
+        if !profile.profiles.is_empty() {
+            return Err(anyhow!("Profile '{}' cannot define nested profiles", name));
+        }
+
+        for publication in self.publications.values_mut() {
+            if profile.delay.is_some() {
+                publication.delay = None;
+            }
+            if profile.cookies.is_some() || profile.browser_profile.is_some() {
+                publication.cookies = None;
+                publication.browser_profile = None;
+            }
+        }
+        for (key, over) in profile.publications.drain() {
+            self.publications.entry(key).or_default().merge(over);
+        }
+
+        self.output = profile.output.or(self.output);
+        self.delay = profile.delay.or(self.delay);
+        self.force = profile.force.or(self.force);
+        self.verbose = profile.verbose.or(self.verbose);
+        self.quiet = profile.quiet.or(self.quiet);
+        self.name = profile.name.or(self.name);
+        self.filename_template = profile.filename_template.or(self.filename_template);
+        self.slugify = profile.slugify.or(self.slugify);
+        self.charset = profile.charset.or(self.charset);
+        self.format = profile.format.or(self.format);
+        self.generated_cover = profile.generated_cover.or(self.generated_cover);
+        self.cover = profile.cover.or(self.cover);
+        self.library = profile.library.or(self.library);
+        self.email = profile.email.or(self.email);
+        self.device = profile.device.or(self.device);
+        self.calibre = profile.calibre.or(self.calibre);
+        self.pdf = profile.pdf.or(self.pdf);
+        self.text = profile.text.or(self.text);
+        self.style = profile.style.or(self.style);
+        if profile.cookies.is_some() || profile.browser_profile.is_some() {
+            self.cookies = profile.cookies;
+            self.browser_profile = profile.browser_profile;
+        }
+        Ok(self)
+    }
+
+    fn expand_paths(&mut self) {
+        for path in [
+            &mut self.output,
+            &mut self.cookies,
+            &mut self.browser_profile,
+            &mut self.library,
+        ] {
+            expand_home(path);
+        }
+        if let Some(device) = &mut self.device {
+            expand_home(&mut device.path);
+        }
+        if let Some(style) = &mut self.style {
+            expand_home(&mut style.css);
+            for font in &mut style.fonts {
+                expand_home_path(font);
+            }
+        }
+        if let Some(calibre) = &mut self.calibre {
+            expand_home(&mut calibre.library);
+            expand_home(&mut calibre.calibredb);
+        }
+        for publication in self.publications.values_mut() {
+            for path in [
+                &mut publication.cookies,
+                &mut publication.browser_profile,
+                &mut publication.stylesheet,
+            ] {
+                expand_home(path);
+            }
+        }
+        for profile in self.profiles.values_mut() {
+            profile.expand_paths();
+        }
+    }
+}
+
+pub fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("magaziner").join("config.toml"))
+}
+
+/// Reads the config at `path`, treating a missing file as an empty config.
+pub fn load_config(path: &Path) -> Result<Config> {
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    parse_config(&content)
+        .with_context(|| format!("Failed to parse config file {}", path.display()))
+}
+
+fn parse_config(content: &str) -> Result<Config> {
+    let mut config: Config = toml::from_str(content)?;
+    config.expand_paths();
+    Ok(config)
+}
+
+// Config files are hand-written, so accept "~/Books" the way a shell would.
+fn expand_home(path: &mut Option<PathBuf>) {
+    if let Some(p) = path {
+        expand_home_path(p);
+    }
+}
+
+fn expand_home_path(path: &mut PathBuf) {
+    if let Ok(rest) = path.strip_prefix("~")
+        && let Some(home) = dirs::home_dir()
+    {
+        *path = home.join(rest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+output = "/books"
+delay = 2000
+
+[publications.harpers]
+delay = 5000
+username = "reader"
+output_subdir = "Harpers"
+
+[profiles.fast]
+delay = 500
+
+[profiles.fast.publications.harpers]
+output_subdir = "Harpers-fast"
+
+[profiles.archive]
+output = "/archive"
+force = true
+"#;
+
+    #[test]
+    fn test_parse_config_defaults_and_publications() {
+        let config = parse_config(CONFIG).unwrap();
+        assert_eq!(config.output, Some(PathBuf::from("/books")));
+        assert_eq!(config.delay, Some(2000));
+        let harpers = config.publication("harpers").unwrap();
+        assert_eq!(harpers.delay, Some(5000));
+        assert_eq!(harpers.username.as_deref(), Some("reader"));
+        assert_eq!(config.profiles.len(), 2);
+    }
+
+    #[test]
+    fn test_profile_overrides_base_and_publication_settings() {
+        let config = parse_config(CONFIG).unwrap().with_profile("fast").unwrap();
+        assert_eq!(config.delay, Some(500));
+        assert_eq!(config.output, Some(PathBuf::from("/books")));
+        let harpers = config.publication("harpers").unwrap();
+        assert_eq!(
+            harpers.delay, None,
+            "profile delay beats base publication delay"
+        );
+        assert_eq!(harpers.username.as_deref(), Some("reader"));
+        assert_eq!(harpers.output_subdir, Some(PathBuf::from("Harpers-fast")));
+    }
+
+    #[test]
+    fn test_profile_without_publication_section_keeps_base_publication() {
+        let config = parse_config(CONFIG)
+            .unwrap()
+            .with_profile("archive")
+            .unwrap();
+        assert_eq!(config.output, Some(PathBuf::from("/archive")));
+        assert_eq!(config.force, Some(true));
+        assert_eq!(config.publication("harpers").unwrap().delay, Some(5000));
+    }
+
+    #[test]
+    fn test_unknown_profile_lists_known_profiles() {
+        let err = parse_config(CONFIG)
+            .unwrap()
+            .with_profile("slow")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("archive, fast"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        assert!(parse_config("dleay = 1000").is_err());
+    }
+
+    #[test]
+    fn test_home_is_expanded() {
+        let config = parse_config(r#"output = "~/Books""#).unwrap();
+        let output = config.output.unwrap();
+        assert!(!output.starts_with("~"));
+        assert!(output.ends_with("Books"));
+    }
+}
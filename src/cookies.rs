@@ -0,0 +1,429 @@
+use anyhow::{Context, Result, anyhow};
+use reqwest::cookie::Jar;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    pub domain: String,
+    pub include_subdomains: bool,
+    pub path: String,
+    pub secure: bool,
+    /// Unix timestamp in seconds; `None` for session cookies.
+    pub expires: Option<u64>,
+    pub name: String,
+    pub value: String,
+}
+
+impl Cookie {
+    pub fn matches_host(&self, host: &str) -> bool {
+        let domain = self.domain.trim_start_matches('.');
+        host == domain
+            || (self.include_subdomains || self.domain.starts_with('.'))
+                && host.ends_with(&format!(".{}", domain))
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+}
+
+pub fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Parses the tab-separated Netscape `cookies.txt` format written by curl and
+/// the common "export cookies" browser extensions. Malformed lines are skipped.
+pub fn parse_netscape(content: &str) -> Vec<Cookie> {
+    content
+        .lines()
+        .filter_map(|line| {
+            // curl marks HttpOnly cookies by prefixing the domain rather than
+            // commenting the line out.
+            let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+            if line.trim().is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let fields: Vec<&str> = line.trim_end_matches(['\r', '\n']).split('\t').collect();
+            if fields.len() < 7 {
+                return None;
+            }
+            let expires = fields[4].parse::<u64>().ok().filter(|&e| e > 0);
+            Some(Cookie {
+                domain: fields[0].to_string(),
+                include_subdomains: fields[1].eq_ignore_ascii_case("TRUE"),
+                path: fields[2].to_string(),
+                secure: fields[3].eq_ignore_ascii_case("TRUE"),
+                expires,
+                name: fields[5].to_string(),
+                value: fields[6..].join("\t"),
+            })
+        })
+        .collect()
+}
+
+/// Where session cookies were read from, so errors can say how to refresh them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CookieSource {
+    /// A Netscape cookies.txt exported from a browser.
+    File,
+    /// A Firefox or Chromium profile's cookie store.
+    Browser,
+    /// A raw `Cookie` header from the environment.
+    Header,
+    /// The session saved after a username/password login.
+    SavedSession,
+}
+
+impl CookieSource {
+    fn expired_hint(self) -> &'static str {
+        match self {
+            CookieSource::File => "Log in again in your browser and re-export cookies.txt.",
+            CookieSource::Browser => "Log in again in your browser to refresh it.",
+            CookieSource::Header => "Copy a fresh Cookie header from a logged-in browser.",
+            CookieSource::SavedSession => "Log in again with your username and password.",
+        }
+    }
+
+    fn missing_hint(self) -> &'static str {
+        match self {
+            CookieSource::File => "Export cookies.txt from a browser where you are logged in.",
+            CookieSource::Browser => "Make sure you are logged in with that browser profile.",
+            CookieSource::Header => "Copy the Cookie header from a logged-in browser session.",
+            CookieSource::SavedSession => "Log in again with your username and password.",
+        }
+    }
+}
+
+/// Checks that a cookie whose name starts with `prefix` is present and still
+/// valid. `cookies` should include expired entries so the error can say so.
+pub fn require_login_cookie(
+    cookies: &[Cookie],
+    prefix: &str,
+    source: &str,
+    kind: CookieSource,
+) -> Result<()> {
+    let now = now_unix();
+    let candidates: Vec<&Cookie> = cookies
+        .iter()
+        .filter(|c| c.name.starts_with(prefix))
+        .collect();
+
+    if candidates.iter().any(|c| !c.is_expired(now)) {
+        return Ok(());
+    }
+
+    match candidates.iter().filter_map(|c| c.expires).max() {
+        Some(expired_at) => Err(anyhow!(
+            "The login cookie '{}*' in {} expired {} ago. {}",
+            prefix,
+            source,
+            describe_duration(now.saturating_sub(expired_at)),
+            kind.expired_hint()
+        )),
+        None => Err(anyhow!(
+            "No login cookie '{}*' found in {}. {}",
+            prefix,
+            source,
+            kind.missing_hint()
+        )),
+    }
+}
+
+/// Loads `path` for `host`, verifying the login cookie when the publication has one.
+pub fn load_session_cookies(
+    path: &Path,
+    host: &str,
+    login_cookie: Option<&str>,
+    kind: CookieSource,
+) -> Result<Vec<Cookie>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read cookies file {}", path.display()))?;
+    filter_session_cookies(
+        parse_netscape(&content),
+        host,
+        login_cookie,
+        &path.display().to_string(),
+        kind,
+    )
+}
+
+/// Keeps the unexpired cookies that apply to `host`, failing if the login
+/// cookie is missing or has expired. `source` names where they came from.
+pub fn filter_session_cookies(
+    cookies: Vec<Cookie>,
+    host: &str,
+    login_cookie: Option<&str>,
+    source: &str,
+    kind: CookieSource,
+) -> Result<Vec<Cookie>> {
+    let for_host: Vec<Cookie> = cookies
+        .into_iter()
+        .filter(|c| c.matches_host(host))
+        .collect();
+
+    if let Some(prefix) = login_cookie {
+        require_login_cookie(&for_host, prefix, source, kind)?;
+    }
+
+    let now = now_unix();
+    Ok(for_host
+        .into_iter()
+        .filter(|c| !c.is_expired(now))
+        .collect())
+}
+
+/// Serialises cookies in the Netscape format read by `parse_netscape`.
+pub fn to_netscape(cookies: &[Cookie]) -> String {
+    let mut out = String::from("# Netscape HTTP Cookie File\n");
+    for c in cookies {
+        out.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            c.domain,
+            if c.include_subdomains {
+                "TRUE"
+            } else {
+                "FALSE"
+            },
+            c.path,
+            if c.secure { "TRUE" } else { "FALSE" },
+            c.expires.unwrap_or(0),
+            c.name,
+            c.value
+        ));
+    }
+    out
+}
+
+/// Parses a raw `Cookie` request header ("a=1; b=2") as session cookies for `host`.
+pub fn parse_cookie_header(header: &str, host: &str) -> Vec<Cookie> {
+    header
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .filter(|(name, _)| !name.is_empty())
+        .map(|(name, value)| Cookie {
+            domain: host.to_string(),
+            include_subdomains: false,
+            path: "/".to_string(),
+            secure: false,
+            expires: None,
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+        .collect()
+}
+
+/// Parses a `Set-Cookie` header value received from `host`.
+pub fn parse_set_cookie(header: &str, host: &str) -> Option<Cookie> {
+    let mut parts = header.split(';').map(str::trim);
+    let (name, value) = parts.next()?.split_once('=')?;
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut cookie = Cookie {
+        domain: host.to_string(),
+        include_subdomains: false,
+        path: "/".to_string(),
+        secure: false,
+        expires: None,
+        name: name.to_string(),
+        value: value.to_string(),
+    };
+    let mut max_age = None;
+
+    for attr in parts {
+        let (key, val) = attr.split_once('=').unwrap_or((attr, ""));
+        match key.to_ascii_lowercase().as_str() {
+            "domain" if !val.is_empty() => {
+                cookie.domain = format!(".{}", val.trim_start_matches('.'));
+                cookie.include_subdomains = true;
+            }
+            "path" if !val.is_empty() => cookie.path = val.to_string(),
+            "secure" => cookie.secure = true,
+            "max-age" => max_age = val.parse::<i64>().ok(),
+            "expires" => {
+                cookie.expires = httpdate::parse_http_date(val)
+                    .ok()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs());
+            }
+            _ => {}
+        }
+    }
+
+    // Max-Age takes precedence over Expires.
+    if let Some(secs) = max_age {
+        cookie.expires = Some((now_unix() as i64 + secs).max(0) as u64);
+    }
+    Some(cookie)
+}
+
+pub fn to_jar(cookies: &[Cookie], host: &str) -> Result<Jar> {
+    let url = Url::parse(&format!("https://{}/", host))?;
+    let jar = Jar::default();
+    for cookie in cookies {
+        // Without `Domain` the jar keeps the cookie to `host` alone.
+        let domain = if cookie.include_subdomains {
+            format!("; Domain={}", cookie.domain.trim_start_matches('.'))
+        } else {
+            String::new()
+        };
+        jar.add_cookie_str(
+            &format!(
+                "{}={}{}; Path={}",
+                cookie.name, cookie.value, domain, cookie.path
+            ),
+            &url,
+        );
+    }
+    Ok(jar)
+}
+
+pub fn describe_duration(secs: u64) -> String {
+    match secs {
+        s if s < 3600 => format!("{} minutes", s / 60),
+        s if s < 86_400 => format!("{} hours", s / 3600),
+        s => format!("{} days", s / 86_400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOKIES_TXT: &str = "# Netscape HTTP Cookie File\n\
+        # https://curl.se/docs/http-cookies.html\n\
+        \n\
+        .harpers.org\tTRUE\t/\tTRUE\t4102444800\twordpress_logged_in_abc\tuser%7C123\n\
+        #HttpOnly_harpers.org\tFALSE\t/\tTRUE\t0\tsession_id\txyz\n\
+        .harpers.org\tTRUE\t/\tFALSE\t1000\tstale\told\n\
+        .lrb.co.uk\tTRUE\t/\tFALSE\t4102444800\tlrb_session\tnope\n";
+
+    #[test]
+    fn test_parse_netscape_reads_httponly_and_session_cookies() {
+        let cookies = parse_netscape(COOKIES_TXT);
+        assert_eq!(cookies.len(), 4);
+        assert_eq!(cookies[0].name, "wordpress_logged_in_abc");
+        assert_eq!(cookies[0].value, "user%7C123");
+        assert!(cookies[0].include_subdomains);
+        assert_eq!(cookies[1].domain, "harpers.org");
+        assert_eq!(cookies[1].expires, None);
+    }
+
+    #[test]
+    fn test_matches_host_filters_other_domains() {
+        let cookies = parse_netscape(COOKIES_TXT);
+        let harpers: Vec<_> = cookies
+            .iter()
+            .filter(|c| c.matches_host("harpers.org"))
+            .collect();
+        assert_eq!(harpers.len(), 3);
+        assert!(cookies[0].matches_host("www.harpers.org"));
+        assert!(!cookies[0].matches_host("notharpers.org"));
+    }
+
+    #[test]
+    fn test_load_session_cookies_drops_expired() {
+        let path =
+            std::env::temp_dir().join(format!("magaziner-cookies-{}.txt", std::process::id()));
+        fs::write(&path, COOKIES_TXT).unwrap();
+        let cookies = load_session_cookies(
+            &path,
+            "harpers.org",
+            Some("wordpress_logged_in_"),
+            CookieSource::File,
+        )
+        .unwrap();
+        fs::remove_file(&path).ok();
+
+        let names: Vec<&str> = cookies.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["wordpress_logged_in_abc", "session_id"]);
+    }
+
+    #[test]
+    fn test_netscape_round_trip() {
+        let cookies = parse_netscape(COOKIES_TXT);
+        assert_eq!(parse_netscape(&to_netscape(&cookies)), cookies);
+    }
+
+    #[test]
+    fn test_to_jar_keeps_host_only_cookies_off_subdomains() {
+        use reqwest::cookie::CookieStore;
+
+        let cookies = parse_netscape(COOKIES_TXT);
+        let jar = to_jar(&cookies[..3], "harpers.org").unwrap();
+        let sent = |url: &str| {
+            jar.cookies(&Url::parse(url).unwrap())
+                .map(|value| value.to_str().unwrap().to_string())
+                .unwrap_or_default()
+        };
+        let apex = sent("https://harpers.org/");
+        assert!(apex.contains("wordpress_logged_in_abc=") && apex.contains("session_id=xyz"));
+        let subdomain = sent("https://shop.harpers.org/");
+        assert!(subdomain.contains("wordpress_logged_in_abc="));
+        assert!(!subdomain.contains("session_id"));
+    }
+
+    #[test]
+    fn test_parse_cookie_header() {
+        let cookies =
+            parse_cookie_header("wordpress_logged_in_x=abc123; other=value", "harpers.org");
+        assert_eq!(cookies.len(), 2);
+        assert_eq!(cookies[1].name, "other");
+        assert_eq!(cookies[1].value, "value");
+    }
+
+    #[test]
+    fn test_parse_set_cookie_attributes() {
+        let cookie = parse_set_cookie(
+            "wordpress_logged_in_abc=user%7C1; Expires=Fri, 01 Jan 2100 00:00:00 GMT; Domain=harpers.org; Path=/; Secure; HttpOnly",
+            "harpers.org",
+        )
+        .unwrap();
+        assert_eq!(cookie.name, "wordpress_logged_in_abc");
+        assert_eq!(cookie.value, "user%7C1");
+        assert_eq!(cookie.domain, ".harpers.org");
+        assert!(cookie.secure);
+        assert_eq!(cookie.expires, Some(4_102_444_800));
+    }
+
+    #[test]
+    fn test_expired_login_cookie_is_reported() {
+        let cookies =
+            parse_netscape(".harpers.org\tTRUE\t/\tTRUE\t1000\twordpress_logged_in_abc\tuser\n");
+        let err = require_login_cookie(
+            &cookies,
+            "wordpress_logged_in_",
+            "cookies.txt",
+            CookieSource::File,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("expired"), "unexpected error: {}", err);
+        assert!(
+            err.contains("re-export cookies.txt"),
+            "unexpected error: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_missing_login_cookie_is_reported() {
+        let err = require_login_cookie(
+            &[],
+            "wordpress_logged_in_",
+            "Firefox profile",
+            CookieSource::Browser,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("No login cookie"), "unexpected error: {}", err);
+        assert!(err.contains("browser profile"), "unexpected error: {}", err);
+    }
+}
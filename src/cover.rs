@@ -0,0 +1,537 @@
+use crate::adapter::IssueData;
+use crate::blocks::Style;
+use crate::pdf::font_data;
+use anyhow::{Context, Result, anyhow};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{ColorType, DynamicImage, ImageFormat, Rgb, RgbImage};
+use serde::Deserialize;
+use ttf_parser::{Face, OutlineBuilder};
+
+const WIDTH: u32 = 1200;
+const HEIGHT: u32 = 1600;
+const MARGIN: f32 = 110.0;
+const BAND: u32 = 60;
+const JPEG_QUALITY: u8 = 90;
+
+/// `--cover-size` names for the portrait screens of common e-readers.
+const SIZE_PRESETS: &[(&str, (u32, u32))] = &[
+    ("kindle", (1236, 1648)),
+    ("kobo", (1264, 1680)),
+    ("kobo-clara", (1072, 1448)),
+];
+
+/// The `[cover]` config section.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CoverConfig {
+    pub size: Option<String>,
+    pub greyscale: Option<bool>,
+}
+
+/// How the cover is prepared for the book.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CoverOptions {
+    /// Largest width and height; bigger covers are scaled down to fit.
+    pub size: Option<(u32, u32)>,
+    pub greyscale: bool,
+}
+
+/// Layers `--cover-size` and `--greyscale-cover` over the config file's
+/// `[cover]` section.
+pub fn resolve_cover(
+    size: Option<&str>,
+    greyscale: bool,
+    config: Option<&CoverConfig>,
+) -> Result<CoverOptions> {
+    let size = size.or(config.and_then(|c| c.size.as_deref()));
+    Ok(CoverOptions {
+        size: size.map(parse_size).transpose()?,
+        greyscale: greyscale || config.and_then(|c| c.greyscale).unwrap_or(false),
+    })
+}
+
+fn parse_size(value: &str) -> Result<(u32, u32)> {
+    if let Some((_, size)) = SIZE_PRESETS.iter().find(|(name, _)| *name == value) {
+        return Ok(*size);
+    }
+    let size = value
+        .split_once(['x', '×'])
+        .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)));
+    match size {
+        Some((w, h)) if (1..=10_000).contains(&w) && (1..=10_000).contains(&h) => Ok((w, h)),
+        _ => Err(anyhow!(
+            "Invalid cover size '{}': use WIDTHxHEIGHT (ex: 1264x1680) or one of {}",
+            value,
+            SIZE_PRESETS
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+/// Turns a cover into the JPEG the book declares it to be: any format the
+/// `image` crate reads, scaled down to fit `size` and greyscaled if asked.
+/// A JPEG that needs neither is kept byte for byte.
+pub fn process_cover(bytes: &[u8], options: &CoverOptions) -> Result<Vec<u8>> {
+    let format = image::guess_format(bytes).context("Cover is not an image")?;
+    let image = image::load_from_memory_with_format(bytes, format)
+        .with_context(|| format!("Failed to decode {:?} cover", format))?;
+    let fits = options
+        .size
+        .is_none_or(|(w, h)| image.width() <= w && image.height() <= h);
+    if format == ImageFormat::Jpeg && fits && !options.greyscale {
+        return Ok(bytes.to_vec());
+    }
+
+    let image = match options.size {
+        Some((w, h)) if !fits => image.resize(w, h, FilterType::Lanczos3),
+        _ => image,
+    };
+    let rgb = flatten(&image);
+    let (width, height) = rgb.dimensions();
+    let mut jpeg = Vec::new();
+    let mut encoder = JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY);
+    if options.greyscale {
+        let grey = DynamicImage::ImageRgb8(rgb).to_luma8();
+        encoder.encode(grey.as_raw(), width, height, ColorType::L8)?;
+    } else {
+        encoder.encode(rgb.as_raw(), width, height, ColorType::Rgb8)?;
+    }
+    Ok(jpeg)
+}
+
+/// Drops the alpha channel, turning transparent areas white rather than black.
+fn flatten(image: &DynamicImage) -> RgbImage {
+    let rgba = image.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let alpha = a as f32 / 255.0;
+        Rgb([r, g, b].map(|c| (c as f32 * alpha + 255.0 * (1.0 - alpha)).round() as u8))
+    })
+}
+
+/// Colours for a generated cover, chosen per publication by
+/// `MagazineAdapter::cover_colours`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoverColours {
+    pub background: [u8; 3],
+    pub text: [u8; 3],
+    /// The bands at the top and bottom and the rule under the masthead.
+    pub accent: [u8; 3],
+}
+
+impl Default for CoverColours {
+    fn default() -> Self {
+        CoverColours {
+            background: [0x23, 0x30, 0x4a],
+            text: [0xff, 0xff, 0xff],
+            accent: [0xe0, 0xb0, 0x4c],
+        }
+    }
+}
+
+/// Draws a JPEG cover for an issue without one: the publication's name as a
+/// masthead, the issue title and its date.
+pub fn generate_cover(issue: &IssueData, colours: &CoverColours) -> Result<Vec<u8>> {
+    let mut image = RgbImage::from_pixel(WIDTH, HEIGHT, Rgb(colours.background));
+    for y in (0..BAND).chain(HEIGHT - BAND..HEIGHT) {
+        for x in 0..WIDTH {
+            image.put_pixel(x, y, Rgb(colours.accent));
+        }
+    }
+
+    let bold = Face::parse(font_data(Style::Bold), 0)?;
+    let regular = Face::parse(font_data(Style::Regular), 0)?;
+    let italic = Face::parse(font_data(Style::Italic), 0)?;
+    let width = WIDTH as f32 - 2.0 * MARGIN;
+
+    let (size, lines) = fit(&bold, &issue.publication_name, 130.0, width, 3);
+    let mut baseline = 230.0 + size;
+    for line in &lines {
+        draw_text(&mut image, &bold, line, size, baseline, colours.text);
+        baseline += size * 1.15;
+    }
+
+    let rule_top = (baseline - size * 0.4) as u32;
+    for y in rule_top..rule_top + 10 {
+        for x in (WIDTH / 2 - 130)..(WIDTH / 2 + 130) {
+            image.put_pixel(x, y, Rgb(colours.accent));
+        }
+    }
+
+    let (size, lines) = fit(&regular, &issue.title, 86.0, width, 5);
+    let block = size * 1.25 * lines.len() as f32;
+    let mut baseline = (HEIGHT as f32 * 0.62 - block / 2.0).max(rule_top as f32 + 80.0) + size;
+    for line in &lines {
+        draw_text(&mut image, &regular, line, size, baseline, colours.text);
+        baseline += size * 1.25;
+    }
+
+    if let Some(date) = issue.identity.long_date()
+        && !issue.title.contains(&date)
+    {
+        let (size, lines) = fit(&italic, &date, 60.0, width, 1);
+        let baseline = HEIGHT as f32 - BAND as f32 - 150.0;
+        draw_text(&mut image, &italic, &lines[0], size, baseline, colours.text);
+    }
+
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY).encode(
+        image.as_raw(),
+        WIDTH,
+        HEIGHT,
+        ColorType::Rgb8,
+    )?;
+    Ok(jpeg)
+}
+
+/// Wraps `text` to `width` pixels, shrinking the type from `size` until it
+/// fits on `max_lines` lines.
+fn fit(face: &Face, text: &str, size: f32, width: f32, max_lines: usize) -> (f32, Vec<String>) {
+    let mut size = size;
+    loop {
+        let lines = wrap(face, text, size, width);
+        let fits =
+            lines.len() <= max_lines && lines.iter().all(|line| measure(face, line, size) <= width);
+        if fits || size < 24.0 {
+            return (size, lines);
+        }
+        size *= 0.9;
+    }
+}
+
+fn wrap(face: &Face, text: &str, size: f32, width: f32) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if measure(face, &format!("{} {}", line, word), size) <= width => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    lines
+}
+
+fn measure(face: &Face, text: &str, size: f32) -> f32 {
+    let scale = size / face.units_per_em() as f32;
+    text.chars()
+        .map(|c| {
+            let glyph = face.glyph_index(c).unwrap_or_default();
+            face.glyph_hor_advance(glyph).unwrap_or(0) as f32 * scale
+        })
+        .sum()
+}
+
+/// Draws one line of text centred on the page with its baseline at `baseline`.
+fn draw_text(
+    image: &mut RgbImage,
+    face: &Face,
+    text: &str,
+    size: f32,
+    baseline: f32,
+    colour: [u8; 3],
+) {
+    let scale = size / face.units_per_em() as f32;
+    let ascent = face.ascender() as f32 * scale;
+    let descent = -face.descender() as f32 * scale;
+    let left = ((WIDTH as f32 - measure(face, text, size)) / 2.0).floor() - 2.0;
+    let top = (baseline - ascent).floor() - 2.0;
+    let mut raster = Raster::new(
+        (measure(face, text, size) + 6.0).ceil() as usize,
+        (ascent + descent + 6.0).ceil() as usize,
+    );
+
+    let mut pen = 2.0;
+    for c in text.chars() {
+        let glyph = face.glyph_index(c).unwrap_or_default();
+        let mut outline = Outline {
+            raster: &mut raster,
+            scale,
+            x: pen,
+            y: baseline - top,
+            start: (0.0, 0.0),
+            last: (0.0, 0.0),
+        };
+        face.outline_glyph(glyph, &mut outline);
+        pen += face.glyph_hor_advance(glyph).unwrap_or(0) as f32 * scale;
+    }
+
+    for (i, coverage) in raster.coverage().enumerate() {
+        let x = left as i64 + (i % raster.width) as i64;
+        let y = top as i64 + (i / raster.width) as i64;
+        if coverage <= 0.0 || x < 0 || y < 0 || x >= WIDTH as i64 || y >= HEIGHT as i64 {
+            continue;
+        }
+        let pixel = image.get_pixel_mut(x as u32, y as u32);
+        for (channel, target) in pixel.0.iter_mut().zip(colour) {
+            *channel =
+                (*channel as f32 * (1.0 - coverage) + target as f32 * coverage).round() as u8;
+        }
+    }
+}
+
+/// Feeds a glyph outline, scaled to pixels and flipped so y grows down, to a
+/// raster as straight lines.
+struct Outline<'a> {
+    raster: &'a mut Raster,
+    scale: f32,
+    /// Where the glyph origin sits in the raster.
+    x: f32,
+    y: f32,
+    start: (f32, f32),
+    last: (f32, f32),
+}
+
+impl Outline<'_> {
+    fn point(&self, x: f32, y: f32) -> (f32, f32) {
+        (self.x + x * self.scale, self.y - y * self.scale)
+    }
+
+    fn line(&mut self, to: (f32, f32)) {
+        self.raster.line(self.last, to);
+        self.last = to;
+    }
+}
+
+impl OutlineBuilder for Outline<'_> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.start = self.point(x, y);
+        self.last = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let to = self.point(x, y);
+        self.line(to);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (p0, p1, p2) = (self.last, self.point(x1, y1), self.point(x, y));
+        let steps = segments(distance(p0, p1) + distance(p1, p2));
+        for i in 1..=steps {
+            let t = i as f32 / steps as f32;
+            let u = 1.0 - t;
+            self.line((
+                u * u * p0.0 + 2.0 * u * t * p1.0 + t * t * p2.0,
+                u * u * p0.1 + 2.0 * u * t * p1.1 + t * t * p2.1,
+            ));
+        }
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (p0, p1, p2, p3) = (
+            self.last,
+            self.point(x1, y1),
+            self.point(x2, y2),
+            self.point(x, y),
+        );
+        let steps = segments(distance(p0, p1) + distance(p1, p2) + distance(p2, p3));
+        for i in 1..=steps {
+            let t = i as f32 / steps as f32;
+            let u = 1.0 - t;
+            let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+            self.line((
+                a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+                a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+            ));
+        }
+    }
+
+    fn close(&mut self) {
+        if self.last != self.start {
+            self.line(self.start);
+        }
+    }
+}
+
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+/// Straight lines to split a curve of roughly `length` pixels into.
+fn segments(length: f32) -> usize {
+    ((length / 3.0).ceil() as usize).clamp(1, 32)
+}
+
+/// Anti-aliased scanline fill: each edge adds the signed area it covers to
+/// its cells, and a running sum along each row gives every pixel's coverage.
+struct Raster {
+    width: usize,
+    height: usize,
+    area: Vec<f32>,
+}
+
+impl Raster {
+    fn new(width: usize, height: usize) -> Raster {
+        Raster {
+            width,
+            height,
+            area: vec![0.0; width * height + 2],
+        }
+    }
+
+    fn line(&mut self, from: (f32, f32), to: (f32, f32)) {
+        let clamp = |(x, y): (f32, f32)| {
+            (
+                x.clamp(0.0, self.width as f32 - 1.01),
+                y.clamp(0.0, self.height as f32),
+            )
+        };
+        let (from, to) = (clamp(from), clamp(to));
+        if from.1 == to.1 {
+            return;
+        }
+        let (direction, top, bottom) = if from.1 < to.1 {
+            (1.0, from, to)
+        } else {
+            (-1.0, to, from)
+        };
+        let dxdy = (bottom.0 - top.0) / (bottom.1 - top.1);
+        let mut x = top.0;
+        for row in top.1 as usize..(bottom.1.ceil() as usize).min(self.height) {
+            let start = row * self.width;
+            let dy = ((row + 1) as f32).min(bottom.1) - (row as f32).max(top.1);
+            let next = x + dxdy * dy;
+            let d = dy * direction;
+            let (x0, x1) = if x < next { (x, next) } else { (next, x) };
+            let x0_floor = x0.floor();
+            let x0i = x0_floor as usize;
+            let x1_ceil = x1.ceil();
+            let x1i = x1_ceil as usize;
+            if x1i <= x0i + 1 {
+                // The edge stays within one pixel on this row.
+                let mid = 0.5 * (x + next) - x0_floor;
+                self.area[start + x0i] += d - d * mid;
+                self.area[start + x0i + 1] += d * mid;
+            } else {
+                let s = (x1 - x0).recip();
+                let x0f = x0 - x0_floor;
+                let a0 = 0.5 * s * (1.0 - x0f) * (1.0 - x0f);
+                let x1f = x1 - x1_ceil + 1.0;
+                let am = 0.5 * s * x1f * x1f;
+                self.area[start + x0i] += d * a0;
+                if x1i == x0i + 2 {
+                    self.area[start + x0i + 1] += d * (1.0 - a0 - am);
+                } else {
+                    let a1 = s * (1.5 - x0f);
+                    self.area[start + x0i + 1] += d * (a1 - a0);
+                    for xi in x0i + 2..x1i - 1 {
+                        self.area[start + xi] += d * s;
+                    }
+                    let a2 = a1 + (x1i - x0i - 3) as f32 * s;
+                    self.area[start + x1i - 1] += d * (1.0 - a2 - am);
+                }
+                self.area[start + x1i] += d * am;
+            }
+            x = next;
+        }
+    }
+
+    /// Coverage from 0 to 1 for each pixel, row by row.
+    fn coverage(&self) -> impl Iterator<Item = f32> + '_ {
+        let mut sum = 0.0;
+        self.area[..self.width * self.height].iter().map(move |a| {
+            sum += a;
+            f32::min(f32::abs(sum), 1.0)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IssueIdentity;
+
+    fn issue(title: &str) -> IssueData {
+        IssueData {
+            links: vec![],
+            title: title.into(),
+            identity: IssueIdentity::from_date_text("March 2026"),
+            css: String::new(),
+            cover_image_uri: String::new(),
+            publication_name: "Harper's Magazine".into(),
+        }
+    }
+
+    #[test]
+    fn test_generated_cover_is_a_jpeg_in_the_publication_colours() {
+        let colours = CoverColours {
+            background: [0xf5, 0xf0, 0xe6],
+            text: [0x1c, 0x1c, 0x1c],
+            accent: [0xc8, 0x10, 0x2e],
+        };
+        let jpeg = generate_cover(&issue("The Future of Work"), &colours).unwrap();
+        assert_eq!(&jpeg[..2], [0xff, 0xd8]);
+
+        let image = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+        assert_eq!(image.dimensions(), (WIDTH, HEIGHT));
+        let near = |pixel: &Rgb<u8>, colour: [u8; 3]| {
+            pixel.0.iter().zip(colour).all(|(a, b)| a.abs_diff(b) < 24)
+        };
+        assert!(near(image.get_pixel(WIDTH / 2, 20), colours.accent));
+        assert!(near(image.get_pixel(20, HEIGHT / 2), colours.background));
+        let inked = image.pixels().filter(|p| near(p, colours.text)).count();
+        assert!(inked > 20_000, "only {} text pixels", inked);
+    }
+
+    #[test]
+    fn test_process_converts_resizes_and_greyscales_covers() {
+        let mut png = Vec::new();
+        let transparent = image::RgbaImage::from_fn(800, 1200, |x, _| {
+            if x < 400 {
+                image::Rgba([200, 0, 0, 255])
+            } else {
+                image::Rgba([0, 0, 0, 0])
+            }
+        });
+        DynamicImage::ImageRgba8(transparent)
+            .write_to(&mut std::io::Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+
+        let options = resolve_cover(Some("400x400"), false, None).unwrap();
+        let jpeg = process_cover(&png, &options).unwrap();
+        assert_eq!(image::guess_format(&jpeg).unwrap(), ImageFormat::Jpeg);
+        let cover = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+        assert_eq!(cover.dimensions(), (267, 400));
+        let [r, g, b] = cover.get_pixel(50, 200).0;
+        assert!(r > 180 && g < 40 && b < 40);
+        assert!(cover.get_pixel(250, 200).0.iter().all(|c| *c > 235));
+
+        // A JPEG that already fits is left alone, unless it's to be greyscaled.
+        assert_eq!(process_cover(&jpeg, &options).unwrap(), jpeg);
+        let config = CoverConfig {
+            size: Some("kobo".into()),
+            greyscale: Some(true),
+        };
+        let options = resolve_cover(None, false, Some(&config)).unwrap();
+        assert_eq!(options.size, Some((1264, 1680)));
+        let grey = image::load_from_memory(&process_cover(&jpeg, &options).unwrap()).unwrap();
+        assert_eq!(grey.color(), image::ColorType::L8);
+
+        assert!(process_cover(b"not really a jpeg", &options).is_err());
+        assert!(resolve_cover(Some("1264 by 1680"), false, None).is_err());
+    }
+
+    #[test]
+    fn test_fit_shrinks_long_text_to_the_line_limit() {
+        let face = Face::parse(font_data(Style::Regular), 0).unwrap();
+        let title = "An Unusually Long Issue Title That Would Never Fit On The Cover At Full Size";
+        let (size, lines) = fit(&face, title, 86.0, 980.0, 3);
+        assert!(size < 86.0);
+        assert!(lines.len() <= 3);
+        assert!(lines.iter().all(|line| measure(&face, line, size) <= 980.0));
+        assert_eq!(lines.join(" "), title);
+
+        assert_eq!(
+            fit(&face, "LRB", 86.0, 980.0, 1),
+            (86.0, vec!["LRB".to_string()])
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use regex::Regex;
+use scraper::{Html, Selector};
+
+/// At-rules whose contents are dropped outright: web fonts, animations, and
+/// imports that would be fetched from the publisher's site.
+const DROPPED_AT_RULES: &[&str] = &[
+    "font-face",
+    "keyframes",
+    "-webkit-keyframes",
+    "import",
+    "charset",
+];
+
+/// At-rules holding ordinary rules, which are pruned like the top level.
+const NESTED_AT_RULES: &[&str] = &["media", "supports"];
+
+/// Colour declarations, which fight e-ink screens and reader night modes.
+const COLOUR_PROPERTIES: &[&str] = &[
+    "color",
+    "background",
+    "background-color",
+    "border-color",
+    "outline-color",
+    "text-decoration-color",
+];
+
+/// Cuts a publisher stylesheet down to what the book needs: rules with a
+/// selector matching an element in one of `bodies`, without web fonts,
+/// fixed positioning, viewport units or colours.
+pub fn prune(css: &str, bodies: &[&str]) -> String {
+    let filter = Filter {
+        documents: bodies
+            .iter()
+            .map(|body| Html::parse_document(&format!("<html><body>{}</body></html>", body)))
+            .collect(),
+        viewport_unit: Regex::new(r"(?i)\d(?:[dls]?v[hw]|vmin|vmax)\b").unwrap(),
+        pseudo: Regex::new(r"::?[a-zA-Z-]+(?:\([^)]*\))?").unwrap(),
+    };
+    filter.rules(&strip_comments(css)).join("\n")
+}
+
+struct Filter {
+    documents: Vec<Html>,
+    viewport_unit: Regex,
+    pseudo: Regex,
+}
+
+impl Filter {
+    fn rules(&self, css: &str) -> Vec<String> {
+        let mut kept = Vec::new();
+        for rule in split_rules(css) {
+            match rule {
+                Rule::At {
+                    name,
+                    prelude,
+                    block,
+                } => {
+                    if DROPPED_AT_RULES.contains(&name.as_str()) {
+                        continue;
+                    }
+                    match block {
+                        Some(block) if NESTED_AT_RULES.contains(&name.as_str()) => {
+                            let inner = self.rules(block);
+                            if !inner.is_empty() {
+                                kept.push(format!("{} {{\n{}\n}}", prelude, inner.join("\n")));
+                            }
+                        }
+                        Some(block) => kept.push(format!("{} {{{}}}", prelude, block)),
+                        None => kept.push(format!("{};", prelude)),
+                    }
+                }
+                Rule::Style { selectors, block } => {
+                    let selectors: Vec<&str> = split_top_level(selectors, ',')
+                        .into_iter()
+                        .map(str::trim)
+                        .filter(|s| self.matches_any(s))
+                        .collect();
+                    let declarations: Vec<&str> = split_top_level(block, ';')
+                        .into_iter()
+                        .map(str::trim)
+                        .filter(|d| !d.is_empty() && self.keep_declaration(d))
+                        .collect();
+                    if !selectors.is_empty() && !declarations.is_empty() {
+                        kept.push(format!(
+                            "{} {{ {}; }}",
+                            selectors.join(", "),
+                            declarations.join("; ")
+                        ));
+                    }
+                }
+            }
+        }
+        kept
+    }
+
+    /// Whether `selector` matches anything in the articles. Pseudo-classes and
+    /// pseudo-elements are ignored, so `a:hover` counts as `a`; selectors that
+    /// don't parse are dropped.
+    fn matches_any(&self, selector: &str) -> bool {
+        let structural = self.pseudo.replace_all(selector, "");
+        let structural = match structural.trim() {
+            "" => "*",
+            s => s.trim_end_matches(['>', '+', '~', ' ']),
+        };
+        let Ok(selector) = Selector::parse(structural) else {
+            return false;
+        };
+        self.documents
+            .iter()
+            .any(|doc| doc.select(&selector).next().is_some())
+    }
+
+    fn keep_declaration(&self, declaration: &str) -> bool {
+        let Some((property, value)) = declaration.split_once(':') else {
+            return false;
+        };
+        let property = property.trim().to_ascii_lowercase();
+        let value = value.trim().to_ascii_lowercase();
+        if property == "position" && value.starts_with("fixed") {
+            return false;
+        }
+        !COLOUR_PROPERTIES.contains(&property.as_str()) && !self.viewport_unit.is_match(&value)
+    }
+}
+
+enum Rule<'a> {
+    /// `@name prelude { block }`, or `@name prelude;` without a block.
+    At {
+        name: String,
+        prelude: &'a str,
+        block: Option<&'a str>,
+    },
+    Style {
+        selectors: &'a str,
+        block: &'a str,
+    },
+}
+
+/// Splits a stylesheet (without comments) into its top-level rules.
+fn split_rules(css: &str) -> Vec<Rule<'_>> {
+    let mut rules = Vec::new();
+    let mut rest = css.trim_start();
+    while !rest.is_empty() {
+        let head_end = find_top_level(rest, &['{', ';']).unwrap_or(rest.len());
+        let head = rest[..head_end].trim();
+        let ends_with_block = rest[head_end..].starts_with('{');
+        let (block, next) = if ends_with_block {
+            let close = matching_brace(rest, head_end);
+            (
+                Some(&rest[head_end + 1..close]),
+                (close + 1).min(rest.len()),
+            )
+        } else {
+            (None, (head_end + 1).min(rest.len()))
+        };
+        if let Some(at) = head.strip_prefix('@') {
+            let name = at
+                .split(|c: char| c.is_whitespace() || c == '(' || c == '"' || c == '\'')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            rules.push(Rule::At {
+                name,
+                prelude: head,
+                block,
+            });
+        } else if let Some(block) = block
+            && !head.is_empty()
+        {
+            rules.push(Rule::Style {
+                selectors: head,
+                block,
+            });
+        }
+        rest = rest[next..].trim_start();
+    }
+    rules
+}
+
+/// Byte offset of the first of `targets` outside strings and parentheses.
+fn find_top_level(text: &str, targets: &[char]) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (_, '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth = depth.saturating_sub(1),
+            (None, c) if depth == 0 && targets.contains(&c) => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Offset of the `}` closing the `{` at `open`, or the end of `text` if the
+/// stylesheet is cut short.
+fn matching_brace(text: &str, open: usize) -> usize {
+    let mut depth = 0usize;
+    let mut offset = open;
+    while let Some(i) = find_top_level(&text[offset..], &['{', '}']) {
+        offset += i;
+        if text[offset..].starts_with('{') {
+            depth += 1;
+        } else {
+            depth -= 1;
+            if depth == 0 {
+                return offset;
+            }
+        }
+        offset += 1;
+    }
+    text.len()
+}
+
+/// Splits at `separator` outside strings and parentheses, so `url(data:…;…)`
+/// and `:is(a, b)` stay whole.
+fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while let Some(i) = find_top_level(rest, &[separator]) {
+        parts.push(&rest[..i]);
+        rest = &rest[i + 1..];
+    }
+    parts.push(rest);
+    parts
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        rest = match rest[start + 2..].find("*/") {
+            Some(end) => &rest[start + 2 + end + 2..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &str = r#"<div class="article-copy"><p class="first">Text <em>here</em>.</p>
+        <blockquote><p>Quoted</p></blockquote></div>"#;
+
+    #[test]
+    fn test_prune_keeps_rules_matching_article_elements() {
+        let css = r#"
+            /* site chrome */
+            .site-nav, .ad-slot { display: none; }
+            .article-copy p.first::first-letter { font-size: 3em; }
+            body .article-copy blockquote, .cookie-banner { margin: 1em 2em; }
+            a:hover { text-decoration: underline; }
+            @media (min-width: 40em) { .article-copy { column-count: 2; } .footer { padding: 0; } }
+            @media print { .share-buttons { display: none; } }
+            ..bad { margin: 0; }
+            em { font-style: italic; }
+        "#;
+        let pruned = prune(css, &[BODY]);
+        assert_eq!(
+            pruned,
+            ".article-copy p.first::first-letter { font-size: 3em; }\n\
+             body .article-copy blockquote { margin: 1em 2em; }\n\
+             @media (min-width: 40em) {\n.article-copy { column-count: 2; }\n}\n\
+             em { font-style: italic; }"
+        );
+    }
+
+    #[test]
+    fn test_prune_strips_fonts_fixed_viewport_and_colour() {
+        let css = r#"
+            @import url("https://www.lrb.co.uk/fonts.css");
+            @font-face { font-family: "LRB"; src: url(data:font/woff2;base64,AAAA) format("woff2"); }
+            p { font-family: "LRB", serif; color: #333; background: url(data:image/png;base64,AA); line-height: 1.4; }
+            div { position: fixed; top: 0; height: 100vh; max-width: 40em; }
+            blockquote { background-color: #f5f5f5 }
+        "#;
+        let pruned = prune(css, &[BODY]);
+        assert_eq!(
+            pruned,
+            "p { font-family: \"LRB\", serif; line-height: 1.4; }\n\
+             div { top: 0; max-width: 40em; }"
+        );
+    }
+}
@@ -0,0 +1,356 @@
+use crate::progress::Progress;
+use anyhow::{Context, Result, anyhow, bail};
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Send-to-Kindle's limit; most mail providers accept 25 MB or more.
+const DEFAULT_MAX_ATTACHMENT_MB: u64 = 50;
+
+/// Room for the message headers and MIME boundaries around the attachment.
+const MESSAGE_OVERHEAD: u64 = 16 * 1024;
+
+/// SMTP settings from the config file's `[email]` section.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    pub smtp_host: String,
+    pub smtp_port: Option<u16>,
+    pub security: Option<Security>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    #[serde(default)]
+    pub to: Vec<String>,
+    pub max_attachment_mb: Option<u64>,
+    /// Email every built issue without needing `--email`.
+    pub send: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Security {
+    /// Implicit TLS, usually port 465.
+    Tls,
+    /// Plain connection upgraded with STARTTLS, usually port 587.
+    #[default]
+    Starttls,
+    /// No encryption; only for local relays and testing.
+    None,
+}
+
+/// A built book that could not be emailed or copied to a device. Kept separate
+/// from build errors so callers can tell "no book" from "book built, not delivered".
+#[derive(Debug)]
+pub struct DeliveryError {
+    pub path: PathBuf,
+    pub source: anyhow::Error,
+}
+
+impl fmt::Display for DeliveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Built {} but could not deliver it: {:#}",
+            self.path.display(),
+            self.source
+        )
+    }
+}
+
+impl std::error::Error for DeliveryError {}
+
+/// Where and how to email finished books.
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    pub config: EmailConfig,
+    pub recipients: Vec<String>,
+}
+
+impl Delivery {
+    /// Emails `path` as an attachment to every recipient.
+    pub fn send(&self, path: &Path, title: &str, progress: &Progress) -> Result<(), DeliveryError> {
+        self.try_send(path, title, progress)
+            .with_context(|| format!("emailing to {}", self.recipients.join(", ")))
+            .map_err(|source| DeliveryError {
+                path: path.to_path_buf(),
+                source,
+            })
+    }
+
+    fn try_send(&self, path: &Path, title: &str, progress: &Progress) -> Result<()> {
+        let config = &self.config;
+        let max_mb = config
+            .max_attachment_mb
+            .unwrap_or(DEFAULT_MAX_ATTACHMENT_MB);
+        let size = std::fs::metadata(path)?.len();
+        let encoded = encoded_message_size(size);
+        if encoded > max_mb * 1024 * 1024 {
+            bail!(
+                "the file is {:.1} MB ({:.1} MB once encoded for email), over the {} MB attachment limit (max_attachment_mb)",
+                size as f64 / (1024.0 * 1024.0),
+                encoded as f64 / (1024.0 * 1024.0),
+                max_mb
+            );
+        }
+
+        let filename = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "issue.epub".to_string());
+        let attachment = Attachment::new(filename).body(
+            std::fs::read(path)?,
+            ContentType::parse("application/epub+zip").expect("valid content type"),
+        );
+
+        let mut builder = Message::builder()
+            .from(
+                config
+                    .from
+                    .parse()
+                    .with_context(|| format!("Invalid from address '{}'", config.from))?,
+            )
+            .subject(title);
+        for recipient in &self.recipients {
+            builder = builder.to(recipient
+                .parse()
+                .with_context(|| format!("Invalid recipient address '{}'", recipient))?);
+        }
+        let message = builder.multipart(
+            MultiPart::mixed()
+                .singlepart(SinglePart::plain(format!(
+                    "{}\n\nSent by magaziner.",
+                    title
+                )))
+                .singlepart(attachment),
+        )?;
+
+        let security = config.security.unwrap_or_default();
+        let mut transport = match security {
+            Security::Tls => SmtpTransport::relay(&config.smtp_host)?,
+            Security::Starttls => SmtpTransport::starttls_relay(&config.smtp_host)?,
+            Security::None => SmtpTransport::builder_dangerous(&config.smtp_host),
+        }
+        .port(config.smtp_port.unwrap_or(match security {
+            Security::Tls => 465,
+            Security::Starttls => 587,
+            Security::None => 25,
+        }))
+        .timeout(Some(Duration::from_secs(60)));
+        if let Some(username) = &config.username {
+            let password = config
+                .password
+                .clone()
+                .or_else(|| std::env::var("MAGAZINER_SMTP_PASSWORD").ok())
+                .ok_or_else(|| {
+                    anyhow!("no SMTP password; set email.password or MAGAZINER_SMTP_PASSWORD")
+                })?;
+            transport = transport.credentials(Credentials::new(username.clone(), password));
+        }
+
+        progress.verbose(&format!(
+            "Emailing {} via {} to {}",
+            path.display(),
+            config.smtp_host,
+            self.recipients.join(", ")
+        ));
+        transport.build().send(&message)?;
+        Ok(())
+    }
+}
+
+/// Decides whether built books are emailed: `--email-to` addresses, or the
+/// configured recipients when `--email` or `send = true` is set.
+pub fn resolve_delivery(
+    email: bool,
+    email_to: &[String],
+    config: Option<&EmailConfig>,
+) -> Result<Option<Delivery>> {
+    let wanted = email || !email_to.is_empty() || config.and_then(|c| c.send).unwrap_or(false);
+    if !wanted {
+        return Ok(None);
+    }
+    let Some(config) = config else {
+        bail!("Emailing books needs an [email] section with smtp_host and from in the config file");
+    };
+    let recipients = if email_to.is_empty() {
+        config.to.clone()
+    } else {
+        email_to.to_vec()
+    };
+    if recipients.is_empty() {
+        bail!("No email recipients; set email.to in the config file or pass --email-to");
+    }
+    Ok(Some(Delivery {
+        config: config.clone(),
+        recipients,
+    }))
+}
+
+/// Size of the message carrying a `size`-byte attachment: base64 turns every
+/// 3 bytes into 4 and breaks lines every 76 characters, which servers count
+/// against their limit.
+fn encoded_message_size(size: u64) -> u64 {
+    let base64 = size.div_ceil(3) * 4;
+    base64 + base64.div_ceil(76) * 2 + MESSAGE_OVERHEAD
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::Verbosity;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Accepts one SMTP session and returns the message data it received.
+    fn smtp_stand_in(reject_data: bool) -> (u16, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut data = String::new();
+            let mut in_data = false;
+            writer.write_all(b"220 localhost ESMTP\r\n").unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 {
+                if in_data {
+                    if line == ".\r\n" {
+                        in_data = false;
+                        let reply: &[u8] = if reject_data {
+                            b"552 Message size exceeds fixed limit\r\n"
+                        } else {
+                            b"250 OK queued\r\n"
+                        };
+                        writer.write_all(reply).unwrap();
+                    } else {
+                        data.push_str(&line);
+                    }
+                } else {
+                    let command = line.to_ascii_uppercase();
+                    let reply: &[u8] = if command.starts_with("EHLO") {
+                        b"250 localhost\r\n"
+                    } else if command.starts_with("DATA") {
+                        in_data = true;
+                        b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                    } else if command.starts_with("QUIT") {
+                        writer.write_all(b"221 Bye\r\n").unwrap();
+                        break;
+                    } else {
+                        b"250 OK\r\n"
+                    };
+                    writer.write_all(reply).unwrap();
+                }
+                line.clear();
+            }
+            data
+        });
+        (port, handle)
+    }
+
+    fn delivery(port: u16) -> Delivery {
+        Delivery {
+            config: EmailConfig {
+                smtp_host: "127.0.0.1".into(),
+                smtp_port: Some(port),
+                security: Some(Security::None),
+                from: "reader@example.com".into(),
+                ..Default::default()
+            },
+            recipients: vec!["reader@kindle.com".into()],
+        }
+    }
+
+    fn book(name: &str, size: usize) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("magaziner-{}-{}.epub", name, std::process::id()));
+        std::fs::write(&path, vec![b'x'; size]).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_sends_epub_as_attachment() {
+        let (port, server) = smtp_stand_in(false);
+        let path = book("deliver", 1000);
+        delivery(port)
+            .send(&path, "LRB Vol. 47 No. 6", &Progress::new(Verbosity::Quiet))
+            .unwrap();
+
+        let data = server.join().unwrap();
+        assert!(data.contains("To: reader@kindle.com"));
+        assert!(data.contains("Subject: LRB Vol. 47 No. 6"));
+        assert!(data.contains("application/epub+zip"));
+        assert!(data.contains(&format!(
+            "filename=\"{}\"",
+            path.file_name().unwrap().to_string_lossy()
+        )));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_server_rejection_is_a_delivery_error() {
+        let (port, server) = smtp_stand_in(true);
+        let path = book("rejected", 100);
+        let err = delivery(port)
+            .send(&path, "Rejected", &Progress::new(Verbosity::Quiet))
+            .unwrap_err();
+        assert_eq!(err.path, path);
+        assert!(err.to_string().starts_with("Built "));
+        drop(server);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_oversized_book_is_not_sent() {
+        let path = book("oversized", 2 * 1024 * 1024);
+        let mut delivery = delivery(9);
+        delivery.config.max_attachment_mb = Some(1);
+        let err = delivery
+            .send(&path, "Too big", &Progress::new(Verbosity::Quiet))
+            .unwrap_err();
+        assert!(err.to_string().contains("over the 1 MB attachment limit"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_limit_counts_base64_encoding() {
+        // 800 KB is under 1 MB on disk but not once encoded.
+        let path = book("encoded", 800 * 1024);
+        let mut delivery = delivery(9);
+        delivery.config.max_attachment_mb = Some(1);
+        let err = delivery
+            .send(&path, "Nearly", &Progress::new(Verbosity::Quiet))
+            .unwrap_err();
+        assert!(err.to_string().contains("once encoded for email"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_delivery() {
+        let config = EmailConfig {
+            smtp_host: "smtp.example.com".into(),
+            from: "me@example.com".into(),
+            to: vec!["me@kindle.com".into()],
+            ..Default::default()
+        };
+        assert!(
+            resolve_delivery(false, &[], Some(&config))
+                .unwrap()
+                .is_none()
+        );
+        let delivery = resolve_delivery(true, &[], Some(&config)).unwrap().unwrap();
+        assert_eq!(delivery.recipients, ["me@kindle.com"]);
+        let delivery = resolve_delivery(false, &["other@kobo.com".into()], Some(&config))
+            .unwrap()
+            .unwrap();
+        assert_eq!(delivery.recipients, ["other@kobo.com"]);
+        assert!(resolve_delivery(true, &[], None).is_err());
+    }
+}
@@ -0,0 +1,400 @@
+use crate::delivery::DeliveryError;
+use crate::library::file_sha256;
+use crate::progress::Progress;
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Settings from the config file's `[device]` section.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceConfig {
+    /// Mount point to copy to; detected when unset.
+    pub path: Option<PathBuf>,
+    /// Issues of each publication to keep on the device.
+    pub keep: Option<usize>,
+    /// Copy every built issue without needing `--device`.
+    pub copy: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeviceKind {
+    Kobo,
+    Kindle,
+    /// A mount path given explicitly that has no e-reader markers.
+    Generic,
+}
+
+/// A mounted e-reader volume.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub root: PathBuf,
+    pub kind: DeviceKind,
+}
+
+impl Device {
+    pub fn at(root: &Path) -> Result<Self> {
+        if !root.is_dir() {
+            bail!(
+                "Device path '{}' is not a mounted directory",
+                root.display()
+            );
+        }
+        Ok(Device {
+            root: root.to_path_buf(),
+            kind: classify(root),
+        })
+    }
+
+    /// Where books go: anywhere on a Kobo, `documents/` on a Kindle.
+    pub fn books_dir(&self) -> PathBuf {
+        match self.kind {
+            DeviceKind::Kindle => self.root.join("documents"),
+            DeviceKind::Kobo | DeviceKind::Generic => self.root.clone(),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self.kind {
+            DeviceKind::Kobo => "Kobo",
+            DeviceKind::Kindle => "Kindle",
+            DeviceKind::Generic => "device",
+        }
+    }
+}
+
+fn classify(root: &Path) -> DeviceKind {
+    if root.join(".kobo").is_dir() {
+        DeviceKind::Kobo
+    } else if root.join("documents").is_dir() && root.join("system").is_dir() {
+        DeviceKind::Kindle
+    } else {
+        DeviceKind::Generic
+    }
+}
+
+/// Directories that removable volumes are mounted under on this platform.
+fn mount_roots() -> Vec<PathBuf> {
+    if cfg!(target_os = "macos") {
+        return vec![PathBuf::from("/Volumes")];
+    }
+    let mut roots = Vec::new();
+    if let Ok(user) = std::env::var("USER") {
+        roots.push(Path::new("/media").join(&user));
+        roots.push(Path::new("/run/media").join(&user));
+    }
+    roots.push(PathBuf::from("/media"));
+    roots.push(PathBuf::from("/mnt"));
+    roots
+}
+
+/// Kobo and Kindle volumes mounted directly under any of `roots`.
+pub fn detect(roots: &[PathBuf]) -> Vec<Device> {
+    let mut devices: Vec<Device> = Vec::new();
+    for root in roots {
+        let Ok(entries) = fs::read_dir(root) else {
+            continue;
+        };
+        let mut mounts: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect();
+        mounts.sort();
+        for mount in mounts {
+            let kind = classify(&mount);
+            if kind != DeviceKind::Generic && !devices.iter().any(|d| d.root == mount) {
+                devices.push(Device { root: mount, kind });
+            }
+        }
+    }
+    devices
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceTarget {
+    Detect,
+    Path(PathBuf),
+}
+
+/// Copying finished books to an e-reader, with optional retention.
+#[derive(Debug, Clone)]
+pub struct DeviceCopy {
+    pub target: DeviceTarget,
+    pub keep: Option<usize>,
+}
+
+impl DeviceCopy {
+    fn device(&self) -> Result<Device> {
+        match &self.target {
+            DeviceTarget::Path(path) => Device::at(path),
+            DeviceTarget::Detect => {
+                let mut devices = detect(&mount_roots());
+                match devices.len() {
+                    0 => Err(anyhow!(
+                        "No mounted Kobo or Kindle found; connect it or pass --device <PATH>"
+                    )),
+                    1 => Ok(devices.remove(0)),
+                    _ => Err(anyhow!(
+                        "Several e-readers are mounted ({}); pass --device <PATH> to pick one",
+                        devices
+                            .iter()
+                            .map(|d| d.root.display().to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )),
+                }
+            }
+        }
+    }
+
+    /// Copies `path` into the publication's folder on the device, then
+    /// removes that folder's oldest issues beyond the retention count.
+    pub fn copy(
+        &self,
+        path: &Path,
+        folder: &str,
+        date: Option<NaiveDate>,
+        progress: &Progress,
+    ) -> Result<(), DeliveryError> {
+        self.try_copy(path, folder, date, progress)
+            .map_err(|source| DeliveryError {
+                path: path.to_path_buf(),
+                source,
+            })
+    }
+
+    fn try_copy(
+        &self,
+        path: &Path,
+        folder: &str,
+        date: Option<NaiveDate>,
+        progress: &Progress,
+    ) -> Result<()> {
+        let device = self.device()?;
+        let dir = device.books_dir().join(folder);
+        let context = || format!("copying to {} at {}", device.name(), dir.display());
+
+        let (dest, copied) = copy_book(path, &dir, date).with_context(context)?;
+        if copied {
+            progress.info(&format!("Copied to {}: {}", device.name(), dest.display()));
+        } else {
+            progress.info(&format!("Already on {}: {}", device.name(), dest.display()));
+        }
+
+        if let Some(keep) = self.keep {
+            for removed in prune(&dir, keep, &dest).with_context(context)? {
+                progress.info(&format!(
+                    "Removed from {}: {}",
+                    device.name(),
+                    removed.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Copies `path` into `dir` unless an identical file is already there.
+/// The copy is dated with the issue's cover date so that retention keeps
+/// the newest issues rather than the most recently copied ones.
+fn copy_book(path: &Path, dir: &Path, date: Option<NaiveDate>) -> Result<(PathBuf, bool)> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("'{}' has no file name", path.display()))?;
+    let dest = dir.join(name);
+    if dest.is_file()
+        && fs::metadata(&dest)?.len() == fs::metadata(path)?.len()
+        && file_sha256(&dest)? == file_sha256(path)?
+    {
+        return Ok((dest, false));
+    }
+
+    fs::create_dir_all(dir)?;
+    fs::copy(path, &dest)?;
+    if let Some(date) = date {
+        let noon = date.and_hms_opt(12, 0, 0).expect("valid time").and_utc();
+        // Some filesystems can't store every date; the copy itself still succeeded.
+        let _ = fs::File::options()
+            .write(true)
+            .open(&dest)
+            .and_then(|file| file.set_modified(SystemTime::from(noon)));
+    }
+    Ok((dest, true))
+}
+
+/// Deletes all but the `keep` newest EPUBs in `dir`, never touching `current`.
+fn prune(dir: &Path, keep: usize, current: &Path) -> Result<Vec<PathBuf>> {
+    let mut books: Vec<(SystemTime, PathBuf)> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_epub = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().to_lowercase().ends_with(".epub"));
+        if is_epub && path.is_file() {
+            books.push((fs::metadata(&path)?.modified()?, path));
+        }
+    }
+    books.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.cmp(&a.1)));
+
+    let mut removed = Vec::new();
+    for (_, path) in books.into_iter().skip(keep) {
+        if path != current {
+            fs::remove_file(&path)?;
+            removed.push(path);
+        }
+    }
+    Ok(removed)
+}
+
+/// Decides whether built books are copied to a device: `--device`, or
+/// `copy = true` in the config, with the CLI's path and count winning.
+pub fn resolve_device(
+    device: Option<&Option<PathBuf>>,
+    keep: Option<usize>,
+    config: Option<&DeviceConfig>,
+) -> Option<DeviceCopy> {
+    let copy = config.and_then(|c| c.copy).unwrap_or(false);
+    let configured_path = config.and_then(|c| c.path.clone());
+    let path = match device {
+        Some(Some(path)) => Some(path.clone()),
+        Some(None) => configured_path,
+        None if copy => configured_path,
+        None => return None,
+    };
+    Some(DeviceCopy {
+        target: path.map_or(DeviceTarget::Detect, DeviceTarget::Path),
+        keep: keep.or(config.and_then(|c| c.keep)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::Verbosity;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("magaziner-device-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn test_detects_kobo_and_kindle_volumes() {
+        let root = temp_root("detect");
+        fs::create_dir_all(root.join("KOBOeReader/.kobo")).unwrap();
+        fs::create_dir_all(root.join("Kindle/documents")).unwrap();
+        fs::create_dir_all(root.join("Kindle/system")).unwrap();
+        fs::create_dir_all(root.join("USB_STICK")).unwrap();
+
+        let devices = detect(&[root.clone(), root.join("missing")]);
+        assert_eq!(
+            devices,
+            [
+                Device {
+                    root: root.join("KOBOeReader"),
+                    kind: DeviceKind::Kobo
+                },
+                Device {
+                    root: root.join("Kindle"),
+                    kind: DeviceKind::Kindle
+                },
+            ]
+        );
+        assert_eq!(devices[1].books_dir(), root.join("Kindle/documents"));
+        assert_eq!(
+            Device::at(&root.join("USB_STICK")).unwrap().kind,
+            DeviceKind::Generic
+        );
+        assert!(Device::at(&root.join("missing")).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_copy_skips_identical_and_prunes_oldest() {
+        let root = temp_root("copy");
+        let device = root.join("KOBOeReader");
+        fs::create_dir_all(device.join(".kobo")).unwrap();
+        let copy = DeviceCopy {
+            target: DeviceTarget::Path(device.clone()),
+            keep: Some(2),
+        };
+        let progress = Progress::new(Verbosity::Quiet);
+        let folder = device.join("London Review of Books");
+
+        // Copied out of order: the retained issues are the newest by date.
+        for (number, month, day) in [(3, 2, 6), (1, 1, 9), (2, 1, 23)] {
+            let book = root.join(format!("LRB-v47-n0{}.epub", number));
+            fs::write(&book, format!("issue {}", number)).unwrap();
+            let date = NaiveDate::from_ymd_opt(2025, month, day);
+            copy.copy(&book, "London Review of Books", date, &progress)
+                .unwrap();
+        }
+        let mut names: Vec<String> = fs::read_dir(&folder)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["LRB-v47-n02.epub", "LRB-v47-n03.epub"]);
+
+        let (dest, copied) = copy_book(&root.join("LRB-v47-n03.epub"), &folder, None).unwrap();
+        assert!(!copied);
+        assert_eq!(fs::read_to_string(dest).unwrap(), "issue 3");
+        fs::write(root.join("LRB-v47-n03.epub"), "issue 3, rebuilt").unwrap();
+        assert!(
+            copy_book(&root.join("LRB-v47-n03.epub"), &folder, None)
+                .unwrap()
+                .1
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_missing_device_is_a_delivery_error() {
+        let copy = DeviceCopy {
+            target: DeviceTarget::Path(PathBuf::from("/nonexistent/KOBOeReader")),
+            keep: None,
+        };
+        let err = copy
+            .copy(
+                Path::new("LRB.epub"),
+                "LRB",
+                None,
+                &Progress::new(Verbosity::Quiet),
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("is not a mounted directory"));
+    }
+
+    #[test]
+    fn test_resolve_device() {
+        let config = DeviceConfig {
+            path: Some(PathBuf::from("/media/KOBOeReader")),
+            keep: Some(4),
+            copy: None,
+        };
+        assert!(resolve_device(None, None, Some(&config)).is_none());
+        let copy = resolve_device(Some(&None), None, Some(&config)).unwrap();
+        assert_eq!(
+            copy.target,
+            DeviceTarget::Path(PathBuf::from("/media/KOBOeReader"))
+        );
+        assert_eq!(copy.keep, Some(4));
+        let copy =
+            resolve_device(Some(&Some(PathBuf::from("/Volumes/Kindle"))), Some(1), None).unwrap();
+        assert_eq!(
+            copy.target,
+            DeviceTarget::Path(PathBuf::from("/Volumes/Kindle"))
+        );
+        assert_eq!(copy.keep, Some(1));
+        assert_eq!(
+            resolve_device(Some(&None), None, None).unwrap().target,
+            DeviceTarget::Detect
+        );
+    }
+}
@@ -1,54 +1,106 @@
-use crate::fetch::download_image;
+use crate::adapter::{ArticleData, IssueData};
+use crate::fonts::FontFile;
+use crate::kepub;
+use crate::output::{IssueModel, OutputBackend};
 use crate::progress::Progress;
 use anyhow::Result;
-use epub_builder::{EpubBuilder, EpubContent, ReferenceType, ZipLibrary};
+use chrono::{TimeZone, Utc};
+use epub_builder::{EpubBuilder, EpubContent, MetadataOpf, ReferenceType, ZipLibrary};
 use regex::Regex;
-use reqwest::blocking::Client;
-use std::fs::{File, remove_file};
-use std::path::PathBuf;
+use std::path::Path;
 
+/// Writes an EPUB, or a Kobo KEPUB when `kepub` is set.
+pub struct EpubBackend {
+    pub kepub: bool,
+}
+
+impl OutputBackend for EpubBackend {
+    fn file_name(&self, stem: &str) -> String {
+        if self.kepub {
+            format!("{}.kepub.epub", stem)
+        } else {
+            format!("{}.epub", stem)
+        }
+    }
+
+    fn write(&self, model: &IssueModel, path: &Path, progress: &mut Progress) -> Result<()> {
+        let book = build_epub(
+            progress,
+            &model.issue,
+            &model.articles,
+            model.cover.as_deref(),
+            &model.fonts,
+        )?;
+        let book = if self.kepub {
+            progress.next("Converting to KEPUB and saving…");
+            kepub::convert(&book, progress)?
+        } else {
+            progress.next("Saving EPUB…");
+            book
+        };
+        std::fs::write(path, book)?;
+        Ok(())
+    }
+}
+
+/// Assembles the issue's EPUB in memory.
 pub fn build_epub(
     progress: &mut Progress,
-    title: &str,
-    publication_name: &str,
-    filename: &str,
-    output: &PathBuf,
-    articles: Vec<(String, String)>,
-    css_sheet: &str,
-    image_uri: &str,
-    client: &Client,
-) -> Result<()> {
+    issue: &IssueData,
+    articles: &[ArticleData],
+    cover: Option<&[u8]>,
+    fonts: &[FontFile],
+) -> Result<Vec<u8>> {
+    let title = issue.title.as_str();
+    let publication_name = issue.publication_name.as_str();
+
     let mut epub = EpubBuilder::new(ZipLibrary::new()?)?;
     epub.metadata("title", title)?
         .metadata("author", publication_name)?;
 
-    progress.next("Downloading cover…");
-    if !image_uri.trim().is_empty() && image_uri.starts_with("http") {
-        let cover_path = "cover.jpg";
-        download_image(client, image_uri, cover_path, progress)?;
-
-        {
-            let mut cover_file = File::open(cover_path)?;
-            epub.add_cover_image("cover.jpg", &mut cover_file, "image/jpeg")?;
-        }
+    if let Some(date) = issue.identity.date()
+        && let Some(midnight) = date.and_hms_opt(0, 0, 0)
+    {
+        epub.set_publication_date(Utc.from_utc_datetime(&midnight));
+    }
+    // Calibre's series metadata, understood by most e-reader library views.
+    if let Some(index) = issue.identity.series_index() {
+        epub.add_metadata_opf(MetadataOpf {
+            name: "calibre:series".to_string(),
+            content: publication_name.to_string(),
+        });
+        epub.add_metadata_opf(MetadataOpf {
+            name: "calibre:series_index".to_string(),
+            content: format!("{:.2}", index),
+        });
+    }
 
-        remove_file(cover_path)?;
+    if let Some(cover) = cover {
+        epub.add_cover_image("cover.jpg", cover, "image/jpeg")?;
     }
 
     progress.next("Building EPUB…");
-    epub.stylesheet(css_sheet.as_bytes())?;
+    epub.stylesheet(issue.css.as_bytes())?;
+    for font in fonts {
+        epub.add_resource(&font.file_name, font.data.as_slice(), font.media_type)?;
+    }
 
     let title_page = format!(
         r#"<?xml version="1.0" encoding="utf-8"?>
 <!DOCTYPE html>
 <html xmlns="http://www.w3.org/1999/xhtml">
-  <head><title>{}</title></head>
-  <body style="text-align: center; margin-top: 40%;">
+  <head>
+    <title>{}</title>
+    <link rel="stylesheet" type="text/css" href="stylesheet.css" />
+  </head>
+  <body class="title-page">
     <h1>{}</h1>
     <h3>{}</h3>
   </body>
 </html>"#,
-        title, title, publication_name
+        escape(title),
+        escape(title),
+        escape(publication_name)
     );
 
     epub.add_content(
@@ -59,10 +111,11 @@ pub fn build_epub(
 
     let toc_html = {
         let mut list_items = String::new();
-        for (i, (article_title, _)) in articles.iter().enumerate() {
+        for (i, article) in articles.iter().enumerate() {
             list_items.push_str(&format!(
                 r#"<li><a href="article{}.xhtml">{}</a></li>"#,
-                i, article_title
+                i,
+                escape(&article.title)
             ));
         }
 
@@ -70,7 +123,10 @@ pub fn build_epub(
             r#"<?xml version="1.0" encoding="utf-8"?>
 <!DOCTYPE html>
 <html xmlns="http://www.w3.org/1999/xhtml">
-  <head><title>Table of Contents</title></head>
+  <head>
+    <title>Table of Contents</title>
+    <link rel="stylesheet" type="text/css" href="stylesheet.css" />
+  </head>
   <body>
     <h2>Table of Contents</h2>
     <ol>
@@ -88,10 +144,11 @@ pub fn build_epub(
             .reftype(ReferenceType::Toc),
     )?;
 
-    for (i, (article_title, body)) in articles.into_iter().enumerate() {
+    for (i, article) in articles.iter().enumerate() {
+        let article_title = &article.title;
         progress.verbose(&format!("Adding article: {}", article_title));
         let filename = format!("article{}.xhtml", i);
-        let safe_body = sanitize_html_for_epub(&body);
+        let safe_body = sanitize_html_for_epub(&article.body);
 
         let xhtml = format!(
             r#"<?xml version="1.0" encoding="utf-8"?>
@@ -99,45 +156,50 @@ pub fn build_epub(
 <html xmlns="http://www.w3.org/1999/xhtml">
   <head>
     <title>{}</title>
-    <style>
-      body {{
-        font-family: serif;
-        margin: 2em;
-      }}
-      h1.article-title {{
-        font-size: 2em;
-        text-align: center;
-        margin-top: 1em;
-        margin-bottom: 1.5em;
-      }}
-    </style>
+    <link rel="stylesheet" type="text/css" href="stylesheet.css" />
   </head>
   <body>
     <h1 class="article-title">{}</h1>
     {}
   </body>
 </html>"#,
-            article_title, article_title, safe_body
+            escape(article_title),
+            escape(article_title),
+            safe_body
         );
 
         epub.add_content(
             EpubContent::new(filename, xhtml.as_bytes())
-                .title(&article_title)
+                .title(article_title)
                 .reftype(ReferenceType::Text),
         )?;
     }
 
-    progress.next("Saving EPUB…");
-    let output_path = output.join(format!("{}.epub", filename));
-    let file = File::create(&output_path)?;
-    epub.generate(file)?;
+    let mut book = Vec::new();
+    epub.generate(&mut book)?;
+    Ok(book)
+}
 
-    progress.done(output_path.display());
+/// Escapes scraped text, such as titles, for the XHTML templates.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
 
-    Ok(())
+/// Makes article HTML safe to embed: XHTML-compatible entities and void
+/// elements, with iframes and remote images removed.
+pub fn sanitize_html_for_epub(html: &str) -> String {
+    let img_regex = Regex::new(r"(?is)<img[^>]*>").unwrap();
+    img_regex
+        .replace_all(&sanitize_markup(html), "")
+        .to_string()
 }
 
-fn sanitize_html_for_epub(html: &str) -> String {
+/// `sanitize_html_for_epub` without removing images, for callers that have
+/// already replaced them.
+pub fn sanitize_markup(html: &str) -> String {
     let body = html
         .replace("&nbsp;", "&#160;")
         .replace("&mdash;", "&#8212;")
@@ -146,16 +208,15 @@ fn sanitize_html_for_epub(html: &str) -> String {
         .replace("&rsquo;", "&#8217;")
         .replace("&ldquo;", "&#8220;")
         .replace("&rdquo;", "&#8221;")
-        .replace("&hellip;", "&#8230;")
-        .replace("<br>", "<br />")
-        .replace("<hr>", "<hr />")
-        .replace("<img ", "<img ")
-        .replace("<br/>", "<br />")
-        .replace("<hr/>", "<hr />");
-
+        .replace("&hellip;", "&#8230;");
+
+    // Adapters hand over HTML5 serializations, where void elements such as
+    // `<br class="x">` or `<source srcset="…">` are never closed.
+    let void_regex = Regex::new(
+        r"(?i)<(area|base|br|col|embed|hr|img|input|link|meta|param|source|track|wbr)\b([^>]*?)\s*/?>",
+    )
+    .unwrap();
+    let body = void_regex.replace_all(&body, "<$1$2 />");
     let iframe_regex = Regex::new(r"(?is)<iframe.*?</iframe>").unwrap();
-    let stripped = iframe_regex.replace_all(&body, "").into_owned();
-
-    let img_regex = Regex::new(r"(?is)<img[^>]*>").unwrap();
-    img_regex.replace_all(&stripped, "").to_string()
+    iframe_regex.replace_all(&body, "").into_owned()
 }
@@ -1,20 +1,17 @@
 use crate::progress::Progress;
 use anyhow::Result;
 use reqwest::blocking::Client;
-use reqwest::header::{COOKIE, HeaderMap, HeaderValue};
+use reqwest::cookie::Jar;
 use scraper::Html;
-use std::fs::File;
-use std::io::copy;
+use std::sync::Arc;
 use std::{thread, time::Duration};
 
-pub fn make_client(cookie: Option<&str>) -> Result<Client> {
-    let mut headers = HeaderMap::new();
-    if let Some(cookie_str) = cookie
-        && !cookie_str.is_empty()
-    {
-        headers.insert(COOKIE, HeaderValue::from_str(cookie_str)?);
+pub fn make_client(jar: Option<Arc<Jar>>) -> Result<Client> {
+    let mut builder = Client::builder();
+    if let Some(jar) = jar {
+        builder = builder.cookie_provider(jar);
     }
-    Ok(Client::builder().default_headers(headers).build()?)
+    Ok(builder.build()?)
 }
 
 pub fn fetch_html_body(
@@ -35,21 +32,16 @@ pub fn fetch_html_raw(
 ) -> Result<String> {
     progress.verbose(&format!("GET {}", url));
     thread::sleep(Duration::from_millis(*delay));
-    let body = client.get(url).send()?.text()?;
+    // Error and login pages must not end up in the page cache as articles.
+    let body = client.get(url).send()?.error_for_status()?.text()?;
+    progress.add_bytes(body.len());
     progress.verbose(&format!("{} bytes received", body.len()));
     Ok(body)
 }
 
-pub fn download_image(
-    client: &Client,
-    url: &str,
-    output_path: &str,
-    progress: &Progress,
-) -> Result<()> {
+pub fn fetch_bytes(client: &Client, url: &str, progress: &Progress) -> Result<Vec<u8>> {
     progress.verbose(&format!("Downloading image: {}", url));
-    let response = client.get(url).send()?;
-    let mut file = File::create(output_path)?;
-    let mut content = std::io::Cursor::new(response.bytes()?);
-    copy(&mut content, &mut file)?;
-    Ok(())
+    let bytes = client.get(url).send()?.error_for_status()?.bytes()?;
+    progress.add_bytes(bytes.len());
+    Ok(bytes.to_vec())
 }
@@ -0,0 +1,611 @@
+use crate::adapter::{ArticleData, IssueData};
+use crate::style::StyleConfig;
+use crate::template::slugify;
+use anyhow::{Context, Result, anyhow};
+use flate2::read::ZlibDecoder;
+use scraper::Html;
+use std::collections::BTreeSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use ttf_parser::gsub::{SingleSubstitution, SubstitutionSubtable};
+use ttf_parser::name_id;
+use ttf_parser::{Face, GlyphId};
+
+const TRUETYPE: u32 = 0x0001_0000;
+const OPENTYPE: u32 = u32::from_be_bytes(*b"OTTO");
+const APPLE_TRUETYPE: u32 = u32::from_be_bytes(*b"true");
+
+/// A font to embed in the EPUB. WOFF files are unpacked on load, since
+/// EPUB 2 readers only know TrueType and OpenType.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbeddedFont {
+    pub family: String,
+    pub weight: u16,
+    pub italic: bool,
+    /// Path inside the book, relative to `stylesheet.css`.
+    pub file_name: String,
+    /// The font as an sfnt (TrueType or OpenType) file.
+    data: Vec<u8>,
+}
+
+/// Fonts from `--font` or the `[style]` section.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FontOptions {
+    pub fonts: Vec<EmbeddedFont>,
+    /// Keep only the glyphs the issue uses.
+    pub subset: bool,
+}
+
+/// A font file ready to be added to the book.
+pub struct FontFile {
+    pub file_name: String,
+    pub media_type: &'static str,
+    pub data: Vec<u8>,
+}
+
+/// Loads the fonts from `--font`, or from the config file's `[style]` section
+/// when none are given, so a bad file is reported before anything is fetched.
+pub fn resolve_fonts(
+    paths: &[PathBuf],
+    subset: bool,
+    config: Option<&StyleConfig>,
+) -> Result<FontOptions> {
+    let paths = if paths.is_empty() {
+        config.map(|c| c.fonts.as_slice()).unwrap_or_default()
+    } else {
+        paths
+    };
+    let mut fonts: Vec<EmbeddedFont> = Vec::new();
+    for path in paths {
+        let mut font = load_font(path)?;
+        // Two files with the same stem, e.g. from different folders.
+        let stem = font.file_name.clone();
+        let mut n = 1;
+        while fonts.iter().any(|f| f.file_name == font.file_name) {
+            n += 1;
+            font.file_name = stem.replacen('.', &format!("-{}.", n), 1);
+        }
+        fonts.push(font);
+    }
+    Ok(FontOptions {
+        fonts,
+        subset: subset || config.and_then(|c| c.subset_fonts).unwrap_or(false),
+    })
+}
+
+/// Reads a TTF, OTF or WOFF file and its family, weight and style.
+pub fn load_font(path: &Path) -> Result<EmbeddedFont> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to read font {}", path.display()))?;
+    let data = match bytes.get(..4) {
+        Some(b"wOFF") => {
+            unpack_woff(&bytes).with_context(|| format!("Failed to unpack {}", path.display()))?
+        }
+        Some(b"wOF2") => {
+            return Err(anyhow!(
+                "WOFF2 fonts aren't supported; convert {} to TTF or WOFF first",
+                path.display()
+            ));
+        }
+        _ => bytes,
+    };
+    let face = Face::parse(&data, 0).map_err(|_| {
+        anyhow!(
+            "{} is not a TrueType, OpenType or WOFF font",
+            path.display()
+        )
+    })?;
+
+    let names: Vec<_> = face.names().into_iter().collect();
+    let family = [name_id::TYPOGRAPHIC_FAMILY, name_id::FAMILY]
+        .iter()
+        .find_map(|id| {
+            names
+                .iter()
+                .filter(|n| n.name_id == *id && n.is_unicode())
+                .find_map(|n| n.to_string())
+        })
+        .map(|family| family.replace(['"', '\\'], ""))
+        .ok_or_else(|| anyhow!("{} has no font family name", path.display()))?;
+    let extension = if face.tables().glyf.is_some() {
+        "ttf"
+    } else {
+        "otf"
+    };
+    let stem = path
+        .file_stem()
+        .map(|s| slugify(&s.to_string_lossy()))
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| slugify(&family));
+
+    Ok(EmbeddedFont {
+        weight: face.weight().to_number(),
+        italic: face.is_italic(),
+        file_name: format!("fonts/{}.{}", stem, extension),
+        family,
+        data,
+    })
+}
+
+impl FontOptions {
+    /// `@font-face` rules for every font, and a `body` rule setting the
+    /// first font's family.
+    pub fn css(&self) -> String {
+        let mut css = String::new();
+        for font in &self.fonts {
+            css.push_str(&format!(
+                "@font-face {{ font-family: \"{}\"; font-weight: {}; font-style: {}; src: url(\"{}\"); }}\n",
+                font.family,
+                font.weight,
+                if font.italic { "italic" } else { "normal" },
+                font.file_name
+            ));
+        }
+        if let Some(font) = self.fonts.first() {
+            css.push_str(&format!(
+                "body {{ font-family: \"{}\", serif; }}\n",
+                font.family
+            ));
+        }
+        css
+    }
+
+    /// The font files for the book, subset to the issue's characters if asked.
+    pub fn files(&self, issue: &IssueData, articles: &[ArticleData]) -> Result<Vec<FontFile>> {
+        let chars = self.subset.then(|| used_chars(issue, articles));
+        self.fonts
+            .iter()
+            .map(|font| {
+                let data = match &chars {
+                    Some(chars) => subset(&font.data, chars)
+                        .with_context(|| format!("Failed to subset {}", font.family))?,
+                    None => font.data.clone(),
+                };
+                Ok(FontFile {
+                    media_type: if font.file_name.ends_with(".ttf") {
+                        "application/x-font-truetype"
+                    } else {
+                        "application/vnd.ms-opentype"
+                    },
+                    file_name: font.file_name.clone(),
+                    data,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Every character the book can show: the issue's text plus printable ASCII,
+/// which covers the contents page and anything the stylesheet generates.
+fn used_chars(issue: &IssueData, articles: &[ArticleData]) -> BTreeSet<char> {
+    let mut chars: BTreeSet<char> = (' '..='~').collect();
+    chars.extend(issue.title.chars());
+    chars.extend(issue.publication_name.chars());
+    for article in articles {
+        chars.extend(article.title.chars());
+        chars.extend(article.author.iter().flat_map(|a| a.chars()));
+        let body = Html::parse_fragment(&article.body);
+        chars.extend(body.root_element().text().flat_map(str::chars));
+    }
+    chars
+}
+
+/// Empties every glyph outside `chars` (and the components and `GSUB`
+/// substitutes of the glyphs that stay), keeping glyph ids as they are so
+/// `cmap`, metrics and layout tables still line up. Fonts with CFF outlines
+/// are returned whole.
+fn subset(sfnt: &[u8], chars: &BTreeSet<char>) -> Result<Vec<u8>> {
+    let face = Face::parse(sfnt, 0)?;
+    let (flavor, tables) = read_tables(sfnt)?;
+    let table = |tag: &[u8; 4]| tables.iter().find(|(t, _)| t == tag).map(|(_, d)| *d);
+    let (Some(head), Some(loca), Some(glyf)) = (table(b"head"), table(b"loca"), table(b"glyf"))
+    else {
+        return Ok(sfnt.to_vec());
+    };
+
+    let long = read_u16(head, 50)? == 1;
+    let count = face.number_of_glyphs() as usize;
+    let mut offsets = Vec::with_capacity(count + 1);
+    for i in 0..=count {
+        offsets.push(if long {
+            read_u32(loca, i * 4)? as usize
+        } else {
+            read_u16(loca, i * 2)? as usize * 2
+        });
+    }
+    let glyph = |id: usize| -> Result<&[u8]> {
+        glyf.get(offsets[id]..offsets[id + 1])
+            .ok_or_else(|| anyhow!("glyph {} is outside the glyf table", id))
+    };
+
+    let mut keep = BTreeSet::new();
+    let mut queue: Vec<u16> = vec![0];
+    queue.extend(
+        chars
+            .iter()
+            .filter_map(|c| face.glyph_index(*c))
+            .map(|g| g.0),
+    );
+    loop {
+        while let Some(id) = queue.pop() {
+            if (id as usize) < count && keep.insert(id) {
+                queue.extend(components(glyph(id as usize)?));
+            }
+        }
+        queue = substitutes(&face, &keep);
+        queue.retain(|id| !keep.contains(id));
+        if queue.is_empty() {
+            break;
+        }
+    }
+
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::with_capacity((count + 1) * 4);
+    for id in 0..count {
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+        if keep.contains(&(id as u16)) {
+            new_glyf.extend_from_slice(glyph(id)?);
+            while new_glyf.len() % 4 != 0 {
+                new_glyf.push(0);
+            }
+        }
+    }
+    new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+    let mut new_head = head.to_vec();
+    new_head[50..52].copy_from_slice(&1u16.to_be_bytes());
+    // Glyph names are only for tools; version 3 of `post` goes without.
+    let mut new_post = table(b"post").map(|post| post[..post.len().min(32)].to_vec());
+    if let Some(post) = &mut new_post
+        && post.len() == 32
+    {
+        post[..4].copy_from_slice(&0x0003_0000u32.to_be_bytes());
+    }
+
+    let tables = tables
+        .iter()
+        .filter(|(tag, _)| tag != b"DSIG")
+        .map(|(tag, data)| {
+            let data = match tag {
+                b"glyf" => std::mem::take(&mut new_glyf),
+                b"loca" => std::mem::take(&mut new_loca),
+                b"head" => std::mem::take(&mut new_head),
+                b"post" => new_post.take().unwrap_or_default(),
+                _ => data.to_vec(),
+            };
+            (*tag, data)
+        })
+        .collect();
+    Ok(write_sfnt(flavor, tables))
+}
+
+/// Glyph ids `GSUB` can put in place of the glyphs in `keep`: ligatures
+/// whose parts are all kept, alternates, small caps and the like. Lookups
+/// are taken on their own rather than through the features and contexts
+/// that call them, which keeps a few glyphs too many but never too few.
+fn substitutes(face: &Face, keep: &BTreeSet<u16>) -> Vec<u16> {
+    let Some(gsub) = face.tables().gsub else {
+        return Vec::new();
+    };
+    let mut ids = Vec::new();
+    for lookup in gsub.lookups {
+        for subtable in lookup.subtables.into_iter::<SubstitutionSubtable>() {
+            let coverage = subtable.coverage();
+            for &id in keep {
+                let Some(index) = coverage.get(GlyphId(id)) else {
+                    continue;
+                };
+                match subtable {
+                    SubstitutionSubtable::Single(single) => match single {
+                        SingleSubstitution::Format1 { delta, .. } => {
+                            ids.push(id.wrapping_add(delta as u16));
+                        }
+                        SingleSubstitution::Format2 { substitutes, .. } => {
+                            ids.extend(substitutes.get(index).map(|g| g.0));
+                        }
+                    },
+                    SubstitutionSubtable::Multiple(multiple) => {
+                        let sequence = multiple.sequences.get(index);
+                        ids.extend(
+                            sequence
+                                .into_iter()
+                                .flat_map(|s| s.substitutes)
+                                .map(|g| g.0),
+                        );
+                    }
+                    SubstitutionSubtable::Alternate(alternate) => {
+                        let set = alternate.alternate_sets.get(index);
+                        ids.extend(set.into_iter().flat_map(|s| s.alternates).map(|g| g.0));
+                    }
+                    SubstitutionSubtable::Ligature(ligature) => {
+                        let set = ligature.ligature_sets.get(index);
+                        ids.extend(
+                            set.into_iter()
+                                .flatten()
+                                .filter(|l| l.components.into_iter().all(|g| keep.contains(&g.0)))
+                                .map(|l| l.glyph.0),
+                        );
+                    }
+                    SubstitutionSubtable::ReverseChainSingle(reverse) => {
+                        ids.extend(reverse.substitutes.get(index).map(|g| g.0));
+                    }
+                    // These only call other lookups, which are visited anyway.
+                    SubstitutionSubtable::Context(_) | SubstitutionSubtable::ChainContext(_) => {}
+                }
+            }
+        }
+    }
+    ids
+}
+
+/// Glyph ids a composite glyph is built from.
+fn components(glyph: &[u8]) -> Vec<u16> {
+    const ARGS_ARE_WORDS: u16 = 0x0001;
+    const HAVE_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const HAVE_XY_SCALE: u16 = 0x0040;
+    const HAVE_TWO_BY_TWO: u16 = 0x0080;
+
+    let mut ids = Vec::new();
+    if read_u16(glyph, 0).map_or(true, |contours| contours as i16 >= 0) {
+        return ids;
+    }
+    let mut pos = 10;
+    while let (Ok(flags), Ok(id)) = (read_u16(glyph, pos), read_u16(glyph, pos + 2)) {
+        ids.push(id);
+        pos += 4 + if flags & ARGS_ARE_WORDS != 0 { 4 } else { 2 };
+        pos += if flags & HAVE_SCALE != 0 {
+            2
+        } else if flags & HAVE_XY_SCALE != 0 {
+            4
+        } else if flags & HAVE_TWO_BY_TWO != 0 {
+            8
+        } else {
+            0
+        };
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+    ids
+}
+
+/// Turns a WOFF 1.0 file back into the sfnt it was made from.
+fn unpack_woff(woff: &[u8]) -> Result<Vec<u8>> {
+    let flavor = read_u32(woff, 4)?;
+    let count = read_u16(woff, 12)? as usize;
+    let mut tables = Vec::with_capacity(count);
+    for i in 0..count {
+        let entry = 44 + i * 20;
+        let tag: [u8; 4] = woff
+            .get(entry..entry + 4)
+            .and_then(|t| t.try_into().ok())
+            .ok_or_else(|| anyhow!("truncated table directory"))?;
+        let offset = read_u32(woff, entry + 4)? as usize;
+        let compressed = read_u32(woff, entry + 8)? as usize;
+        let length = read_u32(woff, entry + 12)? as usize;
+        let raw = woff.get(offset..offset + compressed).ok_or_else(|| {
+            anyhow!(
+                "table {} is outside the file",
+                String::from_utf8_lossy(&tag)
+            )
+        })?;
+        let data = if compressed < length {
+            let mut data = Vec::with_capacity(length);
+            ZlibDecoder::new(raw).read_to_end(&mut data)?;
+            data
+        } else {
+            raw.to_vec()
+        };
+        tables.push((tag, data));
+    }
+    Ok(write_sfnt(flavor, tables))
+}
+
+/// An sfnt's flavor and its tables by tag.
+type Tables<'a> = (u32, Vec<([u8; 4], &'a [u8])>);
+
+fn read_tables(sfnt: &[u8]) -> Result<Tables<'_>> {
+    let flavor = read_u32(sfnt, 0)?;
+    if ![TRUETYPE, OPENTYPE, APPLE_TRUETYPE].contains(&flavor) {
+        return Err(anyhow!("not a TrueType or OpenType font"));
+    }
+    let count = read_u16(sfnt, 4)? as usize;
+    let mut tables = Vec::with_capacity(count);
+    for i in 0..count {
+        let record = 12 + i * 16;
+        let tag: [u8; 4] = sfnt
+            .get(record..record + 4)
+            .and_then(|t| t.try_into().ok())
+            .ok_or_else(|| anyhow!("truncated table directory"))?;
+        let offset = read_u32(sfnt, record + 8)? as usize;
+        let length = read_u32(sfnt, record + 12)? as usize;
+        let data = sfnt.get(offset..offset + length).ok_or_else(|| {
+            anyhow!(
+                "table {} is outside the file",
+                String::from_utf8_lossy(&tag)
+            )
+        })?;
+        tables.push((tag, data));
+    }
+    Ok((flavor, tables))
+}
+
+/// Assembles an sfnt file, recomputing the table checksums and the `head`
+/// table's whole-font checksum adjustment.
+fn write_sfnt(flavor: u32, mut tables: Vec<([u8; 4], Vec<u8>)>) -> Vec<u8> {
+    tables.sort_by_key(|(tag, _)| *tag);
+    if let Some((_, head)) = tables.iter_mut().find(|(tag, _)| tag == b"head")
+        && head.len() >= 12
+    {
+        head[8..12].fill(0);
+    }
+
+    let count = tables.len() as u16;
+    let selector = 15 - count.max(1).leading_zeros() as u16;
+    let search_range = 16 * (1 << selector);
+    let mut font = Vec::new();
+    font.extend_from_slice(&flavor.to_be_bytes());
+    for value in [count, search_range, selector, count * 16 - search_range] {
+        font.extend_from_slice(&value.to_be_bytes());
+    }
+
+    let mut offset = 12 + 16 * tables.len();
+    let mut head_offset = None;
+    for (tag, data) in &tables {
+        if tag == b"head" {
+            head_offset = Some(offset);
+        }
+        font.extend_from_slice(tag);
+        font.extend_from_slice(&checksum(data).to_be_bytes());
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        offset += data.len().next_multiple_of(4);
+    }
+    for (_, data) in &tables {
+        font.extend_from_slice(data);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+
+    if let Some(head) = head_offset {
+        let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&font));
+        font[head + 8..head + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    font
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+fn read_u16(data: &[u8], at: usize) -> Result<u16> {
+    data.get(at..at + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| anyhow!("font data ends early"))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32> {
+    data.get(at..at + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| anyhow!("font data ends early"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IssueIdentity;
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    const SERIF: &str = "assets/fonts/DejaVuSerif.ttf";
+    const BOLD_ITALIC: &str = "assets/fonts/DejaVuSerif-BoldItalic.ttf";
+
+    /// Packs an sfnt as WOFF 1.0, compressing every table.
+    fn woff(sfnt: &[u8]) -> Vec<u8> {
+        let (flavor, tables) = read_tables(sfnt).unwrap();
+        let mut body = Vec::new();
+        let mut directory = Vec::new();
+        let mut offset = 44 + 20 * tables.len();
+        for (tag, data) in &tables {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data).unwrap();
+            let mut compressed = encoder.finish().unwrap();
+            if compressed.len() >= data.len() {
+                compressed = data.to_vec();
+            }
+            directory.extend_from_slice(tag);
+            for value in [offset, compressed.len(), data.len(), 0] {
+                directory.extend_from_slice(&(value as u32).to_be_bytes());
+            }
+            offset += compressed.len().next_multiple_of(4);
+            body.extend_from_slice(&compressed);
+            body.resize(body.len().next_multiple_of(4), 0);
+        }
+        let mut woff = b"wOFF".to_vec();
+        woff.extend_from_slice(&flavor.to_be_bytes());
+        woff.extend_from_slice(&((44 + directory.len() + body.len()) as u32).to_be_bytes());
+        woff.extend_from_slice(&(tables.len() as u16).to_be_bytes());
+        woff.resize(44, 0);
+        woff.extend_from_slice(&directory);
+        woff.extend_from_slice(&body);
+        woff
+    }
+
+    #[test]
+    fn test_load_reads_family_weight_and_style_from_ttf_and_woff() {
+        let regular = load_font(Path::new(SERIF)).unwrap();
+        assert_eq!(regular.family, "DejaVu Serif");
+        assert_eq!((regular.weight, regular.italic), (400, false));
+        assert_eq!(regular.file_name, "fonts/dejavuserif.ttf");
+
+        let path = std::env::temp_dir().join(format!("magaziner-font-{}.woff", std::process::id()));
+        std::fs::write(&path, woff(&std::fs::read(BOLD_ITALIC).unwrap())).unwrap();
+        let options = resolve_fonts(&[PathBuf::from(SERIF), path.clone()], false, None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let bold_italic = &options.fonts[1];
+        assert_eq!(bold_italic.family, "DejaVu Serif");
+        assert_eq!((bold_italic.weight, bold_italic.italic), (700, true));
+        assert!(bold_italic.file_name.ends_with(".ttf"));
+
+        let css = options.css();
+        assert!(css.contains(
+            r#"@font-face { font-family: "DejaVu Serif"; font-weight: 700; font-style: italic;"#
+        ));
+        assert!(css.ends_with("body { font-family: \"DejaVu Serif\", serif; }\n"));
+
+        let err = load_font(Path::new("src/test/lrb/issue.html")).unwrap_err();
+        assert!(err.to_string().contains("not a TrueType"));
+    }
+
+    #[test]
+    fn test_subset_keeps_only_the_issues_glyphs() {
+        let issue = IssueData {
+            links: vec![],
+            title: "Vol. 47 No. 6".into(),
+            identity: IssueIdentity::default(),
+            css: String::new(),
+            cover_image_uri: String::new(),
+            publication_name: "London Review of Books".into(),
+        };
+        let articles = vec![ArticleData {
+            title: "Émigrés".into(),
+            author: None,
+            body: "<p>Ça <em>va</em> — “fine”.</p>".into(),
+        }];
+        let options = FontOptions {
+            fonts: vec![load_font(Path::new(SERIF)).unwrap()],
+            subset: true,
+        };
+        let files = options.files(&issue, &articles).unwrap();
+        let full = &options.fonts[0].data;
+        let subset = &files[0].data;
+        assert_eq!(files[0].media_type, "application/x-font-truetype");
+        assert!(subset.len() < full.len() / 3);
+
+        let face = Face::parse(subset, 0).unwrap();
+        let outline = |c: char| {
+            let id = face.glyph_index(c).unwrap();
+            face.glyph_bounding_box(id).is_some()
+        };
+        assert!(['a', 'É', 'Ç', '—', '“'].into_iter().all(outline));
+        assert!(!outline('ж'));
+        assert_eq!(checksum(subset), 0xB1B0_AFBA);
+    }
+
+    #[test]
+    fn test_subset_keeps_ligatures_of_kept_glyphs() {
+        let full = std::fs::read(SERIF).unwrap();
+        let subset = subset(&full, &"fix".chars().collect()).unwrap();
+        let face = Face::parse(&subset, 0).unwrap();
+        let outline = |c: char| {
+            let id = face.glyph_index(c).unwrap();
+            face.glyph_bounding_box(id).is_some()
+        };
+        assert!(outline('ﬁ'));
+        assert!(!outline('ﬂ'));
+    }
+}
@@ -1,4 +1,9 @@
-use crate::adapter::{ArticleData, IssueData, MagazineAdapter};
+use crate::adapter::{
+    ArticleData, IssueData, LoginForm, MagazineAdapter, SessionStatus, meta_author,
+};
+use crate::auth::Credentials;
+use crate::cover::CoverColours;
+use crate::identity::IssueIdentity;
 use crate::progress::Progress;
 use scraper::{Html, Selector};
 use std::collections::HashSet;
@@ -6,6 +11,79 @@ use std::collections::HashSet;
 pub struct HarpersAdapter;
 
 impl MagazineAdapter for HarpersAdapter {
+    fn key(&self) -> &'static str {
+        "harpers"
+    }
+
+    fn publication_name(&self) -> &'static str {
+        "Harper's Magazine"
+    }
+
+    fn language(&self) -> &'static str {
+        "en-US"
+    }
+
+    fn cover_colours(&self) -> CoverColours {
+        CoverColours {
+            background: [0xff, 0xff, 0xff],
+            text: [0x11, 0x11, 0x11],
+            accent: [0xc4, 0x16, 0x1c],
+        }
+    }
+
+    fn cookie_domain(&self) -> &'static str {
+        "harpers.org"
+    }
+
+    fn login_cookie(&self) -> Option<&'static str> {
+        Some("wordpress_logged_in_")
+    }
+
+    // Harper's runs on WordPress, so this is the stock wp-login.php form.
+    fn login_form(&self, credentials: &Credentials) -> Option<LoginForm> {
+        Some(LoginForm {
+            url: "https://harpers.org/wp-login.php".to_string(),
+            fields: vec![
+                ("log".into(), credentials.username.clone()),
+                ("pwd".into(), credentials.password.clone()),
+                ("rememberme".into(), "forever".into()),
+                ("wp-submit".into(), "Log In".into()),
+                ("redirect_to".into(), "https://harpers.org/".into()),
+                ("testcookie".into(), "1".into()),
+            ],
+        })
+    }
+
+    fn session_check_url(&self) -> Option<&'static str> {
+        Some("https://harpers.org/my-account/")
+    }
+
+    // WordPress adds `logged-in` to the body class for authenticated visitors.
+    fn session_status(&self, doc: &Html) -> SessionStatus {
+        let logged_in_sel = Selector::parse("body.logged-in").unwrap();
+        let account_sel = Selector::parse(".account-email, .user-email, .display-name").unwrap();
+
+        let logged_in = doc.select(&logged_in_sel).next().is_some();
+        let account = doc
+            .select(&account_sel)
+            .next()
+            .map(|el| el.text().collect::<String>().trim().to_string())
+            .filter(|a| !a.is_empty());
+
+        SessionStatus { logged_in, account }
+    }
+
+    fn latest_issue_page(&self) -> &'static str {
+        "https://harpers.org/"
+    }
+
+    fn issue_url(&self, identity: &IssueIdentity) -> Option<String> {
+        Some(format!(
+            "https://harpers.org/archive/{}/{:02}",
+            identity.year?, identity.month?
+        ))
+    }
+
     fn extract_issue(&self, doc: &Html, progress: &Progress) -> IssueData {
         let issue_article_sel =
             Selector::parse("section.issue-articles div.issue-article").unwrap();
@@ -92,6 +170,8 @@ impl MagazineAdapter for HarpersAdapter {
             })
             .unwrap_or_else(|| "Untitled".into());
 
+        let identity = IssueIdentity::from_date_text(&title);
+
         let cover_image_uri = doc
             .select(&cover_selector)
             .next()
@@ -101,14 +181,16 @@ impl MagazineAdapter for HarpersAdapter {
 
         progress.verbose(&format!("Found {} article links", links.len()));
         progress.verbose(&format!("Issue title: {}", title));
+        progress.verbose(&format!("Issue identity: {:?}", identity));
         progress.verbose(&format!("Cover image: {}", cover_image_uri));
 
         IssueData {
             links,
             title,
+            identity,
             css: String::new(),
             cover_image_uri,
-            publication_name: "Harper's Magazine".to_string(),
+            publication_name: self.publication_name().to_string(),
         }
     }
 
@@ -117,6 +199,7 @@ impl MagazineAdapter for HarpersAdapter {
         let fallback_title_selector = Selector::parse("title").unwrap();
         let body_selector = Selector::parse("div.wysiwyg-content.entry-content").unwrap();
         let header_meta_sel = Selector::parse("div.header-meta").unwrap();
+        let byline_sel = Selector::parse(r#".byline a[rel="author"], .byline a"#).unwrap();
 
         let title = doc
             .select(&title_selector)
@@ -146,7 +229,18 @@ impl MagazineAdapter for HarpersAdapter {
 
         progress.verbose(&format!("Extracted: {}", title));
 
-        ArticleData { title, body }
+        let author = meta_author(doc).or_else(|| {
+            doc.select(&byline_sel)
+                .next()
+                .map(|el| el.text().collect::<String>().trim().to_string())
+                .filter(|a| !a.is_empty())
+        });
+
+        ArticleData {
+            title,
+            author,
+            body,
+        }
     }
 }
 
@@ -175,6 +269,8 @@ mod tests {
             "Expected at least one article link"
         );
         assert_eq!(issue.title, "February 2026");
+        assert_eq!(issue.identity.year, Some(2026));
+        assert_eq!(issue.identity.month, Some(2));
         assert!(
             issue.links.iter().all(|l| {
                 l.starts_with("https://harpers.org/archive/")
@@ -193,12 +289,25 @@ mod tests {
         let article = adapter.extract_article(&doc, &progress);
 
         assert!(!article.title.is_empty(), "Article should have a title");
+        assert_eq!(article.author.as_deref(), Some("Jane Writer"));
         assert!(
             article.body.len() > 100,
             "Article body should be long enough"
         );
     }
 
+    #[test]
+    fn test_session_status_from_account_page() {
+        let adapter = HarpersAdapter;
+        let status = adapter.session_status(&load_html_fixture("src/test/harpers/account.html"));
+        assert!(status.logged_in);
+        assert_eq!(status.account.as_deref(), Some("reader@example.com"));
+
+        let logged_out =
+            adapter.session_status(&load_html_fixture("src/test/harpers/article.html"));
+        assert!(!logged_out.logged_in);
+    }
+
     #[test]
     fn test_article_body_excludes_adjust_share_controls() {
         let doc = load_html_fixture("src/test/harpers/article.html");
@@ -211,4 +320,23 @@ mod tests {
             "Article body should not contain the Adjust/Share UI controls"
         );
     }
+
+    #[test]
+    fn test_issue_url_round_trips_identity() {
+        let doc = load_html_fixture("src/test/harpers/issue.html");
+        let issue = HarpersAdapter.extract_issue(&doc, &Progress::new(Verbosity::Quiet));
+        assert_eq!(
+            HarpersAdapter.issue_url(&issue.identity).as_deref(),
+            Some("https://harpers.org/archive/2026/02")
+        );
+    }
+
+    #[test]
+    fn test_find_latest_issue() {
+        let doc = load_html_fixture("src/test/harpers/home.html");
+        assert_eq!(
+            HarpersAdapter.find_latest_issue(&doc).as_deref(),
+            Some("https://harpers.org/archive/2026/02")
+        );
+    }
 }
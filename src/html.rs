@@ -0,0 +1,281 @@
+use crate::adapter::{ArticleData, IssueData};
+use crate::epub::{sanitize_html_for_epub, sanitize_markup};
+use crate::output::{IssueModel, OutputBackend};
+use crate::progress::Progress;
+use anyhow::Result;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use regex::{Captures, Regex};
+use scraper::{Html, Selector};
+use std::collections::HashMap;
+use std::path::Path;
+use url::Url;
+
+const PAGE_CSS: &str = r#"
+body { font-family: Georgia, serif; max-width: 42em; margin: 0 auto; padding: 1em 2em; line-height: 1.5; }
+header.issue { text-align: center; margin-bottom: 3em; }
+header.issue img.cover { max-width: 60%; height: auto; }
+nav.toc ol { padding-left: 1.5em; }
+article { margin-top: 4em; border-top: 1px solid #ccc; padding-top: 1em; }
+h1.article-title { text-align: center; }
+p.byline { text-align: center; font-style: italic; }
+p.source { font-size: 0.85em; color: #666; }
+"#;
+
+/// Writes one self-contained `.html` page.
+pub struct HtmlBackend;
+
+impl OutputBackend for HtmlBackend {
+    fn file_name(&self, stem: &str) -> String {
+        format!("{}.html", stem)
+    }
+
+    fn inlines_images(&self) -> bool {
+        true
+    }
+
+    fn write(&self, model: &IssueModel, path: &Path, progress: &mut Progress) -> Result<()> {
+        progress.next("Building HTML…");
+        let page = build_html(
+            &model.issue,
+            &model.articles,
+            model.cover.as_deref(),
+            &model.images,
+        );
+        progress.next("Saving HTML…");
+        std::fs::write(path, page)?;
+        Ok(())
+    }
+}
+
+/// Renders the issue as one self-contained HTML page: the publication's
+/// stylesheet inlined, the cover and article images as data URIs, and a
+/// linked contents list. Images missing from `images` are left out.
+pub fn build_html(
+    issue: &IssueData,
+    articles: &[ArticleData],
+    cover: Option<&[u8]>,
+    images: &HashMap<String, Vec<u8>>,
+) -> String {
+    let title = escape(&issue.title);
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<style>\n{}\n{}</style>\n</head>\n<body>\n",
+        title, issue.css, PAGE_CSS
+    );
+
+    page.push_str("<header class=\"issue\">\n");
+    if let Some(cover) = cover {
+        page.push_str(&format!(
+            "<img class=\"cover\" src=\"{}\" alt=\"Cover\">\n",
+            data_uri(cover)
+        ));
+    }
+    page.push_str(&format!(
+        "<h1>{}</h1>\n<p class=\"publication\">{}</p>\n",
+        title,
+        escape(&issue.publication_name)
+    ));
+    if let Some(date) = issue.identity.long_date() {
+        page.push_str(&format!("<p class=\"date\">{}</p>\n", date));
+    }
+    page.push_str("</header>\n");
+
+    page.push_str("<nav class=\"toc\" id=\"contents\">\n<h2>Contents</h2>\n<ol>\n");
+    for (i, article) in articles.iter().enumerate() {
+        page.push_str(&format!(
+            "<li><a href=\"#article-{}\">{}</a>{}</li>\n",
+            i + 1,
+            escape(&article.title),
+            article
+                .author
+                .as_ref()
+                .map(|a| format!(" <span class=\"author\">— {}</span>", escape(a)))
+                .unwrap_or_default()
+        ));
+    }
+    page.push_str("</ol>\n</nav>\n");
+
+    for (i, article) in articles.iter().enumerate() {
+        page.push_str(&format!(
+            "<article id=\"article-{}\">\n<h1 class=\"article-title\">{}</h1>\n",
+            i + 1,
+            escape(&article.title)
+        ));
+        if let Some(author) = &article.author {
+            page.push_str(&format!("<p class=\"byline\">{}</p>\n", escape(author)));
+        }
+        page.push_str(&match issue.links.get(i) {
+            Some(link) => sanitize_markup(&inline_images(&article.body, link, images)),
+            None => sanitize_html_for_epub(&article.body),
+        });
+        page.push_str("\n<p class=\"source\">");
+        if let Some(link) = issue.links.get(i) {
+            page.push_str(&format!(
+                "<a href=\"{}\">Original article</a> · ",
+                escape(link)
+            ));
+        }
+        page.push_str("<a href=\"#contents\">Contents</a></p>\n</article>\n");
+    }
+
+    page.push_str("</body>\n</html>\n");
+    page
+}
+
+/// Absolute URLs of the images in an article body, resolved against the
+/// article's `link`. Lazy-loaded images are found through `data-src`.
+pub fn image_urls(body: &str, link: &str) -> Vec<String> {
+    let img = Selector::parse("img").unwrap();
+    Html::parse_fragment(body)
+        .select(&img)
+        .filter_map(|el| {
+            image_url(
+                el.value().attr("src").or(el.value().attr("data-src"))?,
+                link,
+            )
+        })
+        .collect()
+}
+
+fn image_url(src: &str, link: &str) -> Option<String> {
+    Some(Url::parse(link).ok()?.join(src.trim()).ok()?.to_string())
+}
+
+/// Rewrites each `<img>` whose image was downloaded as one with a data URI,
+/// keeping its alt text, and drops the rest so the page never loads remote
+/// images.
+fn inline_images(body: &str, link: &str, images: &HashMap<String, Vec<u8>>) -> String {
+    let tag = Regex::new(r"(?is)<img[^>]*>").unwrap();
+    tag.replace_all(body, |caps: &Captures| {
+        let fragment = Html::parse_fragment(&caps[0]);
+        let Some(el) = fragment.select(&Selector::parse("img").unwrap()).next() else {
+            return String::new();
+        };
+        let el = el.value();
+        el.attr("src")
+            .or(el.attr("data-src"))
+            .and_then(|src| image_url(src, link))
+            .and_then(|url| images.get(&url))
+            .map(|bytes| {
+                format!(
+                    "<img src=\"{}\" alt=\"{}\">",
+                    data_uri(bytes),
+                    escape(el.attr("alt").unwrap_or_default())
+                )
+            })
+            .unwrap_or_default()
+    })
+    .into_owned()
+}
+
+fn data_uri(image: &[u8]) -> String {
+    let content_type = image::guess_format(image)
+        .map(|format| format.to_mime_type())
+        .unwrap_or("image/jpeg");
+    format!("data:{};base64,{}", content_type, STANDARD.encode(image))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IssueIdentity;
+
+    fn issue() -> IssueData {
+        IssueData {
+            links: vec![
+                "https://www.lrb.co.uk/the-paper/v47/n06/a".into(),
+                "https://www.lrb.co.uk/the-paper/v47/n06/b".into(),
+            ],
+            title: "Vol. 47 No. 6 · 20 March 2025".into(),
+            identity: IssueIdentity::from_date_text("20 March 2025"),
+            css: "p { margin: 0; }".into(),
+            cover_image_uri: String::new(),
+            publication_name: "London Review of Books".into(),
+        }
+    }
+
+    fn articles() -> Vec<ArticleData> {
+        vec![
+            ArticleData {
+                title: "Diary".into(),
+                author: Some("Jane Writer".into()),
+                body: "<p>Text &amp; more.</p><img src=\"https://example.com/a.jpg\">".into(),
+            },
+            ArticleData {
+                title: "Letters <and> replies".into(),
+                author: None,
+                body: "<p>Dear sir</p><iframe src=\"x\"></iframe>".into(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_html_is_self_contained() {
+        let html = build_html(
+            &issue(),
+            &articles(),
+            Some(b"\xff\xd8jpeg"),
+            &HashMap::new(),
+        );
+        assert!(html.contains("<style>\np { margin: 0; }"));
+        assert!(
+            html.contains(
+                r#"<img class="cover" src="data:image/jpeg;base64,/9hqcGVn" alt="Cover">"#
+            )
+        );
+        assert!(!html.contains("https://example.com/a.jpg"));
+        assert!(!html.contains("<iframe"));
+        assert!(!html.contains("<link"));
+        assert!(html.contains("<p class=\"date\">20 March 2025</p>"));
+    }
+
+    #[test]
+    fn test_html_contents_link_to_articles() {
+        let html = build_html(&issue(), &articles(), None, &HashMap::new());
+        assert!(html.contains(
+            r##"<li><a href="#article-1">Diary</a> <span class="author">— Jane Writer</span></li>"##
+        ));
+        assert!(
+            html.contains(r##"<li><a href="#article-2">Letters &lt;and&gt; replies</a></li>"##)
+        );
+        assert!(html.contains(r#"<article id="article-2">"#));
+        assert!(html.contains(
+            r#"<a href="https://www.lrb.co.uk/the-paper/v47/n06/a">Original article</a>"#
+        ));
+        assert!(!html.contains("class=\"cover\""));
+    }
+
+    #[test]
+    fn test_article_images_are_inlined() {
+        let mut articles = articles();
+        articles[1].body =
+            r#"<p>Map:</p><img src="/images/map.png" alt="A &quot;map&quot;"><img data-src="gone.jpg">"#
+                .into();
+        let urls = image_urls(&articles[1].body, &issue().links[1]);
+        assert_eq!(
+            urls,
+            [
+                "https://www.lrb.co.uk/images/map.png",
+                "https://www.lrb.co.uk/the-paper/v47/n06/gone.jpg"
+            ]
+        );
+
+        let png = b"\x89PNG\r\n\x1a\nrest".to_vec();
+        let images = HashMap::from([(urls[0].clone(), png)]);
+        let html = build_html(&issue(), &articles, None, &images);
+        assert!(html.contains(
+            r#"<img src="data:image/png;base64,iVBORw0KGgpyZXN0" alt="A &quot;map&quot;" />"#
+        ));
+        assert!(!html.contains("gone.jpg"));
+        assert!(!html.contains("example.com/a.jpg"));
+    }
+}
@@ -0,0 +1,140 @@
+use chrono::NaiveDate;
+use regex::Regex;
+
+pub const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const SEASONS: [&str; 4] = ["Spring", "Summer", "Autumn", "Winter"];
+
+/// Structured identity of an issue, parsed by each adapter from the issue
+/// page. Fields a publication doesn't use are left as `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IssueIdentity {
+    pub volume: Option<u32>,
+    pub number: Option<u32>,
+    pub year: Option<i32>,
+    pub month: Option<u32>,
+    pub day: Option<u32>,
+    /// Season name for issues dated by season rather than month, e.g. "Summer".
+    pub season: Option<String>,
+}
+
+impl IssueIdentity {
+    /// Picks a cover date out of free text: "15 March 2025", "February 2026"
+    /// or "Summer 2025". Only the date fields are filled in.
+    pub fn from_date_text(text: &str) -> Self {
+        let mut identity = IssueIdentity::default();
+        let date_re = Regex::new(r"(?:\b(\d{1,2}) )?\b([A-Z][a-z]+) (\d{4})\b").unwrap();
+
+        for caps in date_re.captures_iter(text) {
+            let word = &caps[2];
+            let year = caps[3].parse().ok();
+            if let Some(month) = MONTHS.iter().position(|m| *m == word) {
+                identity.month = Some(month as u32 + 1);
+                identity.day = caps.get(1).and_then(|d| d.as_str().parse().ok());
+                identity.year = year;
+                break;
+            }
+            if SEASONS.contains(&word) {
+                identity.season = Some(word.to_string());
+                identity.year = year;
+                break;
+            }
+        }
+        identity
+    }
+
+    /// Cover date; issues without a day are dated the first of the month.
+    pub fn date(&self) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(self.year?, self.month?, self.day.unwrap_or(1))
+    }
+
+    /// `YYYY-MM-DD`, or `YYYY-MM` for monthly issues.
+    pub fn date_string(&self) -> Option<String> {
+        let year = self.year?;
+        let month = self.month?;
+        Some(match self.day {
+            Some(day) => format!("{}-{:02}-{:02}", year, month, day),
+            None => format!("{}-{:02}", year, month),
+        })
+    }
+
+    /// "20 March 2025", or "March 2025" for monthly issues.
+    pub fn long_date(&self) -> Option<String> {
+        let format = if self.day.is_some() {
+            "%-d %B %Y"
+        } else {
+            "%B %Y"
+        };
+        Some(self.date()?.format(format).to_string())
+    }
+
+    pub fn month_name(&self) -> Option<&'static str> {
+        self.month
+            .and_then(|m| MONTHS.get((m as usize).checked_sub(1)?))
+            .copied()
+    }
+
+    /// Position within the publication's run, for e-reader series sorting:
+    /// `volume.number` when numbered (47.06), otherwise `year.month` (2026.02).
+    pub fn series_index(&self) -> Option<f64> {
+        match (self.volume, self.number, self.year, self.month) {
+            (Some(volume), Some(number), _, _) => Some(volume as f64 + number as f64 / 100.0),
+            (_, _, Some(year), Some(month)) => Some(year as f64 + month as f64 / 100.0),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_date_text_with_day() {
+        let identity = IssueIdentity::from_date_text("Vol. 47 No. 6 · 20 March 2025");
+        assert_eq!(identity.year, Some(2025));
+        assert_eq!(identity.month, Some(3));
+        assert_eq!(identity.day, Some(20));
+        assert_eq!(identity.date_string().as_deref(), Some("2025-03-20"));
+    }
+
+    #[test]
+    fn test_from_date_text_month_and_season() {
+        let monthly = IssueIdentity::from_date_text("February 2026");
+        assert_eq!(monthly.date_string().as_deref(), Some("2026-02"));
+        assert_eq!(monthly.month_name(), Some("February"));
+        assert_eq!(monthly.date(), NaiveDate::from_ymd_opt(2026, 2, 1));
+
+        let seasonal = IssueIdentity::from_date_text("Summer 2025");
+        assert_eq!(seasonal.season.as_deref(), Some("Summer"));
+        assert_eq!(seasonal.year, Some(2025));
+        assert_eq!(seasonal.month, None);
+    }
+
+    #[test]
+    fn test_series_index_prefers_volume_and_number() {
+        let numbered = IssueIdentity {
+            volume: Some(47),
+            number: Some(6),
+            ..IssueIdentity::from_date_text("20 March 2025")
+        };
+        assert_eq!(numbered.series_index(), Some(47.06));
+        assert_eq!(
+            IssueIdentity::from_date_text("February 2026").series_index(),
+            Some(2026.02)
+        );
+    }
+}
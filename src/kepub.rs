@@ -0,0 +1,477 @@
+use crate::progress::Progress;
+use anyhow::{Context, Result};
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use scraper::{Html, Selector};
+use std::io::{Cursor, Read, Write};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Elements that start a new Kobo "paragraph" in span ids.
+const BLOCKS: &[&str] = &[
+    "p",
+    "div",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "li",
+    "blockquote",
+    "dd",
+    "dt",
+    "td",
+    "th",
+    "caption",
+    "figcaption",
+    "pre",
+];
+
+/// Elements whose text is left alone.
+const SKIP: &[&str] = &["script", "style", "svg", "math", "noscript"];
+
+/// Same rule kepubify adds, so the wrapper divs don't add page margins.
+const STYLE_HACKS: &str = r#"<style type="text/css" class="kobostylehacks">div#book-inner { margin-top: 0; margin-bottom: 0; }</style>"#;
+
+/// Rewrites a built EPUB as a Kobo KEPUB: every content document gets its
+/// text wrapped in numbered `koboSpan`s and its body in the `book-columns`
+/// and `book-inner` divs Kobo's reading engine expects. Other entries are
+/// copied unchanged, with `mimetype` first and uncompressed, and so is any
+/// chapter that isn't well-formed XML.
+pub fn convert(epub: &[u8], progress: &Progress) -> Result<Vec<u8>> {
+    let mut zip = ZipArchive::new(Cursor::new(epub)).context("The built EPUB is not a zip")?;
+    let mut out = ZipWriter::new(Cursor::new(Vec::new()));
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let navigation = navigation_documents(&mut zip)?;
+    let mut names: Vec<String> = zip.file_names().map(str::to_string).collect();
+    names.sort_by_key(|name| name != "mimetype");
+    for name in names {
+        let mut bytes = Vec::new();
+        zip.by_name(&name)?.read_to_end(&mut bytes)?;
+        if name == "mimetype" {
+            out.start_file(name, stored)?;
+            out.write_all(&bytes)?;
+            continue;
+        }
+        if is_xhtml(&name) && !navigation.contains(&name) {
+            let xhtml =
+                String::from_utf8(bytes).with_context(|| format!("{} is not UTF-8", name))?;
+            bytes = match add_kobo_spans(&xhtml) {
+                Ok(kepub) => kepub.into_bytes(),
+                Err(err) => {
+                    progress.verbose(&format!(
+                        "Warning: could not add Kobo spans to {}, copying it unchanged: {}",
+                        name, err
+                    ));
+                    xhtml.into_bytes()
+                }
+            };
+        }
+        out.start_file(name, deflated)?;
+        out.write_all(&bytes)?;
+    }
+    Ok(out.finish()?.into_inner())
+}
+
+fn is_xhtml(name: &str) -> bool {
+    [".xhtml", ".html", ".htm"]
+        .iter()
+        .any(|ext| name.to_lowercase().ends_with(ext))
+}
+
+/// Zip paths of the table of contents documents: the EPUB 3 manifest item
+/// marked `properties="nav"`, or the EPUB 2 guide's `toc` reference.
+fn navigation_documents(zip: &mut ZipArchive<Cursor<&[u8]>>) -> Result<Vec<String>> {
+    let rootfile = Selector::parse("rootfile").unwrap();
+    let toc = Selector::parse(r#"item[properties], reference[type="toc"]"#).unwrap();
+    let Some(opf_path) = Html::parse_document(&read_entry(zip, "META-INF/container.xml")?)
+        .select(&rootfile)
+        .find_map(|el| el.value().attr("full-path").map(str::to_string))
+    else {
+        return Ok(Vec::new());
+    };
+    let dir = opf_path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+    Ok(Html::parse_document(&read_entry(zip, &opf_path)?)
+        .select(&toc)
+        .filter(|el| {
+            el.value()
+                .attr("properties")
+                .is_none_or(|p| p.split_whitespace().any(|p| p == "nav"))
+        })
+        .filter_map(|el| el.value().attr("href"))
+        .map(|href| match dir {
+            "" => href.to_string(),
+            dir => format!("{}/{}", dir, href),
+        })
+        .collect())
+}
+
+fn read_entry(zip: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<String> {
+    let mut text = String::new();
+    zip.by_name(name)
+        .with_context(|| format!("EPUB has no {}", name))?
+        .read_to_string(&mut text)?;
+    Ok(text)
+}
+
+struct Spans {
+    out: String,
+    paragraph: u32,
+    segment: u32,
+}
+
+impl Spans {
+    fn open(&mut self) {
+        if self.paragraph == 0 {
+            self.paragraph = 1;
+        }
+        self.segment += 1;
+        self.out.push_str(&format!(
+            r#"<span class="koboSpan" id="kobo.{}.{}">"#,
+            self.paragraph, self.segment
+        ));
+    }
+
+    /// Wraps each sentence of already-escaped text in its own span.
+    fn text(&mut self, raw: &str) {
+        for sentence in sentences(raw) {
+            if sentence.trim().is_empty() {
+                self.out.push_str(sentence);
+            } else {
+                self.open();
+                self.out.push_str(sentence);
+                self.out.push_str("</span>");
+            }
+        }
+    }
+}
+
+/// Splits after sentence-ending punctuation (and any closing quotes) that is
+/// followed by whitespace or the end of the text. The whitespace stays with
+/// the sentence before it.
+fn sentences(text: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let offset = |i: usize| chars.get(i).map_or(text.len(), |&(offset, _)| offset);
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        if !matches!(chars[i].1, '.' | '!' | '?') {
+            i += 1;
+            continue;
+        }
+        let mut end = i + 1;
+        while end < chars.len()
+            && matches!(chars[end].1, '.' | '!' | '?' | '"' | '\'' | '’' | '”' | ')')
+        {
+            end += 1;
+        }
+        let mut next = end;
+        while next < chars.len() && chars[next].1.is_whitespace() {
+            next += 1;
+        }
+        if next > end || end == chars.len() {
+            parts.push(&text[start..offset(next)]);
+            start = offset(next);
+        }
+        i = next.max(i + 1);
+    }
+    if start < text.len() {
+        parts.push(&text[start..]);
+    }
+    parts
+}
+
+/// Adds Kobo spans and wrapper divs to one XHTML document. Everything
+/// except the inserted markup is copied byte for byte from the input.
+pub fn add_kobo_spans(xhtml: &str) -> Result<String> {
+    let mut reader = Reader::from_str(xhtml);
+    let mut spans = Spans {
+        out: String::with_capacity(xhtml.len() * 2),
+        paragraph: 0,
+        segment: 0,
+    };
+    let mut in_body = false;
+    let mut skip_depth = 0usize;
+    // Text arrives split around entity references, so sentences are found
+    // across the whole run before any of it is written.
+    let mut pending = String::new();
+
+    loop {
+        let start = reader.buffer_position() as usize;
+        let event = reader.read_event()?;
+        let raw = &xhtml[start..reader.buffer_position() as usize];
+        let wrap = in_body && skip_depth == 0;
+
+        match &event {
+            Event::Text(_) | Event::GeneralRef(_) if wrap => {
+                pending.push_str(raw);
+                continue;
+            }
+            _ => {}
+        }
+        if !pending.is_empty() {
+            spans.text(&pending);
+            pending.clear();
+        }
+
+        match event {
+            Event::Eof => break,
+            Event::Start(e) => {
+                let name = e.local_name();
+                let name = name.as_ref();
+                if in_body {
+                    if skip_depth > 0 || SKIP.contains(&name) || is_kobo_span(&e) {
+                        skip_depth += 1;
+                    } else if BLOCKS.contains(&name) {
+                        spans.paragraph += 1;
+                        spans.segment = 0;
+                    }
+                }
+                spans.out.push_str(raw);
+                if name == "body" {
+                    in_body = true;
+                    spans
+                        .out
+                        .push_str(r#"<div id="book-columns"><div id="book-inner">"#);
+                }
+            }
+            Event::End(e) => {
+                let name = e.local_name();
+                let name = name.as_ref();
+                if name == "body" {
+                    in_body = false;
+                    spans.out.push_str("</div></div>");
+                } else if name == "head" {
+                    spans.out.push_str(STYLE_HACKS);
+                } else if in_body && skip_depth > 0 {
+                    skip_depth -= 1;
+                }
+                spans.out.push_str(raw);
+            }
+            Event::Empty(e) if wrap && e.local_name().as_ref() == "img" => {
+                spans.open();
+                spans.out.push_str(raw);
+                spans.out.push_str("</span>");
+            }
+            _ => spans.out.push_str(raw),
+        }
+    }
+    Ok(spans.out)
+}
+
+fn is_kobo_span(e: &quick_xml::events::BytesStart) -> bool {
+    e.local_name().as_ref() == "span"
+        && e.attributes()
+            .flatten()
+            .any(|a| a.key.as_ref() == "class" && a.value.as_ref() == "koboSpan")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::Verbosity;
+    use epub_builder::{EpubBuilder, EpubContent, ZipLibrary};
+
+    const CHAPTER: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml">
+  <head><title>Diary</title></head>
+  <body>
+    <h1 class="article-title">Diary</h1>
+    <p>First sentence. Second &amp; last one!</p>
+    <p>With <em>emphasis</em> inside.<br/>And a break.</p>
+    <figure><img src="a.jpg" alt="A"/><figcaption>A picture</figcaption></figure>
+    <style>p { color: black. }</style>
+  </body>
+</html>"#;
+
+    /// Fails unless every element is properly closed.
+    fn assert_well_formed(xml: &str) {
+        let mut reader = Reader::from_str(xml);
+        loop {
+            match reader.read_event() {
+                Ok(Event::Eof) => break,
+                Ok(_) => {}
+                Err(e) => panic!("not well-formed: {}\n{}", e, xml),
+            }
+        }
+    }
+
+    #[test]
+    fn test_spans_wrap_sentences_and_images() {
+        let kepub = add_kobo_spans(CHAPTER).unwrap();
+        assert_well_formed(&kepub);
+        assert!(kepub.contains(r#"<div id="book-columns"><div id="book-inner">"#));
+        assert!(kepub.contains("</div></div></body>"));
+        assert!(kepub.contains(STYLE_HACKS));
+        assert!(kepub.contains(
+            r#"<h1 class="article-title"><span class="koboSpan" id="kobo.1.1">Diary</span></h1>"#
+        ));
+        assert!(kepub.contains(
+            r#"<p><span class="koboSpan" id="kobo.2.1">First sentence. </span><span class="koboSpan" id="kobo.2.2">Second &amp; last one!</span></p>"#
+        ));
+        assert!(kepub.contains(
+            r#"<span class="koboSpan" id="kobo.3.1">With </span><em><span class="koboSpan" id="kobo.3.2">emphasis</span></em><span class="koboSpan" id="kobo.3.3"> inside.</span><br/><span class="koboSpan" id="kobo.3.4">And a break.</span>"#
+        ));
+        assert!(
+            kepub.contains(
+                r#"<span class="koboSpan" id="kobo.3.5"><img src="a.jpg" alt="A"/></span>"#
+            )
+        );
+        assert!(kepub.contains(
+            r#"<figcaption><span class="koboSpan" id="kobo.4.1">A picture</span></figcaption>"#
+        ));
+        assert!(kepub.contains("<style>p { color: black. }</style>"));
+        assert!(kepub.contains("<head><title>Diary</title>"));
+    }
+
+    #[test]
+    fn test_converting_twice_adds_no_new_spans() {
+        let once = add_kobo_spans(CHAPTER).unwrap();
+        let body = |xml: &str| xml.matches("koboSpan").count();
+        let twice = add_kobo_spans(&once).unwrap();
+        assert_eq!(body(&once), body(&twice));
+        assert_well_formed(&twice);
+    }
+
+    #[test]
+    fn test_sentences() {
+        assert_eq!(
+            sentences("One. Two! \"Three?\" Four"),
+            ["One. ", "Two! ", "\"Three?\" ", "Four"]
+        );
+        assert_eq!(sentences("No stop"), ["No stop"]);
+        assert_eq!(sentences("3.5 million. Done."), ["3.5 million. ", "Done."]);
+    }
+
+    #[test]
+    fn test_convert_epub() {
+        let mut builder = EpubBuilder::new(ZipLibrary::new().unwrap()).unwrap();
+        builder.metadata("title", "Test").unwrap();
+        builder
+            .add_content(EpubContent::new("chapter.xhtml", CHAPTER.as_bytes()).title("Diary"))
+            .unwrap();
+        let broken = "<html><body><p>Line<br class=x></p></body></html>";
+        builder
+            .add_content(EpubContent::new("broken.xhtml", broken.as_bytes()).title("Broken"))
+            .unwrap();
+        let mut epub = Vec::new();
+        builder.generate(&mut epub).unwrap();
+
+        let kepub = convert(&epub, &Progress::new(Verbosity::Quiet)).unwrap();
+        let mut zip = ZipArchive::new(Cursor::new(kepub)).unwrap();
+        let first = zip.by_index(0).unwrap();
+        assert_eq!(first.name(), "mimetype");
+        assert_eq!(first.compression(), CompressionMethod::Stored);
+        drop(first);
+
+        let mut chapter = String::new();
+        zip.by_name("OEBPS/chapter.xhtml")
+            .unwrap()
+            .read_to_string(&mut chapter)
+            .unwrap();
+        assert!(chapter.contains(r#"id="kobo.2.2""#));
+        let mut nav = String::new();
+        zip.by_name("OEBPS/nav.xhtml")
+            .unwrap()
+            .read_to_string(&mut nav)
+            .unwrap();
+        assert!(!nav.contains("koboSpan"));
+        let mut copied = String::new();
+        zip.by_name("OEBPS/broken.xhtml")
+            .unwrap()
+            .read_to_string(&mut copied)
+            .unwrap();
+        assert_eq!(copied, broken);
+    }
+
+    #[test]
+    fn test_convert_book_with_markup_characters_in_titles() {
+        use crate::adapter::{ArticleData, IssueData};
+        use crate::identity::IssueIdentity;
+
+        let issue = IssueData {
+            links: Vec::new(),
+            title: "Bread & Roses".into(),
+            identity: IssueIdentity::default(),
+            css: String::new(),
+            cover_image_uri: String::new(),
+            publication_name: "Harper's & Co <Weekly>".into(),
+        };
+        let articles = [ArticleData {
+            title: "Fish & Chips".into(),
+            author: None,
+            body: "<p>Text &amp; more.</p>".into(),
+        }];
+        let mut progress = Progress::new(Verbosity::Quiet);
+        let epub = crate::epub::build_epub(&mut progress, &issue, &articles, None, &[]).unwrap();
+
+        let kepub = convert(&epub, &progress).unwrap();
+        let mut zip = ZipArchive::new(Cursor::new(kepub)).unwrap();
+        for name in [
+            "OEBPS/title.xhtml",
+            "OEBPS/toc.xhtml",
+            "OEBPS/article0.xhtml",
+        ] {
+            let mut page = String::new();
+            zip.by_name(name)
+                .unwrap()
+                .read_to_string(&mut page)
+                .unwrap();
+            assert_well_formed(&page);
+        }
+        let mut chapter = String::new();
+        zip.by_name("OEBPS/article0.xhtml")
+            .unwrap()
+            .read_to_string(&mut chapter)
+            .unwrap();
+        assert!(chapter.contains("<title>Fish &amp; Chips</title>"));
+    }
+
+    #[test]
+    fn test_convert_book_with_html5_void_elements() {
+        use crate::adapter::{ArticleData, IssueData};
+        use crate::identity::IssueIdentity;
+
+        let page = Html::parse_fragment(
+            r#"<div class="body"><p>One<br class=x>two<wbr>three.</p><picture><source srcset="a.webp" type="image/webp"><img src="a.jpg" alt=""></picture></div>"#,
+        );
+        let body = page
+            .select(&Selector::parse("div.body").unwrap())
+            .next()
+            .unwrap()
+            .inner_html();
+        assert!(body.contains(r#"<br class="x">"#));
+        let issue = IssueData {
+            links: Vec::new(),
+            title: "Vol. 47 No. 6".into(),
+            identity: IssueIdentity::default(),
+            css: String::new(),
+            cover_image_uri: String::new(),
+            publication_name: "London Review of Books".into(),
+        };
+        let articles = [ArticleData {
+            title: "Diary".into(),
+            author: None,
+            body,
+        }];
+        let mut progress = Progress::new(Verbosity::Quiet);
+        let epub = crate::epub::build_epub(&mut progress, &issue, &articles, None, &[]).unwrap();
+
+        let kepub = convert(&epub, &progress).unwrap();
+        let mut zip = ZipArchive::new(Cursor::new(kepub)).unwrap();
+        let mut chapter = String::new();
+        zip.by_name("OEBPS/article0.xhtml")
+            .unwrap()
+            .read_to_string(&mut chapter)
+            .unwrap();
+        assert_well_formed(&chapter);
+        assert!(chapter.contains(r#"<br class="x" />"#));
+        assert!(chapter.contains(r#"<source srcset="a.webp" type="image/webp" />"#));
+        assert!(chapter.contains("koboSpan"));
+    }
+}
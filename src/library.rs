@@ -0,0 +1,618 @@
+use crate::config::Config;
+use crate::identity::IssueIdentity;
+use anyhow::{Context, Result, bail};
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use scraper::Html;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Applied in order; `PRAGMA user_version` records how many have run.
+const MIGRATIONS: &[&str] = &[
+    r#"
+CREATE TABLE issues (
+    id          INTEGER PRIMARY KEY,
+    publication TEXT NOT NULL,
+    url         TEXT NOT NULL UNIQUE,
+    title       TEXT NOT NULL,
+    volume      INTEGER,
+    number      INTEGER,
+    year        INTEGER,
+    month       INTEGER,
+    day         INTEGER,
+    season      TEXT,
+    output_path TEXT NOT NULL,
+    sha256      TEXT NOT NULL,
+    size        INTEGER NOT NULL,
+    built_at    TEXT NOT NULL,
+    version     TEXT NOT NULL
+);
+CREATE TABLE articles (
+    issue_id INTEGER NOT NULL REFERENCES issues(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    title    TEXT NOT NULL,
+    url      TEXT NOT NULL,
+    PRIMARY KEY (issue_id, position)
+);
+"#,
+    r#"
+ALTER TABLE articles ADD COLUMN author TEXT;
+CREATE VIRTUAL TABLE article_text USING fts5(
+    title,
+    author,
+    body,
+    issue_id UNINDEXED,
+    position UNINDEXED,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+"#,
+];
+
+/// The library set with `library = "..."` in the config file, or
+/// `<data dir>/magaziner/library.db`.
+pub fn library_path(config: &Config) -> Option<PathBuf> {
+    config
+        .library
+        .clone()
+        .or_else(|| dirs::data_dir().map(|dir| dir.join("magaziner").join("library.db")))
+}
+
+/// A built issue, as passed to `Library::record`.
+pub struct NewIssue<'a> {
+    pub publication: &'a str,
+    pub url: &'a str,
+    pub title: &'a str,
+    pub identity: &'a IssueIdentity,
+    pub output_path: &'a Path,
+    /// Articles in reading order.
+    pub articles: &'a [NewArticle],
+}
+
+pub struct NewArticle {
+    pub title: String,
+    pub author: Option<String>,
+    pub url: String,
+    /// Plain text of the article, for full-text search.
+    pub text: String,
+}
+
+pub struct IssueRecord {
+    pub id: i64,
+    pub publication: String,
+    pub url: String,
+    pub title: String,
+    pub identity: IssueIdentity,
+    pub output_path: PathBuf,
+    pub sha256: String,
+    pub size: u64,
+    pub built_at: String,
+    pub version: String,
+}
+
+pub struct ArticleRecord {
+    pub title: String,
+    pub author: Option<String>,
+    pub url: String,
+}
+
+pub struct SearchHit {
+    pub issue_id: i64,
+    pub publication: String,
+    pub issue_title: String,
+    pub article_title: String,
+    pub author: Option<String>,
+    pub url: String,
+    pub snippet: String,
+}
+
+/// Index of every issue magaziner has built, kept in a SQLite database.
+pub struct Library {
+    conn: Connection,
+}
+
+impl Library {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open library {}", path.display()))?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        let library = Library { conn };
+        library
+            .migrate()
+            .with_context(|| format!("Failed to upgrade library {}", path.display()))?;
+        Ok(library)
+    }
+
+    fn migrate(&self) -> Result<()> {
+        let applied: usize = self
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if applied > MIGRATIONS.len() {
+            bail!("library was written by a newer version of magaziner");
+        }
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+            self.conn.execute_batch(migration)?;
+            self.conn.pragma_update(None, "user_version", i + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Records a freshly built issue, replacing any earlier build of the same URL.
+    pub fn record(&mut self, issue: &NewIssue) -> Result<i64> {
+        let sha256 = file_sha256(issue.output_path)?;
+        let size = fs::metadata(issue.output_path)?.len();
+        let output_path = fs::canonicalize(issue.output_path)?;
+        let built_at = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        let identity = issue.identity;
+
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "DELETE FROM article_text WHERE issue_id IN (SELECT id FROM issues WHERE url = ?1)",
+            [issue.url],
+        )?;
+        tx.execute("DELETE FROM issues WHERE url = ?1", [issue.url])?;
+        tx.execute(
+            "INSERT INTO issues (publication, url, title, volume, number, year, month, day,
+                                 season, output_path, sha256, size, built_at, version)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                issue.publication,
+                issue.url,
+                issue.title,
+                identity.volume,
+                identity.number,
+                identity.year,
+                identity.month,
+                identity.day,
+                identity.season,
+                output_path.to_string_lossy(),
+                sha256,
+                size,
+                built_at,
+                env!("CARGO_PKG_VERSION"),
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        for (position, article) in issue.articles.iter().enumerate() {
+            let title = article.title.trim();
+            tx.execute(
+                "INSERT INTO articles (issue_id, position, title, author, url)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![id, position, title, article.author, article.url],
+            )?;
+            tx.execute(
+                "INSERT INTO article_text (title, author, body, issue_id, position)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![title, article.author, article.text, id, position],
+            )?;
+        }
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// Lists issues oldest first, optionally for one publication.
+    pub fn issues(&self, publication: Option<&str>) -> Result<Vec<IssueRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM issues WHERE ?1 IS NULL OR publication = ?1
+             ORDER BY publication, year, month, day, volume, number, id",
+        )?;
+        let rows = stmt.query_map([publication], issue_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Finds an issue by its library id or source URL.
+    pub fn find(&self, id_or_url: &str) -> Result<Option<IssueRecord>> {
+        let url = id_or_url.trim_end_matches('/');
+        let id: i64 = id_or_url.parse().unwrap_or(-1);
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT * FROM issues WHERE id = ?1 OR url = ?2 OR url = ?2 || '/'",
+                params![id, url],
+                issue_from_row,
+            )
+            .optional()?)
+    }
+
+    pub fn articles(&self, issue_id: i64) -> Result<Vec<ArticleRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT title, author, url FROM articles WHERE issue_id = ?1 ORDER BY position",
+        )?;
+        let rows = stmt.query_map([issue_id], |row| {
+            Ok(ArticleRecord {
+                title: row.get(0)?,
+                author: row.get(1)?,
+                url: row.get(2)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Finds articles whose title, author or text contain `phrase`, best
+    /// matches first. The matching words are wrapped in `[` `]` in the snippet.
+    pub fn search(
+        &self,
+        phrase: &str,
+        publication: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>> {
+        // Quoted as one FTS5 phrase so punctuation in the input isn't query syntax.
+        let query = format!("\"{}\"", phrase.replace('"', "\"\""));
+        let mut stmt = self.conn.prepare(
+            "SELECT issues.id, issues.publication, issues.title, articles.title, articles.author,
+                    articles.url, snippet(article_text, -1, '[', ']', '…', 16)
+             FROM article_text
+             JOIN issues ON issues.id = article_text.issue_id
+             JOIN articles ON articles.issue_id = article_text.issue_id
+                          AND articles.position = article_text.position
+             WHERE article_text MATCH ?1 AND (?2 IS NULL OR issues.publication = ?2)
+             ORDER BY rank
+             LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(params![query, publication, limit], |row| {
+            Ok(SearchHit {
+                issue_id: row.get(0)?,
+                publication: row.get(1)?,
+                issue_title: row.get(2)?,
+                article_title: row.get(3)?,
+                author: row.get(4)?,
+                url: row.get(5)?,
+                snippet: row.get(6)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+}
+
+fn issue_from_row(row: &Row) -> rusqlite::Result<IssueRecord> {
+    Ok(IssueRecord {
+        id: row.get("id")?,
+        publication: row.get("publication")?,
+        url: row.get("url")?,
+        title: row.get("title")?,
+        identity: IssueIdentity {
+            volume: row.get("volume")?,
+            number: row.get("number")?,
+            year: row.get("year")?,
+            month: row.get("month")?,
+            day: row.get("day")?,
+            season: row.get("season")?,
+        },
+        output_path: PathBuf::from(row.get::<_, String>("output_path")?),
+        sha256: row.get("sha256")?,
+        size: row.get("size")?,
+        built_at: row.get("built_at")?,
+        version: row.get("version")?,
+    })
+}
+
+/// Text content of an article body, with whitespace collapsed.
+pub fn html_to_text(html: &str) -> String {
+    Html::parse_fragment(html)
+        .root_element()
+        .text()
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn file_sha256(path: &Path) -> Result<String> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(Sha256::digest(&bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// A hole in a publication's run of issues.
+#[derive(Debug, PartialEq)]
+pub enum Gap {
+    Issue(IssueIdentity),
+    /// A whole volume between two held volumes; its issue count is unknown.
+    Volume(u32),
+}
+
+/// Finds the issues missing between the earliest and latest held. When any
+/// held issue has a volume and number, the numbered issues are checked volume
+/// by volume and the rest are ignored; otherwise the run is checked month by
+/// month. Volumes before the latest run to `issues_per_volume` when it is
+/// known, and to the highest number held in them when it isn't.
+pub fn find_gaps(held: &[IssueIdentity], issues_per_volume: Option<u32>) -> Vec<Gap> {
+    let mut gaps = Vec::new();
+    let numbered: Vec<(u32, u32)> = held
+        .iter()
+        .filter_map(|i| Some((i.volume?, i.number?)))
+        .collect();
+
+    if !numbered.is_empty() {
+        let mut volumes: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+        for (volume, number) in numbered {
+            volumes.entry(volume).or_default().push(number);
+        }
+        let first = *volumes.keys().next().unwrap();
+        let last = *volumes.keys().next_back().unwrap();
+        for volume in first..=last {
+            let numbers = volumes.get(&volume).map(Vec::as_slice).unwrap_or_default();
+            let (Some(&lowest), Some(&highest)) = (numbers.iter().min(), numbers.iter().max())
+            else {
+                match issues_per_volume {
+                    Some(count) => gaps.extend((1..=count).map(|number| {
+                        Gap::Issue(IssueIdentity {
+                            volume: Some(volume),
+                            number: Some(number),
+                            ..Default::default()
+                        })
+                    })),
+                    None => gaps.push(Gap::Volume(volume)),
+                }
+                continue;
+            };
+            let start = if volume == first { lowest } else { 1 };
+            let end = match issues_per_volume {
+                Some(count) if volume != last => count.max(highest),
+                _ => highest,
+            };
+            for number in start..=end {
+                if !numbers.contains(&number) {
+                    gaps.push(Gap::Issue(IssueIdentity {
+                        volume: Some(volume),
+                        number: Some(number),
+                        ..Default::default()
+                    }));
+                }
+            }
+        }
+        return gaps;
+    }
+
+    let mut months: Vec<(i32, u32)> = held
+        .iter()
+        .filter_map(|i| Some((i.year?, i.month?)))
+        .collect();
+    months.sort();
+    months.dedup();
+    for pair in months.windows(2) {
+        let (mut year, mut month) = pair[0];
+        loop {
+            (year, month) = if month == 12 {
+                (year + 1, 1)
+            } else {
+                (year, month + 1)
+            };
+            if (year, month) >= pair[1] {
+                break;
+            }
+            gaps.push(Gap::Issue(IssueIdentity {
+                year: Some(year),
+                month: Some(month),
+                ..Default::default()
+            }));
+        }
+    }
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("magaziner-library-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn article(title: &str, author: Option<&str>, url: &str, text: &str) -> NewArticle {
+        NewArticle {
+            title: title.to_string(),
+            author: author.map(str::to_string),
+            url: url.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    fn numbered(volume: u32, number: u32) -> IssueIdentity {
+        IssueIdentity {
+            volume: Some(volume),
+            number: Some(number),
+            ..Default::default()
+        }
+    }
+
+    fn monthly(year: i32, month: u32) -> IssueIdentity {
+        IssueIdentity {
+            year: Some(year),
+            month: Some(month),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_record_list_and_rebuild_replaces_entry() {
+        let dir = temp_dir("record");
+        let epub = dir.join("issue.epub");
+        fs::write(&epub, b"epub bytes").unwrap();
+        let mut library = Library::open(&dir.join("library.db")).unwrap();
+
+        let identity = IssueIdentity {
+            volume: Some(47),
+            number: Some(6),
+            ..IssueIdentity::from_date_text("20 March 2025")
+        };
+        let articles = vec![
+            article(
+                "First ",
+                Some("Jane Austen"),
+                "https://www.lrb.co.uk/a",
+                "Courtship in Bath.",
+            ),
+            article(
+                "Second",
+                None,
+                "https://www.lrb.co.uk/b",
+                "A review of the naval lists.",
+            ),
+        ];
+        let issue = NewIssue {
+            publication: "lrb",
+            url: "https://www.lrb.co.uk/the-paper/v47/n06",
+            title: "Vol. 47 No. 6 · 20 March 2025",
+            identity: &identity,
+            output_path: &epub,
+            articles: &articles,
+        };
+        library.record(&issue).unwrap();
+        let id = library.record(&issue).unwrap();
+
+        let issues = library.issues(None).unwrap();
+        assert_eq!(issues.len(), 1, "rebuilding replaces the earlier entry");
+        assert_eq!(issues[0].identity, identity);
+        assert_eq!(issues[0].size, 10);
+        assert_eq!(issues[0].sha256, file_sha256(&epub).unwrap());
+        assert!(library.issues(Some("harpers")).unwrap().is_empty());
+
+        let found = library
+            .find("https://www.lrb.co.uk/the-paper/v47/n06/")
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.id, id);
+        assert_eq!(library.find(&id.to_string()).unwrap().unwrap().id, id);
+        let titles: Vec<String> = library
+            .articles(id)
+            .unwrap()
+            .into_iter()
+            .map(|a| a.title)
+            .collect();
+        assert_eq!(titles, ["First", "Second"]);
+        assert_eq!(
+            library.search("naval lists", None, 10).unwrap().len(),
+            1,
+            "rebuilding doesn't duplicate the search index"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_search_by_title_author_and_body() {
+        let dir = temp_dir("search");
+        let epub = dir.join("issue.epub");
+        fs::write(&epub, b"epub bytes").unwrap();
+        let mut library = Library::open(&dir.join("library.db")).unwrap();
+        let identity = IssueIdentity::from_date_text("February 2026");
+        let articles = vec![
+            article(
+                "The Cartographer",
+                Some("Jane Writer"),
+                "https://harpers.org/archive/2026/02/the-cartographer/",
+                "Maps of the Café Müller, drawn from memory, hung over the bar.",
+            ),
+            article(
+                "Readings",
+                None,
+                "https://harpers.org/archive/2026/02/readings/",
+                "Letters from a lighthouse keeper.",
+            ),
+        ];
+        library
+            .record(&NewIssue {
+                publication: "harpers",
+                url: "https://harpers.org/archive/2026/02",
+                title: "February 2026",
+                identity: &identity,
+                output_path: &epub,
+                articles: &articles,
+            })
+            .unwrap();
+
+        let by_title = library.search("cartographer", None, 10).unwrap();
+        assert_eq!(by_title.len(), 1);
+        assert_eq!(by_title[0].issue_title, "February 2026");
+        assert_eq!(by_title[0].author.as_deref(), Some("Jane Writer"));
+
+        assert_eq!(library.search("Jane Writer", None, 10).unwrap().len(), 1);
+
+        let by_body = library.search("cafe muller", None, 10).unwrap();
+        assert_eq!(by_body.len(), 1, "diacritics are folded");
+        assert!(
+            by_body[0].snippet.contains("[Café Müller]"),
+            "{}",
+            by_body[0].snippet
+        );
+
+        assert!(
+            library
+                .search("lighthouse keeper", Some("lrb"), 10)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            library
+                .search("keeper lighthouse", None, 10)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(library.search("\"unbalanced", None, 10).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        assert_eq!(
+            html_to_text("<p>One <em>two</em></p>\n\n<p>three&nbsp;four</p>"),
+            "One two three four"
+        );
+    }
+
+    #[test]
+    fn test_find_gaps_in_numbered_run() {
+        let held = [
+            numbered(46, 22),
+            numbered(46, 24),
+            numbered(48, 1),
+            numbered(48, 3),
+        ];
+        assert_eq!(
+            find_gaps(&held, None),
+            vec![
+                Gap::Issue(numbered(46, 23)),
+                Gap::Volume(47),
+                Gap::Issue(numbered(48, 2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_gaps_reports_missing_volume_ends() {
+        // An issue without a volume and number doesn't switch to monthly gaps.
+        let held = [
+            numbered(46, 19),
+            numbered(46, 20),
+            monthly(2025, 1),
+            numbered(47, 23),
+            numbered(48, 2),
+        ];
+        let gaps = find_gaps(&held, Some(24));
+        let mut expected: Vec<Gap> = (21..=24).map(|n| Gap::Issue(numbered(46, n))).collect();
+        expected.extend((1..=22).map(|n| Gap::Issue(numbered(47, n))));
+        expected.push(Gap::Issue(numbered(47, 24)));
+        expected.push(Gap::Issue(numbered(48, 1)));
+        assert_eq!(gaps, expected);
+
+        let held = [numbered(46, 24), numbered(48, 1)];
+        assert_eq!(find_gaps(&held, Some(24)).len(), 24);
+    }
+
+    #[test]
+    fn test_find_gaps_in_monthly_run() {
+        let held = [monthly(2025, 11), monthly(2026, 2), monthly(2025, 11)];
+        assert_eq!(
+            find_gaps(&held, None),
+            vec![Gap::Issue(monthly(2025, 12)), Gap::Issue(monthly(2026, 1)),]
+        );
+    }
+}
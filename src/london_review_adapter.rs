@@ -1,10 +1,54 @@
-use crate::adapter::{ArticleData, IssueData, MagazineAdapter};
+use crate::adapter::{ArticleData, IssueData, MagazineAdapter, meta_author};
+use crate::cover::CoverColours;
+use crate::identity::IssueIdentity;
 use crate::progress::Progress;
+use regex::Regex;
 use scraper::{Html, Selector};
 
 pub struct LondonReviewAdapter;
 
 impl MagazineAdapter for LondonReviewAdapter {
+    fn key(&self) -> &'static str {
+        "lrb"
+    }
+
+    fn publication_name(&self) -> &'static str {
+        "London Review of Books"
+    }
+
+    fn language(&self) -> &'static str {
+        "en-GB"
+    }
+
+    // Black on the paper's cream, with the masthead's red.
+    fn cover_colours(&self) -> CoverColours {
+        CoverColours {
+            background: [0xf5, 0xf0, 0xe6],
+            text: [0x1c, 0x1c, 0x1c],
+            accent: [0xc8, 0x10, 0x2e],
+        }
+    }
+
+    fn cookie_domain(&self) -> &'static str {
+        "www.lrb.co.uk"
+    }
+
+    fn latest_issue_page(&self) -> &'static str {
+        "https://www.lrb.co.uk/the-paper"
+    }
+
+    fn issue_url(&self, identity: &IssueIdentity) -> Option<String> {
+        Some(format!(
+            "https://www.lrb.co.uk/the-paper/v{:02}/n{:02}",
+            identity.volume?, identity.number?
+        ))
+    }
+
+    // Twenty-four fortnightly issues make up each year's volume.
+    fn issues_per_volume(&self) -> Option<u32> {
+        Some(24)
+    }
+
     fn extract_issue(&self, doc: &Html, progress: &Progress) -> IssueData {
         let articles_selector = Selector::parse("a.toc-item").unwrap();
         let title_selector = Selector::parse("title").unwrap();
@@ -28,6 +72,15 @@ impl MagazineAdapter for LondonReviewAdapter {
             })
             .unwrap_or_else(|| "Untitled".into());
 
+        // "Vol. 47 No. 6 · 20 March 2025"
+        let vol_no_re = Regex::new(r"Vol\.?\s*(\d+)\s*No\.?\s*(\d+)").unwrap();
+        let vol_no = vol_no_re.captures(&title);
+        let identity = IssueIdentity {
+            volume: vol_no.as_ref().and_then(|c| c[1].parse().ok()),
+            number: vol_no.as_ref().and_then(|c| c[2].parse().ok()),
+            ..IssueIdentity::from_date_text(&title)
+        };
+
         let css = doc
             .select(&css_selector)
             .map(|el| el.text().collect::<String>().trim().to_string())
@@ -46,14 +99,16 @@ impl MagazineAdapter for LondonReviewAdapter {
 
         progress.verbose(&format!("Found {} article links", links.len()));
         progress.verbose(&format!("Issue title: {}", title));
+        progress.verbose(&format!("Issue identity: {:?}", identity));
         progress.verbose(&format!("Cover image: {}", cover_image_uri));
 
         IssueData {
             links,
             title,
+            identity,
             css,
             cover_image_uri,
-            publication_name: "London Review of Books".to_string(),
+            publication_name: self.publication_name().to_string(),
         }
     }
 
@@ -82,10 +137,18 @@ impl MagazineAdapter for LondonReviewAdapter {
 
         let complete_article = format!("{reviewed_items}{body}");
 
+        // Article titles read "Author · Title".
+        let author = meta_author(doc).or_else(|| {
+            title
+                .split_once(" · ")
+                .map(|(author, _)| author.trim().to_string())
+        });
+
         progress.verbose(&format!("Extracted: {}", title));
 
         ArticleData {
             title,
+            author,
             body: complete_article,
         }
     }
@@ -116,6 +179,9 @@ mod tests {
             "Expected at least one article link"
         );
         assert_eq!(issue.title, "Vol.99 No. 3 · 15 March 2025");
+        assert_eq!(issue.identity.volume, Some(99));
+        assert_eq!(issue.identity.number, Some(3));
+        assert_eq!(issue.identity.date_string().as_deref(), Some("2025-03-15"));
         assert!(
             issue
                 .links
@@ -135,9 +201,29 @@ mod tests {
         let article = adapter.extract_article(&doc, &progress);
 
         assert!(!article.title.is_empty(), "Article should have a title");
+        assert_eq!(article.author.as_deref(), Some("Officer Stabler"));
         assert!(
             article.body.len() > 100,
             "Article body should be long enough"
         );
     }
+
+    #[test]
+    fn test_issue_url_round_trips_identity() {
+        let doc = load_html_fixture("src/test/lrb/issue.html");
+        let issue = LondonReviewAdapter.extract_issue(&doc, &Progress::new(Verbosity::Quiet));
+        assert_eq!(
+            LondonReviewAdapter.issue_url(&issue.identity).as_deref(),
+            Some("https://www.lrb.co.uk/the-paper/v99/n03")
+        );
+    }
+
+    #[test]
+    fn test_find_latest_issue() {
+        let doc = load_html_fixture("src/test/lrb/the-paper.html");
+        assert_eq!(
+            LondonReviewAdapter.find_latest_issue(&doc).as_deref(),
+            Some("https://www.lrb.co.uk/the-paper/v48/n02")
+        );
+    }
 }
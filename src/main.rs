@@ -1,172 +1,593 @@
 mod adapter;
+mod auth;
+mod blocks;
+mod browser;
+mod cache;
+mod calibre;
+mod cli;
+mod config;
+mod cookies;
+mod cover;
+mod css;
+mod delivery;
+mod device;
 mod epub;
 mod fetch;
+mod fonts;
 mod harpers_adapter;
+mod html;
+mod identity;
+mod kepub;
+mod library;
 mod london_review_adapter;
+mod markdown;
+mod opds;
+mod output;
+mod pdf;
+mod pipeline;
 mod progress;
+mod style;
+mod template;
+mod txt;
 mod validation;
 
 use adapter::MagazineAdapter;
-use anyhow::Result;
-use clap::Parser;
-use epub::build_epub;
-use fetch::{fetch_html_body, make_client};
+use anyhow::{Context, Result, anyhow};
+use cache::IssueCache;
+use clap::{Parser, ValueEnum};
+use cli::{
+    Args, AuthCommand, CacheCommand, Command, DownloadArgs, FetchArgs, GlobalArgs, LibraryCommand,
+    RebuildArgs,
+};
+use config::{Config, PublicationConfig};
+use delivery::DeliveryError;
+use fetch::fetch_html_body;
 use harpers_adapter::HarpersAdapter;
+use indicatif::HumanBytes;
+use library::{Gap, Library};
 use london_review_adapter::LondonReviewAdapter;
+use pipeline::{BuildSettings, Pages};
 use progress::{Progress, Verbosity};
-use std::path::PathBuf;
+use reqwest::blocking::Client;
+use std::path::Path;
+use std::process::ExitCode;
 use validation::{MagazineSource, detect_source, validate_magazine_url};
 
-#[derive(Parser, Debug)]
-#[command(
-    name = "magaziner",
-    version,
-    about = "Generate epub files from Magazine archives",
-    long_about = None
-)]
-struct Args {
-    #[arg(
-        short,
-        long,
-        value_parser = validate_magazine_url,
-        help = "Magazine archive URL (LRB or Harper's)"
-    )]
-    url: String,
-
-    #[arg(
-        long,
-        short,
-        help = "Output directory for generated EPUBs (Ex: ./downloads)",
-        default_value = "."
-    )]
-    output: PathBuf,
-
-    #[arg(
-        short,
-        long,
-        help = "Delay between calls to magazine source in milliseconds (ex: 1000 = 1 second)",
-        default_value_t = 3000
-    )]
-    delay: u64,
-
-    #[arg(
-        short,
-        long,
-        help = "Overwrite the output file if it already exists",
-        default_value_t = false
-    )]
-    force: bool,
-
-    #[arg(
-        short,
-        long,
-        help = "Print detailed network and parsing logs",
-        conflicts_with = "quiet"
-    )]
-    verbose: bool,
-
-    #[arg(
-        short,
-        long,
-        help = "Suppress all output for script automation",
-        conflicts_with = "verbose"
-    )]
-    quiet: bool,
-
-    #[arg(
-        short,
-        long,
-        help = "Custom output filename without extension (ex: --name \"My Issue\")"
-    )]
-    name: Option<String>,
-}
-
-fn main() -> Result<()> {
+fn adapter_for(source: &MagazineSource) -> Box<dyn MagazineAdapter> {
+    match source {
+        MagazineSource::LondonReview => Box::new(LondonReviewAdapter),
+        MagazineSource::Harpers => Box::new(HarpersAdapter),
+    }
+}
+
+/// Exit status when every EPUB was built but at least one could not be emailed.
+const EXIT_DELIVERY_FAILED: u8 = 3;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            if e.is::<DeliveryError>() {
+                ExitCode::from(EXIT_DELIVERY_FAILED)
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
+
+fn run() -> Result<()> {
     let args = Args::parse();
-    let url = args.url;
-    let output = args.output;
-    let delay = args.delay;
-    let force = args.force;
+    let global = &args.global;
+
+    let config_path = match &global.config {
+        Some(path) if !path.exists() => {
+            return Err(anyhow!("Config file '{}' does not exist.", path.display()));
+        }
+        Some(path) => Some(path.clone()),
+        None => config::default_config_path(),
+    };
+    let mut config = match &config_path {
+        Some(path) => config::load_config(path)?,
+        None => Config::default(),
+    };
+    if let Some(profile) = &global.profile {
+        config = config.with_profile(profile)?;
+    }
 
-    let verbosity = if args.verbose {
+    // A quiet or verbose flag on the command line overrides the opposite config default.
+    let verbose = global.verbose || (!global.quiet && config.verbose.unwrap_or(false));
+    let quiet = global.quiet || (!global.verbose && config.quiet.unwrap_or(false));
+    let verbosity = if verbose {
         Verbosity::Verbose
-    } else if args.quiet {
+    } else if quiet {
         Verbosity::Quiet
     } else {
         Verbosity::Normal
     };
 
-    let mut progress = Progress::new(verbosity);
+    match &args.command {
+        None => run_fetch(global, &args.fetch, &config, verbosity),
+        Some(Command::Fetch(fetch)) => run_fetch(global, fetch, &config, verbosity),
+        Some(Command::Rebuild(rebuild)) => run_rebuild(global, rebuild, &config, verbosity),
+        Some(Command::List { url }) => run_list(global, url, &config, verbosity),
+        Some(Command::Latest {
+            publication,
+            name,
+            download,
+        }) => run_latest(
+            global,
+            publication,
+            name.as_deref(),
+            download,
+            &config,
+            verbosity,
+        ),
+        Some(Command::Batch {
+            urls,
+            from_file,
+            keep_going,
+            download,
+        }) => run_batch(
+            global,
+            urls,
+            from_file.as_deref(),
+            *keep_going,
+            download,
+            &config,
+            verbosity,
+        ),
+        Some(Command::Cache { command }) => run_cache(command),
+        Some(Command::Library { command }) => run_library(command, &config),
+        Some(Command::Serve { bind, port }) => run_serve(bind, *port, &config, verbosity),
+        Some(Command::Auth {
+            command: AuthCommand::Check { publication },
+        }) => run_auth_check(
+            global,
+            publication.as_ref(),
+            &config,
+            &Progress::new(verbosity),
+        ),
+    }
+}
 
-    let source = detect_source(&url).expect("URL already validated by clap");
+fn publication_config(config: &Config, adapter: &dyn MagazineAdapter) -> PublicationConfig {
+    config
+        .publication(adapter.key())
+        .cloned()
+        .unwrap_or_default()
+}
 
-    // HARPERS_COOKIE should be set to the raw Cookie header value from an authenticated
-    // browser session (e.g. "wordpress_logged_in_xxx=abc123; other_cookie=value").
-    let harpers_cookie = std::env::var("HARPERS_COOKIE").ok();
-    if matches!(source, MagazineSource::Harpers) && harpers_cookie.is_none() {
-        eprintln!(
-            "Warning: HARPERS_COOKIE env var not set; subscriber content may be inaccessible."
-        );
+fn run_auth_check(
+    global: &GlobalArgs,
+    publication: Option<&MagazineSource>,
+    config: &Config,
+    progress: &Progress,
+) -> Result<()> {
+    let sources = match publication {
+        Some(source) => vec![source.clone()],
+        None => MagazineSource::value_variants().to_vec(),
+    };
+
+    let mut all_valid = true;
+    for source in &sources {
+        let adapter = adapter_for(source);
+        let publication = publication_config(config, adapter.as_ref());
+        let (cookies, browser_profile) = pipeline::session_sources(global, config, &publication);
+        all_valid &= auth::check_session(
+            adapter.as_ref(),
+            cookies.as_deref(),
+            browser_profile.as_deref(),
+            config,
+            progress,
+        )?;
     }
 
-    let cookie = match source {
-        MagazineSource::Harpers => harpers_cookie.as_deref(),
-        MagazineSource::LondonReview => None,
-    };
+    if !all_valid {
+        return Err(anyhow!("One or more sessions are not valid."));
+    }
+    Ok(())
+}
 
-    let client = make_client(cookie)?;
+fn run_fetch(
+    global: &GlobalArgs,
+    fetch: &FetchArgs,
+    config: &Config,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let url = fetch.url.as_deref().expect("URL required by clap");
+    let source = detect_source(url).expect("URL already validated by clap");
+    let adapter = adapter_for(&source);
+    let publication = publication_config(config, adapter.as_ref());
+    let settings = BuildSettings::resolve(
+        global,
+        &fetch.download.build,
+        fetch.download.delay,
+        fetch.name.as_deref(),
+        config,
+        &publication,
+    )?;
 
-    let adapter: Box<dyn MagazineAdapter> = match source {
-        MagazineSource::LondonReview => Box::new(LondonReviewAdapter),
-        MagazineSource::Harpers => Box::new(HarpersAdapter),
+    let mut progress = Progress::new(verbosity);
+    let client = pipeline::connect(adapter.as_ref(), global, config, &publication, &progress)?;
+    pipeline::fetch_issue(url, adapter.as_ref(), &client, &settings, &mut progress)?;
+    Ok(())
+}
+
+fn run_rebuild(
+    global: &GlobalArgs,
+    rebuild: &RebuildArgs,
+    config: &Config,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let url = rebuild.url.as_str();
+    let source = detect_source(url).expect("URL already validated by clap");
+    let adapter = adapter_for(&source);
+    let publication = publication_config(config, adapter.as_ref());
+    let settings = BuildSettings::resolve(
+        global,
+        &rebuild.build,
+        None,
+        rebuild.name.as_deref(),
+        config,
+        &publication,
+    )?;
+
+    let cache = IssueCache::existing(&cache::cache_root()?, adapter.key(), url)?;
+    let mut progress = Progress::new(verbosity);
+    pipeline::build_issue(
+        url,
+        adapter.as_ref(),
+        &settings,
+        &Pages::Offline { cache },
+        &mut progress,
+    )?;
+    Ok(())
+}
+
+fn run_list(global: &GlobalArgs, url: &str, config: &Config, verbosity: Verbosity) -> Result<()> {
+    let source = detect_source(url).expect("URL already validated by clap");
+    let adapter = adapter_for(&source);
+    let publication = publication_config(config, adapter.as_ref());
+    let progress = Progress::new(verbosity);
+
+    let client = pipeline::connect(adapter.as_ref(), global, config, &publication, &progress)?;
+    let pages = Pages::Online {
+        client: &client,
+        delay: 0,
+        cache: IssueCache::open(&cache::cache_root()?, adapter.key(), url)?,
     };
+    let issue = adapter.extract_issue(&pages.html(url, &progress)?, &progress);
 
-    if !output.exists() {
-        std::fs::create_dir_all(&output)?;
+    println!("{} · {}", adapter.publication_name(), issue.title);
+    if let Some(date) = issue.identity.date_string() {
+        println!("Date:     {}", date);
     }
+    if !issue.cover_image_uri.is_empty() {
+        println!("Cover:    {}", issue.cover_image_uri);
+    }
+    println!("Articles: {}", issue.links.len());
+    for (i, link) in issue.links.iter().enumerate() {
+        println!("  {:>3}. {}", i + 1, link);
+    }
+    Ok(())
+}
 
-    progress.next("Fetching issue HTML…");
-    let doc = fetch_html_body(&client, &url, &delay, &progress)?;
-    let issue = adapter.extract_issue(&doc, &progress);
+fn run_latest(
+    global: &GlobalArgs,
+    source: &MagazineSource,
+    name: Option<&str>,
+    download: &DownloadArgs,
+    config: &Config,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let adapter = adapter_for(source);
+    let publication = publication_config(config, adapter.as_ref());
+    let settings = BuildSettings::resolve(
+        global,
+        &download.build,
+        download.delay,
+        name,
+        config,
+        &publication,
+    )?;
 
-    let magazine_prefix = match source {
-        MagazineSource::Harpers => "Harpers",
-        MagazineSource::LondonReview => "LRB",
-    };
-    let filename = args
-        .name
-        .unwrap_or_else(|| format!("{} - {}", magazine_prefix, issue.title));
-
-    let output_path = output.join(format!("{}.epub", filename));
-    if output_path.exists() && !force {
-        return Err(anyhow::anyhow!(
-            "File '{}' already exists. Use --force to overwrite.",
-            output_path.display()
+    let mut progress = Progress::new(verbosity);
+    let client = pipeline::connect(adapter.as_ref(), global, config, &publication, &progress)?;
+    let page = adapter.latest_issue_page();
+    let doc = fetch_html_body(&client, page, &0, &progress)?;
+    let url = adapter
+        .find_latest_issue(&doc)
+        .ok_or_else(|| anyhow!("Could not find the current issue on {}", page))?;
+    progress.verbose(&format!("Latest issue: {}", url));
+
+    pipeline::fetch_issue(&url, adapter.as_ref(), &client, &settings, &mut progress)?;
+    Ok(())
+}
+
+fn run_batch(
+    global: &GlobalArgs,
+    urls: &[String],
+    from_file: Option<&Path>,
+    keep_going: bool,
+    download: &DownloadArgs,
+    config: &Config,
+    verbosity: Verbosity,
+) -> Result<()> {
+    let mut urls = urls.to_vec();
+    if let Some(path) = from_file {
+        urls.extend(read_url_list(path)?);
+    }
+    if urls.is_empty() {
+        return Err(anyhow!(
+            "No issue URLs given. Pass them as arguments or with --from-file."
         ));
     }
 
-    let article_length = issue.links.len();
-    progress.next(&format!("Extracting {} articles…", article_length));
+    let announce = !matches!(verbosity, Verbosity::Quiet);
+    let mut clients: Vec<(MagazineSource, Client)> = Vec::new();
+    let mut failed = 0;
+    let mut undelivered = Vec::new();
+    for (i, url) in urls.iter().enumerate() {
+        if announce {
+            println!("Issue {}/{}: {}", i + 1, urls.len(), url);
+        }
+        let source = detect_source(url).expect("URLs are validated before the batch starts");
+        let adapter = adapter_for(&source);
+        let publication = publication_config(config, adapter.as_ref());
+        let settings = BuildSettings::resolve(
+            global,
+            &download.build,
+            download.delay,
+            None,
+            config,
+            &publication,
+        )?;
+        let mut progress = Progress::new(verbosity);
 
-    let mut articles = Vec::new();
-    for (i, link) in issue.links.iter().enumerate() {
-        progress.substep(i, article_length);
-        let article_doc = fetch_html_body(&client, link, &delay, &progress)?;
-        let article = adapter.extract_article(&article_doc, &progress);
-        articles.push((article.title, article.body));
+        // One session per publication, reused for every issue in the batch.
+        let client = match clients.iter().find(|(s, _)| *s == source) {
+            Some((_, client)) => Ok(client.clone()),
+            None => pipeline::connect(adapter.as_ref(), global, config, &publication, &progress)
+                .inspect(|client| clients.push((source.clone(), client.clone()))),
+        };
+        let result = client.and_then(|client| {
+            pipeline::fetch_issue(url, adapter.as_ref(), &client, &settings, &mut progress)
+        });
+
+        match result {
+            Ok(_) => {}
+            Err(e) if e.is::<DeliveryError>() => {
+                if !keep_going {
+                    return Err(e);
+                }
+                eprintln!("Error: {:#}", e);
+                undelivered.push(e);
+            }
+            Err(e) => {
+                if !keep_going {
+                    return Err(e.context(format!("Failed to fetch {}", url)));
+                }
+                eprintln!("Error: failed to fetch {}: {:#}", url, e);
+                failed += 1;
+            }
+        }
     }
 
-    build_epub(
-        &mut progress,
-        &issue.title,
-        &issue.publication_name,
-        &filename,
-        &output,
-        articles,
-        &issue.css,
-        &issue.cover_image_uri,
-        &client,
-    )?;
+    if failed > 0 {
+        return Err(anyhow!("{} of {} issues failed.", failed, urls.len()));
+    }
+    // Every issue was built, so the run fails as a delivery failure rather than a build failure.
+    if let Some(e) = undelivered.pop() {
+        return Err(e.context(format!(
+            "{} of {} issues were built but not emailed.",
+            undelivered.len() + 1,
+            urls.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Reads issue URLs one per line, skipping blank lines and `#` comments.
+/// A path of `-` reads standard input.
+fn read_url_list(path: &Path) -> Result<Vec<String>> {
+    let content = if path == Path::new("-") {
+        std::io::read_to_string(std::io::stdin()).context("Failed to read URLs from stdin")?
+    } else {
+        std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read URL list {}", path.display()))?
+    };
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i, line.split('#').next().unwrap_or("").trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(i, line)| {
+            validate_magazine_url(line).map_err(|e| anyhow!("{}:{}: {}", path.display(), i + 1, e))
+        })
+        .collect()
+}
 
+fn run_cache(command: &CacheCommand) -> Result<()> {
+    let root = cache::cache_root()?;
+    match command {
+        CacheCommand::Path => println!("{}", root.display()),
+        CacheCommand::List => {
+            let issues = cache::list_issues(&root)?;
+            if issues.is_empty() {
+                println!("The page cache is empty.");
+            }
+            for issue in &issues {
+                println!(
+                    "{:<8} {}  ({} pages, {})",
+                    issue.key,
+                    issue.url,
+                    issue.pages,
+                    HumanBytes(issue.bytes)
+                );
+            }
+        }
+        CacheCommand::Clear { publication } => {
+            let adapter = publication.as_ref().map(adapter_for);
+            let removed = cache::clear(&root, adapter.as_ref().map(|a| a.key()))?;
+            println!("Removed {} cached issues.", removed);
+        }
+    }
     Ok(())
 }
+
+fn adapter_for_key(key: &str) -> Option<Box<dyn MagazineAdapter>> {
+    MagazineSource::value_variants()
+        .iter()
+        .map(adapter_for)
+        .find(|adapter| adapter.key() == key)
+}
+
+fn open_library(config: &Config) -> Result<Library> {
+    let path = library::library_path(config).ok_or_else(|| {
+        anyhow!("Could not determine the user data directory; set `library` in the config file")
+    })?;
+    Library::open(&path)
+}
+
+fn run_library(command: &LibraryCommand, config: &Config) -> Result<()> {
+    let library = open_library(config)?;
+
+    match command {
+        LibraryCommand::List { publication } => {
+            let key = publication.as_ref().map(|p| adapter_for(p).key());
+            let issues = library.issues(key)?;
+            if issues.is_empty() {
+                println!("The library is empty.");
+            }
+            for issue in &issues {
+                println!(
+                    "{:>4}  {:<8} {:<10}  {}",
+                    issue.id,
+                    issue.publication,
+                    issue.identity.date_string().unwrap_or_default(),
+                    issue.title
+                );
+            }
+        }
+        LibraryCommand::Show { issue } => {
+            let issue = library
+                .find(issue)?
+                .ok_or_else(|| anyhow!("No issue '{}' in the library.", issue))?;
+            let file_status = if !issue.output_path.exists() {
+                "missing"
+            } else if library::file_sha256(&issue.output_path)? == issue.sha256 {
+                "unchanged since build"
+            } else {
+                "modified since build"
+            };
+            let publication = adapter_for_key(&issue.publication)
+                .map(|a| a.publication_name().to_string())
+                .unwrap_or_else(|| issue.publication.clone());
+
+            println!("{}", issue.title);
+            println!("  Publication: {}", publication);
+            println!("  Source:      {}", issue.url);
+            if let (Some(volume), Some(number)) = (issue.identity.volume, issue.identity.number) {
+                println!("  Issue:       Vol. {} No. {}", volume, number);
+            }
+            if let Some(date) = issue.identity.date_string() {
+                println!("  Date:        {}", date);
+            }
+            println!(
+                "  File:        {} ({})",
+                issue.output_path.display(),
+                file_status
+            );
+            println!("  Size:        {}", HumanBytes(issue.size));
+            println!("  SHA-256:     {}", issue.sha256);
+            println!(
+                "  Built:       {} with magaziner {}",
+                issue.built_at, issue.version
+            );
+            let articles = library.articles(issue.id)?;
+            println!("  Articles:    {}", articles.len());
+            for (i, article) in articles.iter().enumerate() {
+                match &article.author {
+                    Some(author) => println!("    {:>3}. {} — {}", i + 1, article.title, author),
+                    None => println!("    {:>3}. {}", i + 1, article.title),
+                }
+                println!("         {}", article.url);
+            }
+        }
+        LibraryCommand::Search {
+            phrase,
+            publication,
+            limit,
+        } => {
+            let key = publication.as_ref().map(|p| adapter_for(p).key());
+            let hits = library.search(phrase, key, *limit)?;
+            if hits.is_empty() {
+                println!("No articles match \"{}\".", phrase);
+            }
+            for hit in &hits {
+                println!(
+                    "{} · {} (#{})",
+                    hit.publication, hit.issue_title, hit.issue_id
+                );
+                match &hit.author {
+                    Some(author) => println!("  {} — {}", hit.article_title, author),
+                    None => println!("  {}", hit.article_title),
+                }
+                println!("  {}", hit.snippet);
+                println!("  {}", hit.url);
+            }
+        }
+        LibraryCommand::Missing { publication } => {
+            let sources = match publication {
+                Some(source) => vec![source.clone()],
+                None => MagazineSource::value_variants().to_vec(),
+            };
+            for source in &sources {
+                let adapter = adapter_for(source);
+                let held: Vec<_> = library
+                    .issues(Some(adapter.key()))?
+                    .into_iter()
+                    .map(|issue| issue.identity)
+                    .collect();
+                if held.is_empty() {
+                    continue;
+                }
+                // Comment lines keep the output usable as a `batch --from-file` list.
+                let gaps = library::find_gaps(&held, adapter.issues_per_volume());
+                println!(
+                    "# {}: {} issues built, {} gaps",
+                    adapter.publication_name(),
+                    held.len(),
+                    gaps.len()
+                );
+                for gap in &gaps {
+                    match gap {
+                        Gap::Issue(identity) => match adapter.issue_url(identity) {
+                            Some(url) => println!("{}", url),
+                            None => println!("# {:?}", identity),
+                        },
+                        Gap::Volume(volume) => {
+                            println!("# Vol. {}: no issues in the library", volume)
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_serve(bind: &str, port: u16, config: &Config, verbosity: Verbosity) -> Result<()> {
+    let publications = MagazineSource::value_variants()
+        .iter()
+        .map(adapter_for)
+        .map(|a| (a.key().to_string(), a.publication_name().to_string()))
+        .collect();
+    let catalog = opds::Catalog::new(open_library(config)?, publications);
+    catalog.serve(
+        &format!("{}:{}", bind, port),
+        matches!(verbosity, Verbosity::Verbose),
+    )
+}
@@ -0,0 +1,184 @@
+use crate::adapter::{ArticleData, IssueData};
+use crate::epub::sanitize_html_for_epub;
+use crate::output::{IssueModel, OutputBackend};
+use crate::progress::Progress;
+use crate::template::slugify;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Writes a folder of Markdown files.
+pub struct MarkdownBackend;
+
+impl OutputBackend for MarkdownBackend {
+    fn file_name(&self, stem: &str) -> String {
+        stem.to_string()
+    }
+
+    fn write(&self, model: &IssueModel, path: &Path, progress: &mut Progress) -> Result<()> {
+        progress.next("Converting articles to Markdown…");
+        write_markdown(
+            path,
+            &model.url,
+            &model.issue,
+            &model.articles,
+            model.cover.as_deref(),
+            progress,
+        )
+    }
+}
+
+/// Writes the issue as a folder of Markdown files: `index.md` with the
+/// contents, one `NN-title.md` per article with YAML front matter, and the
+/// JPEG cover. Markdown files left from an earlier build are removed.
+pub fn write_markdown(
+    dir: &Path,
+    url: &str,
+    issue: &IssueData,
+    articles: &[ArticleData],
+    cover: Option<&[u8]>,
+    progress: &mut Progress,
+) -> Result<()> {
+    let date = issue.identity.date_string();
+    let mut index = String::from("---\n");
+    index.push_str(&field("title", &issue.title));
+    index.push_str(&field("publication", &issue.publication_name));
+    if let Some(date) = &date {
+        index.push_str(&format!("date: {}\n", date));
+    }
+    if let Some(volume) = issue.identity.volume {
+        index.push_str(&format!("volume: {}\n", volume));
+    }
+    if let Some(number) = issue.identity.number {
+        index.push_str(&format!("number: {}\n", number));
+    }
+    index.push_str(&field("source", url));
+    index.push_str(&format!("---\n\n# {}\n\n", issue.title));
+    if cover.is_some() {
+        index.push_str("![Cover](cover.jpg)\n\n");
+    }
+
+    let mut pages = Vec::new();
+    for (i, article) in articles.iter().enumerate() {
+        let slug = slugify(&article.title);
+        let name = if slug.is_empty() {
+            format!("{:02}.md", i + 1)
+        } else {
+            format!("{:02}-{}.md", i + 1, slug)
+        };
+        let byline = article
+            .author
+            .as_ref()
+            .map(|a| format!(" — {}", a))
+            .unwrap_or_default();
+        index.push_str(&format!(
+            "{}. [{}]({}){}\n",
+            i + 1,
+            article.title.replace('[', "\\[").replace(']', "\\]"),
+            name,
+            byline
+        ));
+
+        let mut page = String::from("---\n");
+        page.push_str(&field("title", &article.title));
+        if let Some(author) = &article.author {
+            page.push_str(&field("author", author));
+        }
+        page.push_str(&field("publication", &issue.publication_name));
+        page.push_str(&field("issue", &issue.title));
+        if let Some(date) = &date {
+            page.push_str(&format!("date: {}\n", date));
+        }
+        page.push_str(&format!("position: {}\n", i + 1));
+        if let Some(link) = issue.links.get(i) {
+            page.push_str(&field("source", link));
+        }
+        page.push_str(&format!("---\n\n# {}\n\n", article.title));
+        page.push_str(html2md::parse_html(&sanitize_html_for_epub(&article.body)).trim());
+        page.push('\n');
+        pages.push((name, page));
+    }
+    pages.push(("index.md".to_string(), index));
+
+    progress.next("Saving Markdown…");
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "md") {
+            fs::remove_file(path)?;
+        }
+    }
+    if let Some(image) = cover {
+        fs::write(dir.join("cover.jpg"), image)?;
+    }
+    for (name, page) in pages {
+        fs::write(dir.join(name), page)?;
+    }
+    Ok(())
+}
+
+/// A YAML front matter line with the value double-quoted.
+fn field(key: &str, value: &str) -> String {
+    let value = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', " ");
+    format!("{}: \"{}\"\n", key, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IssueIdentity;
+    use crate::progress::Verbosity;
+
+    #[test]
+    fn test_writes_index_and_articles_with_front_matter() {
+        let dir = std::env::temp_dir().join(format!("magaziner-markdown-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("99-stale.md"), "old").unwrap();
+
+        let issue = IssueData {
+            links: vec!["https://harpers.org/archive/2026/02/a-story/".into()],
+            title: "February 2026".into(),
+            identity: IssueIdentity::from_date_text("February 2026"),
+            css: String::new(),
+            cover_image_uri: String::new(),
+            publication_name: "Harper's Magazine".into(),
+        };
+        let articles = vec![ArticleData {
+            title: "The \"Real\" Story".into(),
+            author: Some("Jane Writer".into()),
+            body: "<p>First <em>paragraph</em>.</p><p>Second.</p>".into(),
+        }];
+        write_markdown(
+            &dir,
+            "https://harpers.org/archive/2026/02",
+            &issue,
+            &articles,
+            Some(b"\xff\xd8jpeg"),
+            &mut Progress::new(Verbosity::Quiet),
+        )
+        .unwrap();
+
+        assert!(!dir.join("99-stale.md").exists());
+        assert_eq!(fs::read(dir.join("cover.jpg")).unwrap(), b"\xff\xd8jpeg");
+        let index = fs::read_to_string(dir.join("index.md")).unwrap();
+        assert!(index.starts_with(
+            "---\ntitle: \"February 2026\"\npublication: \"Harper's Magazine\"\ndate: 2026-02\n"
+        ));
+        assert!(index.contains("![Cover](cover.jpg)"));
+        assert!(index.contains("1. [The \"Real\" Story](01-the-real-story.md) — Jane Writer"));
+
+        let article = fs::read_to_string(dir.join("01-the-real-story.md")).unwrap();
+        assert!(
+            article
+                .starts_with("---\ntitle: \"The \\\"Real\\\" Story\"\nauthor: \"Jane Writer\"\n")
+        );
+        assert!(article.contains("position: 1\nsource: \"https://harpers.org/archive/2026/02/a-story/\"\n---\n\n# The \"Real\" Story\n\n"));
+        assert!(article.contains("First *paragraph*."));
+        assert!(article.contains("Second."));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,577 @@
+use crate::cover::{self, CoverOptions};
+use crate::library::{IssueRecord, Library};
+use anyhow::{Context, Result, anyhow};
+use scraper::{Html, Selector};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use tiny_http::{Header, Response, Server};
+
+const NAVIGATION: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
+const ACQUISITION: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+
+/// Largest width and height of the cover thumbnails in acquisition feeds.
+const THUMBNAIL_SIZE: (u32, u32) = (240, 360);
+
+/// Serves the library as an OPDS 1.2 catalog: publications, then years,
+/// then the issues of that year with cover and download links.
+pub struct Catalog {
+    library: Library,
+    /// `(key, display name)` of every publication magaziner knows.
+    publications: Vec<(String, String)>,
+}
+
+/// A response body and its status code.
+pub struct Reply {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Reply {
+    fn feed(kind: &'static str, xml: String) -> Self {
+        Reply {
+            status: 200,
+            content_type: kind,
+            body: xml.into_bytes(),
+        }
+    }
+
+    fn not_found() -> Self {
+        Reply {
+            status: 404,
+            content_type: "text/plain; charset=utf-8",
+            body: b"Not found\n".to_vec(),
+        }
+    }
+}
+
+impl Catalog {
+    pub fn new(library: Library, publications: Vec<(String, String)>) -> Self {
+        Catalog {
+            library,
+            publications,
+        }
+    }
+
+    /// Handles requests until the process is stopped.
+    pub fn serve(&self, address: &str, verbose: bool) -> Result<()> {
+        let server =
+            Server::http(address).map_err(|e| anyhow!("Failed to listen on {}: {}", address, e))?;
+        println!("Serving OPDS catalog at http://{}/opds", address);
+
+        for request in server.incoming_requests() {
+            let path = request.url().to_string();
+            let reply = self.route(&path).unwrap_or_else(|e| {
+                eprintln!("Error serving {}: {:#}", path, e);
+                Reply {
+                    status: 500,
+                    content_type: "text/plain; charset=utf-8",
+                    body: b"Internal error\n".to_vec(),
+                }
+            });
+            if verbose {
+                println!("  {} {} {}", request.method(), path, reply.status);
+            }
+            let header = Header::from_bytes("Content-Type", reply.content_type)
+                .expect("content type is a valid header");
+            let response = Response::from_data(reply.body)
+                .with_status_code(reply.status)
+                .with_header(header);
+            if let Err(e) = request.respond(response) {
+                eprintln!("Error sending {}: {}", path, e);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn route(&self, url: &str) -> Result<Reply> {
+        let path = url
+            .split(['?', '#'])
+            .next()
+            .unwrap_or("")
+            .trim_end_matches('/');
+        let parts: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+
+        match parts.as_slice() {
+            [] | ["opds"] => self.root(),
+            ["opds", "publications", key] => self.years(key),
+            ["opds", "publications", key, year] => self.issues(key, year),
+            ["issues", file] if file.ends_with(".epub") => {
+                let id = file.trim_end_matches(".epub");
+                match self.issue(id)? {
+                    Some(issue) if issue.output_path.exists() => Ok(Reply {
+                        status: 200,
+                        content_type: "application/epub+zip",
+                        body: std::fs::read(&issue.output_path)?,
+                    }),
+                    _ => Ok(Reply::not_found()),
+                }
+            }
+            ["issues", id, "cover"] => match self.issue(id)? {
+                Some(issue) => match epub_cover(&issue.output_path)? {
+                    Some((body, content_type)) => Ok(Reply {
+                        status: 200,
+                        content_type,
+                        body,
+                    }),
+                    None => Ok(Reply::not_found()),
+                },
+                None => Ok(Reply::not_found()),
+            },
+            ["issues", id, "thumbnail"] => {
+                let cover = match self.issue(id)? {
+                    Some(issue) => epub_cover(&issue.output_path)?,
+                    None => None,
+                };
+                let options = CoverOptions {
+                    size: Some(THUMBNAIL_SIZE),
+                    greyscale: false,
+                };
+                match cover.and_then(|(body, _)| cover::process_cover(&body, &options).ok()) {
+                    Some(body) => Ok(Reply {
+                        status: 200,
+                        content_type: "image/jpeg",
+                        body,
+                    }),
+                    None => Ok(Reply::not_found()),
+                }
+            }
+            _ => Ok(Reply::not_found()),
+        }
+    }
+
+    fn issue(&self, id: &str) -> Result<Option<IssueRecord>> {
+        if id.parse::<i64>().is_err() {
+            return Ok(None);
+        }
+        self.library.find(id)
+    }
+
+    fn name(&self, key: &str) -> String {
+        self.publications
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, name)| name.clone())
+            .unwrap_or_else(|| key.to_string())
+    }
+
+    /// The publication's issues whose books are still on disk, so the
+    /// catalog never offers a download that would fail.
+    fn on_disk(&self, key: Option<&str>) -> Result<Vec<IssueRecord>> {
+        Ok(self
+            .library
+            .issues(key)?
+            .into_iter()
+            .filter(|issue| issue.output_path.is_file())
+            .collect())
+    }
+
+    fn root(&self) -> Result<Reply> {
+        let issues = self.on_disk(None)?;
+        let mut entries = String::new();
+        for (key, name) in &self.publications {
+            let held: Vec<&IssueRecord> = issues.iter().filter(|i| &i.publication == key).collect();
+            if held.is_empty() {
+                continue;
+            }
+            entries.push_str(&navigation_entry(
+                name,
+                &format!("urn:magaziner:publication:{}", key),
+                (&format!("/opds/publications/{}", key), NAVIGATION),
+                &format!("{} issues", held.len()),
+                &latest_build(held.iter().copied()),
+            ));
+        }
+        Ok(Reply::feed(
+            NAVIGATION,
+            feed(
+                "magaziner",
+                "urn:magaziner:root",
+                "/opds",
+                NAVIGATION,
+                &latest_build(issues.iter()),
+                &entries,
+            ),
+        ))
+    }
+
+    fn years(&self, key: &str) -> Result<Reply> {
+        let issues = self.on_disk(Some(key))?;
+        if issues.is_empty() {
+            return Ok(Reply::not_found());
+        }
+        let mut by_year: BTreeMap<Option<i32>, Vec<&IssueRecord>> = BTreeMap::new();
+        for issue in &issues {
+            by_year.entry(issue.identity.year).or_default().push(issue);
+        }
+
+        let mut entries = String::new();
+        for (year, held) in by_year.iter().rev() {
+            let label = year_label(*year);
+            entries.push_str(&navigation_entry(
+                &label,
+                &format!("urn:magaziner:publication:{}:{}", key, label),
+                (
+                    &format!("/opds/publications/{}/{}", key, label),
+                    ACQUISITION,
+                ),
+                &format!("{} issues", held.len()),
+                &latest_build(held.iter().copied()),
+            ));
+        }
+        Ok(Reply::feed(
+            NAVIGATION,
+            feed(
+                &self.name(key),
+                &format!("urn:magaziner:publication:{}", key),
+                &format!("/opds/publications/{}", key),
+                NAVIGATION,
+                &latest_build(issues.iter()),
+                &entries,
+            ),
+        ))
+    }
+
+    fn issues(&self, key: &str, year: &str) -> Result<Reply> {
+        let mut issues: Vec<IssueRecord> = self
+            .on_disk(Some(key))?
+            .into_iter()
+            .filter(|i| year_label(i.identity.year) == year)
+            .collect();
+        if issues.is_empty() {
+            return Ok(Reply::not_found());
+        }
+        issues.reverse();
+
+        let name = self.name(key);
+        let mut entries = String::new();
+        for issue in &issues {
+            entries.push_str(&self.acquisition_entry(issue, &name)?);
+        }
+        Ok(Reply::feed(
+            ACQUISITION,
+            feed(
+                &format!("{} {}", name, year),
+                &format!("urn:magaziner:publication:{}:{}", key, year),
+                &format!("/opds/publications/{}/{}", key, year),
+                ACQUISITION,
+                &latest_build(issues.iter()),
+                &entries,
+            ),
+        ))
+    }
+
+    fn acquisition_entry(&self, issue: &IssueRecord, publication: &str) -> Result<String> {
+        let articles = self.library.articles(issue.id)?;
+        let summary = articles
+            .iter()
+            .map(|a| a.title.as_str())
+            .collect::<Vec<_>>()
+            .join(" · ");
+
+        let mut links = String::new();
+        if let Ok(Some((_, content_type))) = epub_cover(&issue.output_path) {
+            links.push_str(&format!(
+                "\n    <link rel=\"http://opds-spec.org/image\" href=\"/issues/{}/cover\" type=\"{}\"/>",
+                issue.id, content_type
+            ));
+            links.push_str(&format!(
+                "\n    <link rel=\"http://opds-spec.org/image/thumbnail\" href=\"/issues/{}/thumbnail\" type=\"image/jpeg\"/>",
+                issue.id
+            ));
+        }
+        links.push_str(&format!(
+            "\n    <link rel=\"http://opds-spec.org/acquisition\" href=\"/issues/{}.epub\" type=\"application/epub+zip\"/>",
+            issue.id
+        ));
+
+        let issued = issue
+            .identity
+            .date_string()
+            .map(|d| format!("\n    <dc:issued>{}</dc:issued>", d))
+            .unwrap_or_default();
+        Ok(format!(
+            r#"
+  <entry>
+    <title>{}</title>
+    <id>urn:sha256:{}</id>
+    <updated>{}</updated>
+    <author><name>{}</name></author>{}
+    <summary type="text">{}</summary>{}
+  </entry>"#,
+            escape(&issue.title),
+            issue.sha256,
+            issue.built_at,
+            escape(publication),
+            issued,
+            escape(&summary),
+            links
+        ))
+    }
+}
+
+fn year_label(year: Option<i32>) -> String {
+    year.map(|y| y.to_string())
+        .unwrap_or_else(|| "undated".to_string())
+}
+
+fn latest_build<'a>(issues: impl Iterator<Item = &'a IssueRecord>) -> String {
+    issues
+        .map(|i| i.built_at.clone())
+        .max()
+        .unwrap_or_else(|| "1970-01-01T00:00:00Z".to_string())
+}
+
+fn feed(title: &str, id: &str, href: &str, kind: &str, updated: &str, entries: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xmlns:dc="http://purl.org/dc/terms/" xmlns:opds="http://opds-spec.org/2010/catalog">
+  <id>{}</id>
+  <title>{}</title>
+  <updated>{}</updated>
+  <author><name>magaziner</name></author>
+  <link rel="self" href="{}" type="{}"/>
+  <link rel="start" href="/opds" type="{}"/>{}
+</feed>
+"#,
+        escape(id),
+        escape(title),
+        updated,
+        href,
+        kind,
+        NAVIGATION,
+        entries
+    )
+}
+
+fn navigation_entry(
+    title: &str,
+    id: &str,
+    (href, kind): (&str, &str),
+    content: &str,
+    updated: &str,
+) -> String {
+    format!(
+        r#"
+  <entry>
+    <title>{}</title>
+    <id>{}</id>
+    <updated>{}</updated>
+    <content type="text">{}</content>
+    <link rel="subsection" href="{}" type="{}"/>
+  </entry>"#,
+        escape(title),
+        escape(id),
+        updated,
+        escape(content),
+        href,
+        kind
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Reads the cover image declared in an EPUB's package document.
+pub fn epub_cover(path: &Path) -> Result<Option<(Vec<u8>, &'static str)>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut zip =
+        zip::ZipArchive::new(file).with_context(|| format!("{} is not an EPUB", path.display()))?;
+
+    let container = read_entry(&mut zip, "META-INF/container.xml")?;
+    let rootfile = Selector::parse("rootfile").unwrap();
+    let Some(opf_path) = Html::parse_document(&container)
+        .select(&rootfile)
+        .find_map(|el| el.value().attr("full-path").map(str::to_string))
+    else {
+        return Ok(None);
+    };
+
+    let opf = Html::parse_document(&read_entry(&mut zip, &opf_path)?);
+    let cover_meta = Selector::parse(r#"meta[name="cover"]"#).unwrap();
+    let item = Selector::parse("item").unwrap();
+    let cover_id = opf
+        .select(&cover_meta)
+        .find_map(|el| el.value().attr("content"));
+    let href = opf
+        .select(&item)
+        .find(|el| {
+            cover_id.is_some_and(|id| el.value().attr("id") == Some(id))
+                || el
+                    .value()
+                    .attr("properties")
+                    .is_some_and(|p| p.split_whitespace().any(|p| p == "cover-image"))
+        })
+        .and_then(|el| el.value().attr("href"));
+    let Some(href) = href else {
+        return Ok(None);
+    };
+
+    let dir = opf_path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+    let entry = if dir.is_empty() {
+        href.to_string()
+    } else {
+        format!("{}/{}", dir, href)
+    };
+    let mut bytes = Vec::new();
+    zip.by_name(&entry)?.read_to_end(&mut bytes)?;
+    let content_type = if bytes.starts_with(&[0x89, b'P', b'N', b'G']) {
+        "image/png"
+    } else {
+        "image/jpeg"
+    };
+    Ok(Some((bytes, content_type)))
+}
+
+fn read_entry(zip: &mut zip::ZipArchive<File>, name: &str) -> Result<String> {
+    let mut text = String::new();
+    zip.by_name(name)
+        .with_context(|| format!("EPUB has no {}", name))?
+        .read_to_string(&mut text)?;
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IssueIdentity;
+    use crate::library::{NewArticle, NewIssue};
+    use epub_builder::{EpubBuilder, EpubContent, ZipLibrary};
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn catalog(name: &str) -> (Catalog, PathBuf) {
+        let dir =
+            std::env::temp_dir().join(format!("magaziner-opds-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let epub_path = dir.join("issue.epub");
+        let mut epub = EpubBuilder::new(ZipLibrary::new().unwrap()).unwrap();
+        epub.metadata("title", "February 2026").unwrap();
+        epub.add_cover_image("cover.jpg", cover_jpeg().as_slice(), "image/jpeg")
+            .unwrap();
+        epub.add_content(EpubContent::new("a.xhtml", "<p>x</p>".as_bytes()))
+            .unwrap();
+        epub.generate(File::create(&epub_path).unwrap()).unwrap();
+
+        let mut library = Library::open(&dir.join("library.db")).unwrap();
+        library
+            .record(&NewIssue {
+                publication: "harpers",
+                url: "https://harpers.org/archive/2026/02",
+                title: "February 2026",
+                identity: &IssueIdentity::from_date_text("February 2026"),
+                output_path: &epub_path,
+                articles: &[NewArticle {
+                    title: "Easy Chair & Notebook".into(),
+                    author: None,
+                    url: "https://harpers.org/archive/2026/02/easy-chair/".into(),
+                    text: String::new(),
+                }],
+            })
+            .unwrap();
+        let publications = vec![
+            ("lrb".to_string(), "London Review of Books".to_string()),
+            ("harpers".to_string(), "Harper's Magazine".to_string()),
+        ];
+        (Catalog::new(library, publications), dir)
+    }
+
+    fn cover_jpeg() -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new(&mut jpeg)
+            .encode(&[90; 600 * 900 * 3], 600, 900, image::ColorType::Rgb8)
+            .unwrap();
+        jpeg
+    }
+
+    fn text(reply: Reply) -> String {
+        String::from_utf8(reply.body).unwrap()
+    }
+
+    #[test]
+    fn test_navigation_by_publication_and_year() {
+        let (catalog, dir) = catalog("navigation");
+
+        let root = text(catalog.route("/opds").unwrap());
+        assert!(root.contains("<title>Harper's Magazine</title>"));
+        assert!(root.contains(r#"href="/opds/publications/harpers""#));
+        assert!(
+            !root.contains("London Review"),
+            "publications without issues are hidden"
+        );
+
+        let years = text(catalog.route("/opds/publications/harpers/").unwrap());
+        assert!(years.contains(r#"href="/opds/publications/harpers/2026""#));
+        assert!(years.contains(ACQUISITION));
+
+        let issues = catalog
+            .route("/opds/publications/harpers/2026?x=1")
+            .unwrap();
+        assert_eq!(issues.content_type, ACQUISITION);
+        let issues = text(issues);
+        assert!(issues.contains("<dc:issued>2026-02</dc:issued>"));
+        assert!(issues.contains("Easy Chair &amp; Notebook"));
+        assert!(issues.contains(r#"rel="http://opds-spec.org/acquisition" href="/issues/1.epub""#));
+        assert!(issues.contains(
+            r#"rel="http://opds-spec.org/image/thumbnail" href="/issues/1/thumbnail" type="image/jpeg""#
+        ));
+
+        assert_eq!(catalog.route("/opds/publications/lrb").unwrap().status, 404);
+        assert_eq!(
+            catalog
+                .route("/opds/publications/harpers/1999")
+                .unwrap()
+                .status,
+            404
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_download_and_cover() {
+        let (catalog, dir) = catalog("download");
+
+        let epub = catalog.route("/issues/1.epub").unwrap();
+        assert_eq!(epub.status, 200);
+        assert_eq!(epub.content_type, "application/epub+zip");
+        assert!(epub.body.starts_with(b"PK"));
+
+        let cover = catalog.route("/issues/1/cover").unwrap();
+        assert_eq!(cover.status, 200);
+        assert_eq!(cover.body, cover_jpeg());
+
+        let thumbnail = catalog.route("/issues/1/thumbnail").unwrap();
+        assert_eq!(thumbnail.status, 200);
+        let thumbnail = image::load_from_memory(&thumbnail.body).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), THUMBNAIL_SIZE);
+
+        assert_eq!(catalog.route("/issues/2.epub").unwrap().status, 404);
+        assert_eq!(catalog.route("/issues/../library.db").unwrap().status, 404);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_deleted_books_are_left_out() {
+        let (catalog, dir) = catalog("deleted");
+        fs::remove_file(dir.join("issue.epub")).unwrap();
+
+        assert!(!text(catalog.route("/opds").unwrap()).contains("Harper's Magazine"));
+        assert_eq!(
+            catalog
+                .route("/opds/publications/harpers/2026")
+                .unwrap()
+                .status,
+            404
+        );
+        assert_eq!(catalog.route("/issues/1.epub").unwrap().status, 404);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
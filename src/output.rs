@@ -0,0 +1,116 @@
+use crate::adapter::{ArticleData, IssueData};
+use crate::epub::EpubBackend;
+use crate::fonts::FontFile;
+use crate::html::HtmlBackend;
+use crate::markdown::MarkdownBackend;
+use crate::pdf::{PdfBackend, PdfOptions};
+use crate::progress::Progress;
+use crate::txt::{TextBackend, TextOptions};
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// What `build_issue` writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Epub,
+    /// EPUB with Kobo's reading-engine markup, for page counts and stats on Kobo devices.
+    Kepub,
+    /// One self-contained HTML page.
+    Html,
+    /// A folder of Markdown files, one per article.
+    Markdown,
+    /// A paginated PDF for printing.
+    Pdf,
+    /// Wrapped plain text, for text-to-speech and grep.
+    Txt,
+}
+
+impl OutputFormat {
+    /// Whether the output is an e-book that the library, catalog and
+    /// delivery steps can handle.
+    pub fn is_ebook(self) -> bool {
+        matches!(self, OutputFormat::Epub | OutputFormat::Kepub)
+    }
+
+    /// The backend that writes this format, with its page or text settings.
+    pub fn backend(self, pdf: PdfOptions, text: TextOptions) -> Box<dyn OutputBackend> {
+        match self {
+            OutputFormat::Epub => Box::new(EpubBackend { kepub: false }),
+            OutputFormat::Kepub => Box::new(EpubBackend { kepub: true }),
+            OutputFormat::Html => Box::new(HtmlBackend),
+            OutputFormat::Markdown => Box::new(MarkdownBackend),
+            OutputFormat::Pdf => Box::new(PdfBackend { options: pdf }),
+            OutputFormat::Txt => Box::new(TextBackend { options: text }),
+        }
+    }
+}
+
+/// Everything fetched for one issue, ready to be written out.
+pub struct IssueModel {
+    /// The issue page the model was built from.
+    pub url: String,
+    /// Title, identity, stylesheet and article links.
+    pub issue: IssueData,
+    /// Articles in reading order; `issue.links[i]` is where article `i` came from.
+    pub articles: Vec<ArticleData>,
+    /// The cover image: downloaded, or generated when the issue has none.
+    pub cover: Option<Vec<u8>>,
+    /// Font files to embed; only the EPUB backend uses them.
+    pub fonts: Vec<FontFile>,
+    /// Article images by absolute URL, downloaded only for backends that
+    /// inline them.
+    pub images: HashMap<String, Vec<u8>>,
+}
+
+/// Writes an assembled issue in one output format. Fetching and extraction
+/// know nothing about formats, so a new format only needs a backend and an
+/// `OutputFormat` variant.
+pub trait OutputBackend {
+    /// The output file name for `stem`; backends that write several files
+    /// return a folder name.
+    fn file_name(&self, stem: &str) -> String;
+
+    /// Whether the backend embeds article images, which are then downloaded
+    /// into `IssueModel::images`.
+    fn inlines_images(&self) -> bool {
+        false
+    }
+
+    /// Writes the issue to `path`. Backends report two numbered progress
+    /// steps: one for building the output and one for saving it.
+    fn write(&self, model: &IssueModel, path: &Path, progress: &mut Progress) -> Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_file_names() {
+        let name = |format: OutputFormat, text: TextOptions| {
+            format
+                .backend(PdfOptions::default(), text)
+                .file_name("LRB - Vol. 47 No. 6")
+        };
+        let text = TextOptions::default();
+        assert_eq!(name(OutputFormat::Epub, text), "LRB - Vol. 47 No. 6.epub");
+        assert_eq!(
+            name(OutputFormat::Kepub, text),
+            "LRB - Vol. 47 No. 6.kepub.epub"
+        );
+        assert_eq!(name(OutputFormat::Html, text), "LRB - Vol. 47 No. 6.html");
+        assert_eq!(name(OutputFormat::Markdown, text), "LRB - Vol. 47 No. 6");
+        assert_eq!(name(OutputFormat::Pdf, text), "LRB - Vol. 47 No. 6.pdf");
+        assert_eq!(name(OutputFormat::Txt, text), "LRB - Vol. 47 No. 6.txt");
+        let per_article = TextOptions {
+            per_article: true,
+            ..text
+        };
+        assert_eq!(name(OutputFormat::Txt, per_article), "LRB - Vol. 47 No. 6");
+    }
+}
@@ -0,0 +1,850 @@
+use crate::adapter::{ArticleData, IssueData};
+use crate::blocks::{Block, BlockKind, Inline, Notes, Style, blocks};
+use crate::output::{IssueModel, OutputBackend};
+use crate::progress::Progress;
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use image::DynamicImage;
+use printpdf::{
+    ColorBits, ColorSpace, Image, ImageFilter, ImageTransform, ImageXObject, IndirectFontRef, Line,
+    Mm, PdfDocument, Point, Px,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::Path;
+use ttf_parser::Face;
+
+const REGULAR: &[u8] = include_bytes!("../assets/fonts/DejaVuSerif.ttf");
+const BOLD: &[u8] = include_bytes!("../assets/fonts/DejaVuSerif-Bold.ttf");
+const ITALIC: &[u8] = include_bytes!("../assets/fonts/DejaVuSerif-Italic.ttf");
+const BOLD_ITALIC: &[u8] = include_bytes!("../assets/fonts/DejaVuSerif-BoldItalic.ttf");
+
+const DEFAULT_MARGIN: f32 = 20.0;
+const MIN_MARGIN: f32 = 10.0;
+const BODY_SIZE: f32 = 10.5;
+const LEADING: f32 = 1.4;
+const PT: f32 = 25.4 / 72.0;
+
+/// Paper size for `--format pdf`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageSize {
+    #[default]
+    A4,
+    Letter,
+    A5,
+}
+
+impl PageSize {
+    /// Width and height in millimetres.
+    fn dimensions(self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (210.0, 297.0),
+            PageSize::Letter => (215.9, 279.4),
+            PageSize::A5 => (148.0, 210.0),
+        }
+    }
+}
+
+/// The `[pdf]` config section.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PdfConfig {
+    pub page_size: Option<PageSize>,
+    /// Page margin in millimetres.
+    pub margin: Option<f32>,
+}
+
+/// Page setup for a PDF build.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PdfOptions {
+    pub page_size: PageSize,
+    pub margin: f32,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        PdfOptions {
+            page_size: PageSize::default(),
+            margin: DEFAULT_MARGIN,
+        }
+    }
+}
+
+/// Layers `--page-size` and `--margin` over the config file's `[pdf]` section.
+pub fn resolve_pdf(
+    page_size: Option<PageSize>,
+    margin: Option<f32>,
+    config: Option<&PdfConfig>,
+) -> Result<PdfOptions> {
+    let page_size = page_size
+        .or(config.and_then(|c| c.page_size))
+        .unwrap_or_default();
+    let margin = margin
+        .or(config.and_then(|c| c.margin))
+        .unwrap_or(DEFAULT_MARGIN);
+    let (width, _) = page_size.dimensions();
+    if !(MIN_MARGIN..=width / 4.0).contains(&margin) {
+        return Err(anyhow!(
+            "A {:?} page needs a margin between {} and {} mm, not {}",
+            page_size,
+            MIN_MARGIN,
+            width / 4.0,
+            margin
+        ));
+    }
+    Ok(PdfOptions { page_size, margin })
+}
+
+/// Writes a `.pdf` laid out for the configured paper.
+pub struct PdfBackend {
+    pub options: PdfOptions,
+}
+
+impl OutputBackend for PdfBackend {
+    fn file_name(&self, stem: &str) -> String {
+        format!("{}.pdf", stem)
+    }
+
+    fn write(&self, model: &IssueModel, path: &Path, progress: &mut Progress) -> Result<()> {
+        progress.next("Laying out PDF…");
+        let document = build_pdf(
+            &model.issue,
+            &model.articles,
+            model.cover.as_deref(),
+            &self.options,
+        )?;
+        progress.next("Saving PDF…");
+        std::fs::write(path, document)?;
+        Ok(())
+    }
+}
+
+/// Lays the issue out for print: cover, title page, contents with page
+/// numbers, then each article on a fresh page with a running header and
+/// page numbers. Fonts are the bundled DejaVu Serif family.
+pub fn build_pdf(
+    issue: &IssueData,
+    articles: &[ArticleData],
+    cover: Option<&[u8]>,
+    options: &PdfOptions,
+) -> Result<Vec<u8>> {
+    let fonts = Fonts::load()?;
+    let cover = cover.and_then(cover_image);
+    let layout = layout(&fonts, issue, articles, cover.as_ref(), options);
+
+    let (width, height) = options.page_size.dimensions();
+    let (doc, first_page, first_layer) =
+        PdfDocument::new(&issue.title, Mm(width), Mm(height), "Page 1");
+    let doc = doc
+        .with_author(&issue.publication_name)
+        .with_producer("magaziner");
+
+    let mut refs: HashMap<Style, IndirectFontRef> = HashMap::new();
+    for style in layout.styles() {
+        let font = doc
+            .add_external_font(Cursor::new(font_data(style)))
+            .map_err(|e| anyhow!("Failed to embed font: {}", e))?;
+        refs.insert(style, font);
+    }
+
+    for (i, page) in layout.pages.iter().enumerate() {
+        let (page_index, layer_index) = if i == 0 {
+            (first_page, first_layer)
+        } else {
+            doc.add_page(Mm(width), Mm(height), format!("Page {}", i + 1))
+        };
+        let layer = doc.get_page(page_index).get_layer(layer_index);
+        if let Some(title) = &page.bookmark {
+            doc.add_bookmark(title.as_str(), page_index);
+        }
+        for item in &page.items {
+            match item {
+                Item::Text {
+                    x,
+                    y,
+                    size,
+                    style,
+                    text,
+                } => layer.use_text(text.as_str(), *size, Mm(*x), Mm(*y), &refs[style]),
+                Item::Rule { x1, x2, y } => {
+                    layer.set_outline_thickness(0.5);
+                    layer.add_line(Line {
+                        points: vec![
+                            (Point::new(Mm(*x1), Mm(*y)), false),
+                            (Point::new(Mm(*x2), Mm(*y)), false),
+                        ],
+                        is_closed: false,
+                    });
+                }
+                Item::Cover { x, y, width } => {
+                    if let Some(cover) = &cover {
+                        let dpi = cover.width.0 as f32 * 25.4 / width;
+                        Image::from(cover.clone()).add_to_layer(
+                            layer.clone(),
+                            ImageTransform {
+                                translate_x: Some(Mm(*x)),
+                                translate_y: Some(Mm(*y)),
+                                dpi: Some(dpi),
+                                ..Default::default()
+                            },
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    doc.save_to_bytes()
+        .map_err(|e| anyhow!("Failed to write PDF: {}", e))
+}
+
+/// Greyscale and RGB JPEG covers are embedded as they are; anything else the
+/// image crate can read, CMYK JPEGs included, is decoded to RGB. Covers that
+/// can't be read are left out.
+fn cover_image(bytes: &[u8]) -> Option<ImageXObject> {
+    let direct = jpeg_dimensions(bytes).and_then(|(width, height, components)| {
+        let color_space = match components {
+            1 => ColorSpace::Greyscale,
+            3 => ColorSpace::Rgb,
+            _ => return None,
+        };
+        Some((width, height, color_space))
+    });
+    if let Some((width, height, color_space)) = direct {
+        return Some(ImageXObject {
+            width: Px(width),
+            height: Px(height),
+            color_space,
+            bits_per_component: ColorBits::Bit8,
+            interpolate: true,
+            image_data: bytes.to_vec(),
+            image_filter: Some(ImageFilter::DCT),
+            smask: None,
+            clipping_bbox: None,
+        });
+    }
+    let image = image::load_from_memory(bytes).ok()?;
+    Some(Image::from_dynamic_image(&DynamicImage::ImageRgb8(image.to_rgb8())).image)
+}
+
+/// Width, height and component count from a JPEG's start-of-frame marker.
+fn jpeg_dimensions(bytes: &[u8]) -> Option<(usize, usize, u8)> {
+    if !bytes.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+    let mut i = 2;
+    while i + 9 < bytes.len() {
+        if bytes[i] != 0xff {
+            return None;
+        }
+        let marker = bytes[i + 1];
+        let length = u16::from_be_bytes([bytes[i + 2], bytes[i + 3]]) as usize;
+        let start_of_frame =
+            (0xc0..=0xcf).contains(&marker) && !matches!(marker, 0xc4 | 0xc8 | 0xcc);
+        if start_of_frame {
+            let height = u16::from_be_bytes([bytes[i + 5], bytes[i + 6]]) as usize;
+            let width = u16::from_be_bytes([bytes[i + 7], bytes[i + 8]]) as usize;
+            return Some((width, height, bytes[i + 9]));
+        }
+        i += 2 + length;
+    }
+    None
+}
+
+pub fn font_data(style: Style) -> &'static [u8] {
+    match style {
+        Style::Regular => REGULAR,
+        Style::Bold => BOLD,
+        Style::Italic => ITALIC,
+        Style::BoldItalic => BOLD_ITALIC,
+    }
+}
+
+struct Fonts {
+    faces: [Face<'static>; 4],
+}
+
+impl Fonts {
+    fn load() -> Result<Fonts> {
+        let face = |data| Face::parse(data, 0).map_err(|e| anyhow!("Bundled font: {}", e));
+        Ok(Fonts {
+            faces: [
+                face(REGULAR)?,
+                face(BOLD)?,
+                face(ITALIC)?,
+                face(BOLD_ITALIC)?,
+            ],
+        })
+    }
+
+    /// Advance width of `text` in millimetres.
+    fn width(&self, text: &str, style: Style, size: f32) -> f32 {
+        let face = &self.faces[style as usize];
+        let units: u32 = text
+            .chars()
+            .map(|c| {
+                face.glyph_index(c)
+                    .and_then(|g| face.glyph_hor_advance(g))
+                    .unwrap_or(0) as u32
+            })
+            .sum();
+        units as f32 / face.units_per_em() as f32 * size * PT
+    }
+}
+
+enum Item {
+    Text {
+        x: f32,
+        y: f32,
+        size: f32,
+        style: Style,
+        text: String,
+    },
+    Rule {
+        x1: f32,
+        x2: f32,
+        y: f32,
+    },
+    Cover {
+        x: f32,
+        y: f32,
+        width: f32,
+    },
+}
+
+#[derive(Default)]
+struct Page {
+    items: Vec<Item>,
+    bookmark: Option<String>,
+}
+
+struct Layout {
+    pages: Vec<Page>,
+}
+
+impl Layout {
+    fn styles(&self) -> Vec<Style> {
+        let mut styles: Vec<Style> = self
+            .pages
+            .iter()
+            .flat_map(|p| &p.items)
+            .filter_map(|item| match item {
+                Item::Text { style, .. } => Some(*style),
+                _ => None,
+            })
+            .collect();
+        styles.sort();
+        styles.dedup();
+        styles
+    }
+}
+
+/// One laid-out line: text segments with their offsets from the line start.
+type LineSegments = Vec<(f32, Style, String)>;
+
+/// Greedy line breaking. Breaks happen only at whitespace, except inside a
+/// word too long for the line on its own.
+fn wrap(fonts: &Fonts, inlines: &[Inline], size: f32, width: f32) -> Vec<LineSegments> {
+    let space = fonts.width(" ", Style::Regular, size);
+    let mut lines: Vec<LineSegments> = Vec::new();
+    let mut line: LineSegments = Vec::new();
+    let mut x = 0.0;
+
+    let mut words: Vec<Option<Vec<(Style, &str)>>> = Vec::new();
+    for inline in inlines {
+        match inline {
+            Inline::Break => words.push(None),
+            Inline::Text { text, style, space } => match words.last_mut() {
+                Some(Some(word)) if !space => word.push((*style, text)),
+                _ => words.push(Some(vec![(*style, text)])),
+            },
+        }
+    }
+
+    for word in words {
+        let Some(pieces) = word else {
+            lines.push(std::mem::take(&mut line));
+            x = 0.0;
+            continue;
+        };
+        let word_width: f32 = pieces
+            .iter()
+            .map(|(style, text)| fonts.width(text, *style, size))
+            .sum();
+        let mut gap = if line.is_empty() { 0.0 } else { space };
+        if x + gap + word_width > width && !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+            x = 0.0;
+            gap = 0.0;
+        }
+
+        for (i, (style, text)) in pieces.into_iter().enumerate() {
+            let gap = if i == 0 { gap } else { 0.0 };
+            if word_width > width {
+                // Hard-split an overlong word character by character.
+                for c in text.chars() {
+                    let w = fonts.width(&c.to_string(), style, size);
+                    if x + w > width && !line.is_empty() {
+                        lines.push(std::mem::take(&mut line));
+                        x = 0.0;
+                    }
+                    push_segment(&mut line, x, style, &c.to_string(), false);
+                    x += w;
+                }
+                continue;
+            }
+            push_segment(&mut line, x + gap, style, text, gap > 0.0);
+            x += gap + fonts.width(text, style, size);
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+fn push_segment(line: &mut LineSegments, x: f32, style: Style, text: &str, spaced: bool) {
+    match line.last_mut() {
+        Some((_, last_style, last)) if *last_style == style => {
+            if spaced {
+                last.push(' ');
+            }
+            last.push_str(text);
+        }
+        _ => line.push((x, style, text.to_string())),
+    }
+}
+
+/// Places text on pages, starting a new page when the bottom margin is reached.
+struct Flow<'a> {
+    fonts: &'a Fonts,
+    width: f32,
+    height: f32,
+    margin: f32,
+    pages: Vec<Page>,
+    y: f32,
+    /// Running header for continuation pages.
+    header: Option<String>,
+}
+
+impl<'a> Flow<'a> {
+    fn new(fonts: &'a Fonts, options: &PdfOptions) -> Flow<'a> {
+        let (width, height) = options.page_size.dimensions();
+        Flow {
+            fonts,
+            width,
+            height,
+            margin: options.margin,
+            pages: Vec::new(),
+            y: 0.0,
+            header: None,
+        }
+    }
+
+    fn top(&self) -> f32 {
+        self.height - self.margin
+    }
+
+    fn text_width(&self) -> f32 {
+        self.width - 2.0 * self.margin
+    }
+
+    fn page(&mut self) -> &mut Page {
+        self.pages.last_mut().expect("flow has a page")
+    }
+
+    fn new_page(&mut self, bookmark: Option<String>) {
+        self.pages.push(Page {
+            items: Vec::new(),
+            bookmark,
+        });
+        self.y = self.top();
+    }
+
+    fn continue_page(&mut self) {
+        self.new_page(None);
+        if let Some(header) = self.header.clone() {
+            let size = 8.5;
+            let header = truncate(self.fonts, &header, Style::Italic, size, self.text_width());
+            let x = (self.width - self.fonts.width(&header, Style::Italic, size)) / 2.0;
+            let y = self.height - self.margin / 2.0;
+            self.text(x, y, size, Style::Italic, header);
+        }
+    }
+
+    fn text(&mut self, x: f32, y: f32, size: f32, style: Style, text: String) {
+        self.page().items.push(Item::Text {
+            x,
+            y,
+            size,
+            style,
+            text,
+        });
+    }
+
+    fn at_top(&self) -> bool {
+        self.y >= self.top()
+    }
+
+    /// Vertical space before a block, dropped at the top of a page.
+    fn gap(&mut self, space: f32) {
+        if !self.at_top() {
+            self.y -= space;
+        }
+    }
+
+    /// Lays out a paragraph between the given indents and returns the page
+    /// and baseline of its first line.
+    fn paragraph(
+        &mut self,
+        inlines: &[Inline],
+        size: f32,
+        (left, right): (f32, f32),
+        centered: bool,
+    ) -> (usize, f32) {
+        let leading = size * LEADING * PT;
+        let width = self.text_width() - left - right;
+        let mut start = None;
+        for line in wrap(self.fonts, inlines, size, width) {
+            if self.y - leading < self.margin {
+                self.continue_page();
+            }
+            self.y -= leading;
+            let y = self.y + leading - size * PT;
+            start.get_or_insert((self.pages.len() - 1, y));
+            let offset = if centered {
+                let (x, style, text) = line.last().expect("lines are never empty");
+                (width - x - self.fonts.width(text, *style, size)) / 2.0
+            } else {
+                0.0
+            };
+            for (x, style, text) in line {
+                self.text(self.margin + left + offset + x, y, size, style, text);
+            }
+        }
+        start.unwrap_or((self.pages.len() - 1, self.y))
+    }
+
+    fn rule(&mut self) {
+        let y = self.y - 3.0;
+        let middle = self.width / 2.0;
+        self.page().items.push(Item::Rule {
+            x1: middle - 15.0,
+            x2: middle + 15.0,
+            y,
+        });
+        self.y -= 6.0;
+    }
+}
+
+fn plain(text: &str, style: Style) -> Vec<Inline> {
+    text.split_whitespace()
+        .enumerate()
+        .map(|(i, word)| Inline::Text {
+            text: word.to_string(),
+            style,
+            space: i > 0,
+        })
+        .collect()
+}
+
+fn truncate(fonts: &Fonts, text: &str, style: Style, size: f32, width: f32) -> String {
+    if fonts.width(text, style, size) <= width {
+        return text.to_string();
+    }
+    let mut out = String::new();
+    for c in text.chars() {
+        if fonts.width(&format!("{}{}…", out, c), style, size) > width {
+            break;
+        }
+        out.push(c);
+    }
+    format!("{}…", out.trim_end())
+}
+
+fn layout(
+    fonts: &Fonts,
+    issue: &IssueData,
+    articles: &[ArticleData],
+    cover: Option<&ImageXObject>,
+    options: &PdfOptions,
+) -> Layout {
+    let mut front = Flow::new(fonts, options);
+    let text_width = front.text_width();
+
+    if let Some(cover) = cover {
+        let (width, height) = options.page_size.dimensions();
+        let available_height = height - 2.0 * options.margin;
+        let aspect = cover.height.0 as f32 / cover.width.0.max(1) as f32;
+        let image_width = text_width.min(available_height / aspect);
+        let image_height = image_width * aspect;
+        front.new_page(Some("Cover".into()));
+        front.page().items.push(Item::Cover {
+            x: (width - image_width) / 2.0,
+            y: (height - image_height) / 2.0,
+            width: image_width,
+        });
+    }
+
+    front.new_page(Some(issue.title.clone()));
+    front.y = front.height * 0.7;
+    front.paragraph(
+        &plain(&issue.publication_name, Style::Bold),
+        24.0,
+        (0.0, 0.0),
+        true,
+    );
+    front.gap(6.0);
+    front.paragraph(&plain(&issue.title, Style::Regular), 16.0, (0.0, 0.0), true);
+    if let Some(date) = issue.identity.long_date() {
+        front.gap(3.0);
+        front.paragraph(&plain(&date, Style::Italic), 12.0, (0.0, 0.0), true);
+    }
+    let numbered_from = front.pages.len();
+
+    // Articles are laid out first so the contents can give their pages.
+    let mut body = Flow::new(fonts, options);
+    let mut starts = Vec::new();
+    for article in articles {
+        body.header = None;
+        body.new_page(Some(article.title.clone()));
+        starts.push(body.pages.len() - 1);
+        body.paragraph(&plain(&article.title, Style::Bold), 18.0, (0.0, 0.0), false);
+        if let Some(author) = &article.author {
+            body.gap(2.0);
+            body.paragraph(&plain(author, Style::Italic), 12.0, (0.0, 0.0), false);
+        }
+        body.y -= 8.0;
+        body.header = Some(format!("{} · {}", issue.publication_name, article.title));
+
+        for block in blocks(&article.body, Notes::Keep) {
+            match block {
+                Block::Rule => body.rule(),
+                Block::Text { kind, inlines } => {
+                    let (size, indents, space) = match kind {
+                        BlockKind::Body => (BODY_SIZE, (0.0, 0.0), 2.0),
+                        BlockKind::Heading => (BODY_SIZE + 1.5, (0.0, 0.0), 5.0),
+                        BlockKind::Quote => (BODY_SIZE - 0.5, (8.0, 8.0), 2.0),
+                        BlockKind::Item => (BODY_SIZE, (5.0, 0.0), 1.0),
+                        BlockKind::Caption => (BODY_SIZE - 1.5, (0.0, 0.0), 2.0),
+                    };
+                    body.gap(space);
+                    body.paragraph(&inlines, size, indents, false);
+                }
+            }
+        }
+    }
+
+    // The contents, with page numbers filled in once its own length is known.
+    let mut contents = Flow::new(fonts, options);
+    contents.new_page(Some("Contents".into()));
+    contents.paragraph(&plain("Contents", Style::Bold), 16.0, (0.0, 0.0), false);
+    contents.y -= 4.0;
+    let mut entries = Vec::new();
+    for article in articles {
+        contents.gap(3.0);
+        let mut title = plain(&article.title, Style::Regular);
+        if let Some(author) = &article.author {
+            title.push(Inline::Break);
+            title.extend(plain(author, Style::Italic));
+        }
+        entries.push(contents.paragraph(&title, 11.0, (0.0, 12.0), false));
+    }
+    let first_article = front.pages.len() + contents.pages.len();
+    for ((page, y), start) in entries.into_iter().zip(&starts) {
+        let number = (first_article + start + 1).to_string();
+        let x = contents.width - contents.margin - fonts.width(&number, Style::Regular, 11.0);
+        contents.pages[page].items.push(Item::Text {
+            x,
+            y,
+            size: 11.0,
+            style: Style::Regular,
+            text: number,
+        });
+    }
+
+    let mut pages = front.pages;
+    pages.extend(contents.pages);
+    pages.extend(body.pages);
+
+    let (width, _) = options.page_size.dimensions();
+    for (i, page) in pages.iter_mut().enumerate().skip(numbered_from) {
+        let number = (i + 1).to_string();
+        page.items.push(Item::Text {
+            x: (width - fonts.width(&number, Style::Regular, 9.0)) / 2.0,
+            y: options.margin / 2.0,
+            size: 9.0,
+            style: Style::Regular,
+            text: number,
+        });
+    }
+    Layout { pages }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IssueIdentity;
+
+    fn issue(links: usize) -> IssueData {
+        IssueData {
+            links: (0..links)
+                .map(|i| format!("https://www.lrb.co.uk/the-paper/v47/n06/{}", i))
+                .collect(),
+            title: "Vol. 47 No. 6 · 20 March 2025".into(),
+            identity: IssueIdentity::from_date_text("20 March 2025"),
+            css: String::new(),
+            cover_image_uri: String::new(),
+            publication_name: "London Review of Books".into(),
+        }
+    }
+
+    fn article(title: &str, paragraphs: usize) -> ArticleData {
+        ArticleData {
+            title: title.into(),
+            author: Some("Jane Writer".into()),
+            body: "<p>Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod \
+                   tempor incididunt ut labore et dolore magna aliqua.</p>"
+                .repeat(paragraphs),
+        }
+    }
+
+    /// An 8×8 mid-grey baseline JPEG with four (CMYK) components.
+    fn cmyk_jpeg() -> Vec<u8> {
+        let mut jpeg = vec![0xff, 0xd8];
+        jpeg.extend([0xff, 0xdb, 0, 67, 0]);
+        jpeg.extend([1; 64]);
+        jpeg.extend([0xff, 0xc0, 0, 20, 8, 0, 8, 0, 8, 4]);
+        for id in 1..=4 {
+            jpeg.extend([id, 0x11, 0]);
+        }
+        // One DC and one AC table, each with a single one-bit code for symbol 0.
+        jpeg.extend([0xff, 0xc4, 0, 38]);
+        for class in [0x00, 0x10] {
+            jpeg.push(class);
+            jpeg.push(1);
+            jpeg.extend([0; 15]);
+            jpeg.push(0);
+        }
+        jpeg.extend([0xff, 0xda, 0, 14, 4]);
+        for id in 1..=4 {
+            jpeg.extend([id, 0x00]);
+        }
+        jpeg.extend([0, 63, 0]);
+        // Each block is a zero DC difference and an end-of-block: two bits.
+        jpeg.extend([0x00, 0xff, 0xd9]);
+        jpeg
+    }
+
+    #[test]
+    fn test_cmyk_jpeg_cover_is_converted() {
+        let jpeg = cmyk_jpeg();
+        assert_eq!(jpeg_dimensions(&jpeg), Some((8, 8, 4)));
+        let image = cover_image(&jpeg).expect("CMYK cover kept");
+        assert_eq!((image.width, image.height), (Px(8), Px(8)));
+        assert!(matches!(image.color_space, ColorSpace::Rgb));
+        assert!(image.image_filter.is_none());
+    }
+
+    fn text_on(page: &Page) -> Vec<&str> {
+        page.items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Text { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_wrap_fits_lines_to_width() {
+        let fonts = Fonts::load().unwrap();
+        let inlines = plain(&"word ".repeat(40), Style::Regular);
+        let lines = wrap(&fonts, &inlines, 10.0, 50.0);
+        assert!(lines.len() > 1);
+        for line in &lines {
+            let (x, style, text) = line.last().unwrap();
+            assert!(x + fonts.width(text, *style, 10.0) <= 50.0);
+        }
+        let joined: Vec<&str> = lines.iter().map(|l| l[0].2.as_str()).collect();
+        assert!(
+            joined
+                .iter()
+                .all(|l| l.starts_with("word") && !l.ends_with(' '))
+        );
+
+        let long = plain(&"x".repeat(200), Style::Regular);
+        assert!(wrap(&fonts, &long, 10.0, 50.0).len() > 1);
+    }
+
+    #[test]
+    fn test_contents_give_article_start_pages() {
+        let fonts = Fonts::load().unwrap();
+        let articles = vec![article("Diary", 60), article("Letters", 1)];
+        let layout = layout(&fonts, &issue(2), &articles, None, &PdfOptions::default());
+
+        let contents = layout
+            .pages
+            .iter()
+            .position(|p| p.bookmark.as_deref() == Some("Contents"))
+            .unwrap();
+        assert_eq!(contents, 1);
+        let text = text_on(&layout.pages[contents]);
+        for title in ["Diary", "Letters"] {
+            let start = layout
+                .pages
+                .iter()
+                .position(|p| p.bookmark.as_deref() == Some(title))
+                .unwrap();
+            assert!(text.contains(&(start + 1).to_string().as_str()));
+        }
+        let letters = layout
+            .pages
+            .iter()
+            .position(|p| p.bookmark.as_deref() == Some("Letters"))
+            .unwrap();
+        assert!(letters > 3, "a 60-paragraph article spans several pages");
+        // Continuation pages carry the running header; the title page has no number.
+        assert!(text_on(&layout.pages[3]).contains(&"London Review of Books · Diary"));
+        assert!(!text_on(&layout.pages[0]).contains(&"1"));
+    }
+
+    #[test]
+    fn test_jpeg_dimensions_from_start_of_frame() {
+        let header = [
+            0xff, 0xd8, 0xff, 0xe0, 0x00, 0x04, 0x00, 0x00, 0xff, 0xc2, 0x00, 0x11, 0x08, 0x02,
+            0x58, 0x01, 0x90, 0x03, 0x00, 0x00,
+        ];
+        assert_eq!(jpeg_dimensions(&header), Some((400, 600, 3)));
+        assert_eq!(jpeg_dimensions(b"\x89PNG\r\n"), None);
+    }
+
+    #[test]
+    fn test_build_pdf_writes_one_page_per_layout_page() {
+        let articles = vec![article("Diary", 3), article("Letters", 1)];
+        let bytes = build_pdf(&issue(2), &articles, None, &PdfOptions::default()).unwrap();
+        assert!(bytes.starts_with(b"%PDF-"));
+        let document = lopdf::Document::load_mem(&bytes).unwrap();
+        // Title page, contents, two articles.
+        assert_eq!(document.get_pages().len(), 4);
+    }
+
+    #[test]
+    fn test_margin_must_leave_room_for_text() {
+        assert!(resolve_pdf(Some(PageSize::A5), Some(40.0), None).is_err());
+        assert!(resolve_pdf(None, Some(5.0), None).is_err());
+        let config = PdfConfig {
+            page_size: Some(PageSize::Letter),
+            margin: Some(15.0),
+        };
+        assert_eq!(
+            resolve_pdf(None, Some(25.0), Some(&config)).unwrap(),
+            PdfOptions {
+                page_size: PageSize::Letter,
+                margin: 25.0
+            }
+        );
+    }
+}
@@ -0,0 +1,766 @@
+use crate::adapter::{ArticleData, MagazineAdapter};
+use crate::auth;
+use crate::cache::{self, IssueCache};
+use crate::calibre::{self, BookMetadata, CalibreExport};
+use crate::cli::{BuildArgs, GlobalArgs};
+use crate::config::{Config, PublicationConfig};
+use crate::cover::{self, CoverOptions};
+use crate::css;
+use crate::delivery::{self, Delivery};
+use crate::device::{self, DeviceCopy};
+use crate::fetch::{fetch_bytes, fetch_html_raw};
+use crate::fonts::{self, FontOptions};
+use crate::html;
+use crate::library::{self, Library, NewArticle, NewIssue};
+use crate::output::{IssueModel, OutputBackend};
+use crate::pdf;
+use crate::progress::Progress;
+use crate::style::{self, StyleOptions};
+use crate::template::{self, DEFAULT_TEMPLATE, RenderOptions, TemplateFields};
+use crate::txt;
+use crate::validation::{MagazineSource, detect_source};
+use anyhow::{Result, anyhow};
+use reqwest::blocking::Client;
+use scraper::Html;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Where an issue's pages come from: the network, recording every page in
+/// the cache as it goes, or the cache alone when rebuilding.
+pub enum Pages<'a> {
+    Online {
+        client: &'a Client,
+        delay: u64,
+        cache: IssueCache,
+    },
+    Offline {
+        cache: IssueCache,
+    },
+}
+
+impl Pages<'_> {
+    pub fn html(&self, url: &str, progress: &Progress) -> Result<Html> {
+        let body = match self {
+            Pages::Online {
+                client,
+                delay,
+                cache,
+            } => {
+                let body = fetch_html_raw(client, url, delay, progress)?;
+                cache.store(url, body.as_bytes())?;
+                body
+            }
+            Pages::Offline { cache } => {
+                progress.verbose(&format!("Cached {}", url));
+                String::from_utf8_lossy(&cached(cache, url)?).into_owned()
+            }
+        };
+        Ok(Html::parse_document(&body))
+    }
+
+    pub fn bytes(&self, url: &str, progress: &Progress) -> Result<Vec<u8>> {
+        match self {
+            Pages::Online { client, cache, .. } => {
+                let bytes = fetch_bytes(client, url, progress)?;
+                cache.store(url, &bytes)?;
+                Ok(bytes)
+            }
+            Pages::Offline { cache } => cached(cache, url),
+        }
+    }
+}
+
+fn cached(cache: &IssueCache, url: &str) -> Result<Vec<u8>> {
+    cache
+        .load(url)?
+        .ok_or_else(|| anyhow!("{} is not in the page cache; fetch the issue again", url))
+}
+
+/// How the output file is named: a fixed `--name`, or a path template.
+pub enum Naming {
+    Fixed(String),
+    Template(String),
+}
+
+/// Build options after layering the command line over the publication's
+/// config section, the config file and the built-in defaults.
+pub struct BuildSettings {
+    pub delay: u64,
+    pub force: bool,
+    pub skip_existing: bool,
+    pub naming: Naming,
+    pub render: RenderOptions,
+    pub output: PathBuf,
+    /// Writes the output in the chosen format.
+    pub backend: Box<dyn OutputBackend>,
+    /// Publisher CSS, theme and user stylesheet for the book.
+    pub style: StyleOptions,
+    /// Fonts to embed in EPUBs.
+    pub fonts: FontOptions,
+    /// Draw a cover even when the issue has one.
+    pub generated_cover: bool,
+    /// Size and colour of the cover in the book.
+    pub cover: CoverOptions,
+    /// Library to record built issues in; `None` leaves it untouched.
+    pub library: Option<PathBuf>,
+    /// Where to email each finished EPUB; `None` keeps it local.
+    pub delivery: Option<Delivery>,
+    /// E-reader to copy each finished EPUB to.
+    pub device: Option<DeviceCopy>,
+    /// Calibre library to add each finished EPUB to.
+    pub calibre: Option<CalibreExport>,
+}
+
+impl BuildSettings {
+    /// `delay` is the `--delay` flag, which `rebuild` doesn't take.
+    pub fn resolve(
+        global: &GlobalArgs,
+        build: &BuildArgs,
+        delay: Option<u64>,
+        name: Option<&str>,
+        config: &Config,
+        publication: &PublicationConfig,
+    ) -> Result<Self> {
+        let delay = delay.or(publication.delay).or(config.delay).unwrap_or(3000);
+
+        let mut output = global
+            .output
+            .clone()
+            .or_else(|| config.output.clone())
+            .unwrap_or_else(|| PathBuf::from("."));
+        if let Some(subdir) = &publication.output_subdir {
+            output = output.join(subdir);
+        }
+
+        let template = build
+            .filename_template
+            .as_deref()
+            .or(publication.filename_template.as_deref());
+        let naming = match (name, template, config.name.as_deref()) {
+            (Some(name), _, _) | (None, None, Some(name)) => Naming::Fixed(name.to_string()),
+            (None, template, _) => Naming::Template(
+                template
+                    .or(config.filename_template.as_deref())
+                    .unwrap_or(DEFAULT_TEMPLATE)
+                    .to_string(),
+            ),
+        };
+
+        let format = build.format.or(config.format).unwrap_or_default();
+        let mut settings = BuildSettings {
+            delay,
+            force: build.force || config.force.unwrap_or(false),
+            skip_existing: build.skip_existing,
+            naming,
+            render: RenderOptions {
+                slugify: build.slugify || config.slugify.unwrap_or(false),
+                charset: build.charset.or(config.charset).unwrap_or_default(),
+            },
+            output,
+            backend: format.backend(
+                pdf::resolve_pdf(build.page_size, build.margin, config.pdf.as_ref())?,
+                txt::resolve_text(build.wrap, build.per_article, config.text.as_ref()),
+            ),
+            style: style::resolve_style(
+                build.css.as_deref(),
+                build.css_mode,
+                build.theme,
+                build.no_publisher_css,
+                publication.stylesheet.as_deref(),
+                config.style.as_ref(),
+            )?,
+            fonts: fonts::resolve_fonts(&build.fonts, build.subset_fonts, config.style.as_ref())?,
+            generated_cover: build.generated_cover || config.generated_cover.unwrap_or(false),
+            cover: cover::resolve_cover(
+                build.cover_size.as_deref(),
+                build.greyscale_cover,
+                config.cover.as_ref(),
+            )?,
+            library: library::library_path(config),
+            delivery: delivery::resolve_delivery(
+                build.email,
+                &build.email_to,
+                config.email.as_ref(),
+            )?,
+            device: device::resolve_device(
+                build.device.as_ref(),
+                build.keep_on_device,
+                config.device.as_ref(),
+            ),
+            calibre: calibre::resolve_calibre(build.calibre.as_ref(), config.calibre.as_ref()),
+        };
+
+        // HTML, Markdown, PDF and text exports aren't books: they stay out of the library,
+        // and delivery or fonts set in the config file are skipped for them.
+        if !format.is_ebook() {
+            let requested = build.email
+                || !build.email_to.is_empty()
+                || build.device.is_some()
+                || build.calibre.is_some();
+            if requested {
+                return Err(anyhow!(
+                    "--email, --email-to, --device and --calibre need --format epub or kepub"
+                ));
+            }
+            if !build.fonts.is_empty() {
+                return Err(anyhow!("--font needs --format epub or kepub"));
+            }
+            settings.library = None;
+            settings.fonts = FontOptions::default();
+            settings.delivery = None;
+            settings.device = None;
+            settings.calibre = None;
+        }
+        Ok(settings)
+    }
+}
+
+/// Picks the cookie source for a publication. Either flag on the command line
+/// replaces both config settings, since only one source is used at a time.
+pub fn session_sources(
+    global: &GlobalArgs,
+    config: &Config,
+    publication: &PublicationConfig,
+) -> (Option<PathBuf>, Option<PathBuf>) {
+    if global.cookies.is_some() || global.browser_profile.is_some() {
+        return (global.cookies.clone(), global.browser_profile.clone());
+    }
+    if publication.cookies.is_some() || publication.browser_profile.is_some() {
+        return (
+            publication.cookies.clone(),
+            publication.browser_profile.clone(),
+        );
+    }
+    (config.cookies.clone(), config.browser_profile.clone())
+}
+
+/// Resolves the publication's session and returns an HTTP client carrying it.
+pub fn connect(
+    adapter: &dyn MagazineAdapter,
+    global: &GlobalArgs,
+    config: &Config,
+    publication: &PublicationConfig,
+    progress: &Progress,
+) -> Result<Client> {
+    let (cookies, browser_profile) = session_sources(global, config, publication);
+    let session = auth::resolve_session(
+        adapter,
+        cookies.as_deref(),
+        browser_profile.as_deref(),
+        config,
+        progress,
+    )?;
+
+    match &session {
+        Some(session) => progress.verbose(&format!(
+            "Using {} cookies for {} from {}",
+            session.cookies.len(),
+            adapter.cookie_domain(),
+            session.source
+        )),
+        None if adapter.login_cookie().is_some() => {
+            let key = adapter.key().to_uppercase();
+            eprintln!(
+                "Warning: no credentials configured ({key}_COOKIE, --cookies, --browser-profile or {key}_USERNAME/{key}_PASSWORD); subscriber content may be inaccessible."
+            )
+        }
+        None => {}
+    }
+
+    auth::client_for(adapter, session.as_ref())
+}
+
+/// Downloads the issue at `url`, caching every page, and builds its EPUB.
+pub fn fetch_issue(
+    url: &str,
+    adapter: &dyn MagazineAdapter,
+    client: &Client,
+    settings: &BuildSettings,
+    progress: &mut Progress,
+) -> Result<PathBuf> {
+    let cache = IssueCache::open(&cache::cache_root()?, adapter.key(), url)?;
+    let pages = Pages::Online {
+        client,
+        delay: settings.delay,
+        cache,
+    };
+    build_issue(url, adapter, settings, &pages, progress)
+}
+
+/// Builds the EPUB for the issue at `url` from `pages`. Returns the output
+/// path, which already existed if `skip_existing` applied.
+pub fn build_issue(
+    url: &str,
+    adapter: &dyn MagazineAdapter,
+    settings: &BuildSettings,
+    pages: &Pages,
+    progress: &mut Progress,
+) -> Result<PathBuf> {
+    let output = &settings.output;
+    if !output.exists() {
+        std::fs::create_dir_all(output)?;
+    }
+
+    progress.next(match pages {
+        Pages::Online { .. } => "Fetching issue HTML…",
+        Pages::Offline { .. } => "Reading cached issue HTML…",
+    });
+    let doc = pages.html(url, progress)?;
+    let mut issue = adapter.extract_issue(&doc, progress);
+
+    let magazine_prefix = match detect_source(url) {
+        Some(MagazineSource::Harpers) => "Harpers",
+        Some(MagazineSource::LondonReview) => "LRB",
+        None => adapter.key(),
+    };
+    let filename = match &settings.naming {
+        Naming::Fixed(name) => name.clone(),
+        Naming::Template(template) => {
+            let fields = TemplateFields {
+                publication: adapter.publication_name().to_string(),
+                prefix: magazine_prefix.to_string(),
+                key: adapter.key().to_string(),
+                title: issue.title.clone(),
+                identity: issue.identity.clone(),
+            };
+            template::render(template, &fields, &settings.render)?
+                .to_string_lossy()
+                .into_owned()
+        }
+    };
+
+    let output_path = output.join(settings.backend.file_name(&filename));
+    if output_path.exists() && settings.skip_existing {
+        progress.done(format!(
+            "{} (already exists, skipped)",
+            output_path.display()
+        ));
+        return Ok(output_path);
+    }
+    if output_path.exists() && !settings.force {
+        return Err(anyhow!(
+            "File '{}' already exists. Use --force to overwrite or --skip-existing to skip it.",
+            output_path.display()
+        ));
+    }
+    if let Some(parent) = output_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let article_length = issue.links.len();
+    progress.next(&format!("Extracting {} articles…", article_length));
+
+    progress.start_articles(article_length);
+    let mut articles = Vec::new();
+    for (i, link) in issue.links.iter().enumerate() {
+        progress.substep(i, article_length, link);
+        let article_doc = pages.html(link, progress)?;
+        let article = adapter.extract_article(&article_doc, progress);
+        progress.article_done(&article.title);
+        articles.push(article);
+    }
+    progress.finish_articles();
+
+    let bodies: Vec<&str> = articles.iter().map(|a| a.body.as_str()).collect();
+    let publisher_css = css::prune(&issue.css, &bodies);
+    progress.verbose(&format!(
+        "Publisher CSS: kept {} of {} bytes",
+        publisher_css.len(),
+        issue.css.len()
+    ));
+    issue.css = style::stylesheet(&publisher_css, &settings.style, &settings.fonts)?;
+    let contents: Vec<NewArticle> = articles
+        .iter()
+        .zip(&issue.links)
+        .map(|(article, link)| NewArticle {
+            title: article.title.clone(),
+            author: article.author.clone(),
+            url: link.clone(),
+            text: library::html_to_text(&article.body),
+        })
+        .collect();
+
+    let downloaded = if issue.cover_image_uri.starts_with("http") && !settings.generated_cover {
+        progress.next("Downloading cover…");
+        let cover = pages
+            .bytes(&issue.cover_image_uri, progress)
+            .and_then(|bytes| cover::process_cover(&bytes, &settings.cover));
+        match cover {
+            Ok(cover) => Some(cover),
+            Err(err) => {
+                progress.info(&format!("{:#}; using a generated cover instead", err));
+                None
+            }
+        }
+    } else {
+        progress.next("Generating cover…");
+        None
+    };
+    // Library views show a coverless book as a blank or broken tile.
+    let cover = match downloaded {
+        Some(cover) => cover,
+        None => cover::process_cover(
+            &cover::generate_cover(&issue, &adapter.cover_colours())?,
+            &settings.cover,
+        )?,
+    };
+
+    let images = if settings.backend.inlines_images() {
+        article_images(&issue.links, &articles, pages, progress)
+    } else {
+        HashMap::new()
+    };
+
+    let model = IssueModel {
+        url: url.to_string(),
+        fonts: settings.fonts.files(&issue, &articles)?,
+        images,
+        issue,
+        articles,
+        cover: Some(cover),
+    };
+    settings.backend.write(&model, &output_path, progress)?;
+    progress.done(output_path.display());
+
+    if let Some(path) = &settings.library {
+        let entry = NewIssue {
+            publication: adapter.key(),
+            url,
+            title: &model.issue.title,
+            identity: &model.issue.identity,
+            output_path: &output_path,
+            articles: &contents,
+        };
+        // The EPUB is already written, so a library problem shouldn't fail the run.
+        if let Err(e) = Library::open(path).and_then(|mut library| library.record(&entry)) {
+            eprintln!(
+                "Warning: could not add {} to the library: {:#}",
+                output_path.display(),
+                e
+            );
+        }
+    }
+
+    // Reported as `DeliveryError`s so callers can tell a failed delivery from
+    // a failed build. Every target is attempted even if an earlier one fails.
+    let copied = settings.device.as_ref().map_or(Ok(()), |device| {
+        device.copy(
+            &output_path,
+            adapter.publication_name(),
+            model.issue.identity.date(),
+            progress,
+        )
+    });
+    let added = settings.calibre.as_ref().map_or(Ok(()), |calibre| {
+        let book = BookMetadata {
+            title: &model.issue.title,
+            publication: adapter.publication_name(),
+            language: adapter.language(),
+            url,
+            identity: &model.issue.identity,
+            articles: contents.iter().map(|a| a.title.as_str()).collect(),
+        };
+        calibre.add(&output_path, &book, model.cover.as_deref(), progress)
+    });
+    let sent = settings.delivery.as_ref().map_or(Ok(()), |delivery| {
+        delivery
+            .send(&output_path, &model.issue.title, progress)
+            .inspect(|_| progress.info(&format!("Emailed to {}", delivery.recipients.join(", "))))
+    });
+    let mut failures = [copied, added, sent].into_iter().filter_map(Result::err);
+    if let Some(first) = failures.next() {
+        for e in failures {
+            eprintln!("Error: {:#}", e);
+        }
+        return Err(first.into());
+    }
+
+    Ok(output_path)
+}
+
+/// Downloads the images in the articles, keyed by absolute URL, through the
+/// page cache so `rebuild` can inline them too. An image that can't be
+/// fetched is left out of the output rather than failing the build.
+fn article_images(
+    links: &[String],
+    articles: &[ArticleData],
+    pages: &Pages,
+    progress: &Progress,
+) -> HashMap<String, Vec<u8>> {
+    let mut images = HashMap::new();
+    for (link, article) in links.iter().zip(articles) {
+        for url in html::image_urls(&article.body, link) {
+            if images.contains_key(&url) {
+                continue;
+            }
+            match pages.bytes(&url, progress) {
+                Ok(bytes) => {
+                    images.insert(url, bytes);
+                }
+                Err(e) => progress.verbose(&format!("Image left out: {:#}", e)),
+            }
+        }
+    }
+    images
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::london_review_adapter::LondonReviewAdapter;
+    use crate::output::OutputFormat;
+    use crate::pdf::PdfOptions;
+    use crate::progress::Verbosity;
+    use crate::style::Theme;
+    use crate::txt::TextOptions;
+    use std::fs;
+    use std::path::Path;
+
+    fn backend(format: OutputFormat) -> Box<dyn OutputBackend> {
+        format.backend(PdfOptions::default(), TextOptions::default())
+    }
+
+    const URL: &str = "https://www.lrb.co.uk/the-paper/v99/n03";
+
+    /// An LRB issue in a fresh page cache under a temporary root: the issue
+    /// page, every article and a cover that isn't an image.
+    struct CachedIssue {
+        root: PathBuf,
+        pages: Pages<'static>,
+        links: Vec<String>,
+    }
+
+    impl CachedIssue {
+        fn new(name: &str) -> Self {
+            Self::with_cover(name, Some(b"not really a jpeg"))
+        }
+
+        /// The same issue with the given cover bytes, or none cached at all.
+        fn with_cover(name: &str, cover: Option<&[u8]>) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "magaziner-pipeline-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&root);
+            let adapter = LondonReviewAdapter;
+            let issue_html = fs::read_to_string("src/test/lrb/issue.html").unwrap();
+            let article_html = fs::read("src/test/lrb/article.html").unwrap();
+            let cache = IssueCache::open(&root.join("pages"), adapter.key(), URL).unwrap();
+            cache.store(URL, issue_html.as_bytes()).unwrap();
+            let issue = adapter.extract_issue(
+                &Html::parse_document(&issue_html),
+                &Progress::new(Verbosity::Quiet),
+            );
+            for link in &issue.links {
+                cache.store(link, &article_html).unwrap();
+            }
+            if let Some(cover) = cover {
+                cache.store(&issue.cover_image_uri, cover).unwrap();
+            }
+            CachedIssue {
+                root,
+                pages: Pages::Offline { cache },
+                links: issue.links,
+            }
+        }
+
+        fn settings(&self, format: OutputFormat) -> BuildSettings {
+            BuildSettings {
+                delay: 0,
+                force: true,
+                skip_existing: false,
+                naming: Naming::Template("{prefix}-v{volume}-n{number}".into()),
+                render: RenderOptions {
+                    slugify: false,
+                    charset: Default::default(),
+                },
+                output: self.root.join("out"),
+                backend: backend(format),
+                style: StyleOptions {
+                    publisher_css: true,
+                    ..StyleOptions::default()
+                },
+                fonts: FontOptions::default(),
+                generated_cover: false,
+                cover: CoverOptions::default(),
+                library: None,
+                delivery: None,
+                device: None,
+                calibre: None,
+            }
+        }
+
+        fn build(&self, settings: &BuildSettings) -> PathBuf {
+            let mut progress = Progress::new(Verbosity::Quiet);
+            build_issue(
+                URL,
+                &LondonReviewAdapter,
+                settings,
+                &self.pages,
+                &mut progress,
+            )
+            .unwrap()
+        }
+    }
+
+    impl Drop for CachedIssue {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    fn read_entry(book: &Path, name: &str) -> Vec<u8> {
+        let mut zip = zip::ZipArchive::new(fs::File::open(book).unwrap()).unwrap();
+        let mut bytes = Vec::new();
+        std::io::Read::read_to_end(&mut zip.by_name(name).unwrap(), &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_rebuild_from_cache_without_network() {
+        let cached = CachedIssue::new("rebuild");
+        let mut settings = cached.settings(OutputFormat::Epub);
+        settings.force = false;
+        settings.library = Some(cached.root.join("library.db"));
+        let path = cached.build(&settings);
+
+        assert_eq!(path, cached.root.join("out").join("LRB-v99-n03.epub"));
+        assert!(fs::metadata(&path).unwrap().len() > 0);
+        let library = Library::open(&cached.root.join("library.db")).unwrap();
+        let issues = library.issues(Some("lrb")).unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].url, URL);
+        assert_eq!(
+            library.articles(issues[0].id).unwrap().len(),
+            cached.links.len()
+        );
+    }
+
+    #[test]
+    fn test_kepub_chapters_have_kobo_spans() {
+        let cached = CachedIssue::new("kepub");
+        let path = cached.build(&cached.settings(OutputFormat::Kepub));
+        assert_eq!(path, cached.root.join("out").join("LRB-v99-n03.kepub.epub"));
+        let chapter = String::from_utf8(read_entry(&path, "OEBPS/article0.xhtml")).unwrap();
+        assert!(chapter.contains(r#"<span class="koboSpan" id="kobo.1.1">"#));
+    }
+
+    #[test]
+    fn test_chapters_link_the_layered_stylesheet() {
+        let cached = CachedIssue::new("style");
+        let mut settings = cached.settings(OutputFormat::Epub);
+        settings.style.theme = Some(Theme::LargePrint);
+        let path = cached.build(&settings);
+
+        let chapter = String::from_utf8(read_entry(&path, "OEBPS/article0.xhtml")).unwrap();
+        assert!(chapter.contains(r#"href="stylesheet.css""#));
+        assert!(!chapter.contains("<style>"));
+        let css = String::from_utf8(read_entry(&path, "OEBPS/stylesheet.css")).unwrap();
+        assert!(css.contains("h1.article-title") && css.contains("font-size: 1.4em"));
+    }
+
+    #[test]
+    fn test_embedded_fonts_are_subset() {
+        let cached = CachedIssue::new("fonts");
+        let mut settings = cached.settings(OutputFormat::Epub);
+        settings.fonts =
+            fonts::resolve_fonts(&[PathBuf::from("assets/fonts/DejaVuSerif.ttf")], true, None)
+                .unwrap();
+        let path = cached.build(&settings);
+
+        let css = String::from_utf8(read_entry(&path, "OEBPS/stylesheet.css")).unwrap();
+        assert!(css.contains(r#"src: url("fonts/dejavuserif.ttf")"#));
+        assert!(read_entry(&path, "OEBPS/fonts/dejavuserif.ttf").len() < 150_000);
+    }
+
+    #[test]
+    fn test_unreadable_cover_is_replaced_by_a_generated_one() {
+        let cached = CachedIssue::new("cover");
+        let path = cached.build(&cached.settings(OutputFormat::Epub));
+        assert_eq!(&read_entry(&path, "OEBPS/cover.jpg")[..2], [0xff, 0xd8]);
+    }
+
+    #[test]
+    fn test_missing_cover_is_replaced_by_a_generated_one() {
+        let cached = CachedIssue::with_cover("no-cover", None);
+        let path = cached.build(&cached.settings(OutputFormat::Epub));
+        assert_eq!(&read_entry(&path, "OEBPS/cover.jpg")[..2], [0xff, 0xd8]);
+    }
+
+    fn assert_builds(format: OutputFormat, name: &str) {
+        let cached = CachedIssue::new(&format!("{:?}", format));
+        cached.build(&cached.settings(format));
+        assert!(cached.root.join("out").join(name).is_file());
+    }
+
+    #[test]
+    fn test_html_from_cache() {
+        assert_builds(OutputFormat::Html, "LRB-v99-n03.html");
+    }
+
+    #[test]
+    fn test_markdown_from_cache() {
+        assert_builds(OutputFormat::Markdown, "LRB-v99-n03/index.md");
+    }
+
+    #[test]
+    fn test_pdf_from_cache() {
+        assert_builds(OutputFormat::Pdf, "LRB-v99-n03.pdf");
+    }
+
+    #[test]
+    fn test_txt_from_cache() {
+        assert_builds(OutputFormat::Txt, "LRB-v99-n03.txt");
+    }
+
+    #[test]
+    fn test_error_pages_are_not_cached() {
+        use std::io::{BufRead, BufReader, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/the-paper/v99/n03",
+            listener.local_addr().unwrap()
+        );
+        std::thread::spawn(move || {
+            let mut stream = listener.incoming().next().unwrap().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            stream
+                .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\nConnection: close\r\n\r\nNot found")
+                .unwrap();
+        });
+
+        let root = std::env::temp_dir().join(format!("magaziner-404-{}", std::process::id()));
+        let client = Client::new();
+        let pages = Pages::Online {
+            client: &client,
+            delay: 0,
+            cache: IssueCache::open(&root, "lrb", &url).unwrap(),
+        };
+        let err = pages
+            .html(&url, &Progress::new(Verbosity::Quiet))
+            .unwrap_err();
+        assert!(err.to_string().contains("404"), "unexpected error: {}", err);
+        let Pages::Online { cache, .. } = pages else {
+            unreachable!()
+        };
+        assert!(cache.load(&url).unwrap().is_none());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_missing_cached_page_is_an_error() {
+        let root = std::env::temp_dir().join(format!("magaziner-missing-{}", std::process::id()));
+        let url = "https://harpers.org/archive/2026/02";
+        let cache = IssueCache::open(&root, "harpers", url).unwrap();
+        let err = Pages::Offline { cache }
+            .html(url, &Progress::new(Verbosity::Quiet))
+            .unwrap_err();
+        assert!(err.to_string().contains("not in the page cache"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,3 +1,8 @@
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use std::cell::{Cell, RefCell};
+use std::io::IsTerminal;
+
+#[derive(Clone, Copy)]
 pub enum Verbosity {
     Quiet,
     Normal,
@@ -8,43 +13,167 @@ pub struct Progress {
     total: usize,
     current: usize,
     verbosity: Verbosity,
+    interactive: bool,
+    bar: RefCell<Option<ProgressBar>>,
+    bytes: Cell<u64>,
 }
 
 impl Progress {
     pub fn new(verbosity: Verbosity) -> Self {
+        let interactive = !matches!(verbosity, Verbosity::Quiet) && std::io::stdout().is_terminal();
         Self {
             total: 5,
             current: 0,
             verbosity,
+            interactive,
+            bar: RefCell::new(None),
+            bytes: Cell::new(0),
         }
     }
 
     pub fn next(&mut self, message: &str) {
         self.current += 1;
         if !matches!(self.verbosity, Verbosity::Quiet) {
-            println!("[{}/{}] {}", self.current, self.total, message);
+            self.println(&format!("[{}/{}] {}", self.current, self.total, message));
         }
     }
 
     pub fn done(&self, output: impl std::fmt::Display) {
         if !matches!(self.verbosity, Verbosity::Quiet) {
-            println!("Successfully completed: {}", output);
+            self.println(&format!("Successfully completed: {}", output));
+        }
+    }
+
+    /// Starts the article download phase. On a TTY this draws a live bar with
+    /// elapsed time, ETA and bytes downloaded; otherwise it is a no-op and
+    /// `substep` falls back to one line per article.
+    pub fn start_articles(&self, total: usize) {
+        if !self.interactive {
+            return;
+        }
+        let style = ProgressStyle::with_template(
+            "{spinner} [{elapsed_precise}] [{wide_bar}] {pos}/{len} · ETA {eta} · {prefix}\n  {wide_msg}",
+        )
+        .expect("progress template is valid")
+        .progress_chars("=> ");
+        let bar = ProgressBar::new(total as u64).with_style(style);
+        bar.set_prefix(HumanBytes(self.bytes.get()).to_string());
+        bar.enable_steady_tick(std::time::Duration::from_millis(120));
+        *self.bar.borrow_mut() = Some(bar);
+    }
+
+    /// Reports the article about to be fetched. Until the page arrives the
+    /// live bar names it from its URL; `article_done` puts the title there.
+    pub fn substep(&mut self, current: usize, total: usize, url: &str) {
+        if matches!(self.verbosity, Verbosity::Quiet) {
+            return;
+        }
+        let human_readable_current = current + 1;
+        if let Some(bar) = self.bar.borrow().as_ref() {
+            bar.set_position(current as u64);
+            bar.set_message(article_label(url));
+            return;
+        }
+        println!(
+            "[{}/{}] Fetching article {}/{}…",
+            self.current, self.total, human_readable_current, total
+        );
+    }
+
+    /// Records a finished article, advancing the live bar and showing its
+    /// title there.
+    pub fn article_done(&self, title: &str) {
+        if let Some(bar) = self.bar.borrow().as_ref() {
+            bar.set_message(title.trim().to_string());
+            bar.inc(1);
+        }
+        self.verbose(&format!("Fetched: {}", title.trim()));
+    }
+
+    pub fn finish_articles(&self) {
+        if let Some(bar) = self.bar.borrow_mut().take() {
+            bar.finish_and_clear();
+        }
+    }
+
+    pub fn add_bytes(&self, count: usize) {
+        self.bytes.set(self.bytes.get() + count as u64);
+        if let Some(bar) = self.bar.borrow().as_ref() {
+            bar.set_prefix(HumanBytes(self.bytes.get()).to_string());
         }
     }
 
-    pub fn substep(&mut self, current: usize, total: usize) {
+    /// A line that follows the numbered steps, such as a delivery report.
+    pub fn info(&self, message: &str) {
         if !matches!(self.verbosity, Verbosity::Quiet) {
-            let human_readable_current = current + 1;
-            println!(
-                "[{}/{}] Fetching article {}/{}…",
-                self.current, self.total, human_readable_current, total
-            );
+            self.println(message);
         }
     }
 
     pub fn verbose(&self, message: &str) {
         if matches!(self.verbosity, Verbosity::Verbose) {
-            println!("  {}", message);
+            self.println(&format!("  {}", message));
+        }
+    }
+
+    // Prints above the live bar when one is drawn so the two don't interleave.
+    fn println(&self, line: &str) {
+        match self.bar.borrow().as_ref() {
+            Some(bar) => bar.println(line),
+            None => println!("{}", line),
         }
     }
 }
+
+/// A readable name for an article from the last segment of its URL:
+/// `.../v47/n06/jane-doe/bread-and-roses` becomes `bread and roses`.
+fn article_label(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let slug = path
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    let slug = slug
+        .strip_suffix(".html")
+        .or_else(|| slug.strip_suffix(".htm"))
+        .unwrap_or(slug);
+    if slug.is_empty() {
+        url.to_string()
+    } else {
+        slug.replace(['-', '_'], " ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_article_label_names_the_article_from_its_url() {
+        assert_eq!(
+            article_label("https://www.lrb.co.uk/the-paper/v47/n06/jane-doe/bread-and-roses"),
+            "bread and roses"
+        );
+        assert_eq!(
+            article_label("https://harpers.org/archive/2026/02/the-long-view/?src=toc"),
+            "the long view"
+        );
+    }
+
+    #[test]
+    fn test_bar_shows_the_title_once_the_article_is_fetched() {
+        let mut progress = Progress::new(Verbosity::Normal);
+        *progress.bar.borrow_mut() = Some(ProgressBar::hidden());
+        let message = |progress: &Progress| progress.bar.borrow().as_ref().unwrap().message();
+
+        progress.substep(
+            0,
+            2,
+            "https://www.lrb.co.uk/the-paper/v47/n06/jane-doe/bread-and-roses",
+        );
+        assert_eq!(message(&progress), "bread and roses");
+        progress.article_done(" Bread and Roses\n");
+        assert_eq!(message(&progress), "Bread and Roses");
+    }
+}
@@ -0,0 +1,220 @@
+use crate::fonts::FontOptions;
+use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Layout every book starts from: the title page and article headings.
+pub const BOOK_CSS: &str = r#"body { font-family: serif; margin: 2em; }
+body.title-page { text-align: center; margin-top: 40%; }
+h1.article-title { font-size: 2em; text-align: center; margin-top: 1em; margin-bottom: 1.5em; }
+"#;
+
+const SERIF_CSS: &str = r#"body { font-family: Georgia, "Times New Roman", serif; line-height: 1.5; }
+p { margin: 0; text-indent: 1.2em; text-align: justify; hyphens: auto; }
+h1 + p, h2 + p, h3 + p, blockquote p { text-indent: 0; }
+h1, h2, h3 { font-weight: normal; }
+"#;
+
+const EINK_CSS: &str = r#"* { color: #000 !important; background: transparent !important; }
+body { font-family: serif; line-height: 1.45; }
+a { text-decoration: underline; }
+blockquote { border-left: 3px solid #000; padding-left: 0.8em; }
+"#;
+
+const LARGE_PRINT_CSS: &str = r#"body { font-size: 1.4em; line-height: 1.6; margin: 1em; }
+p { text-align: left; hyphens: none; margin: 0 0 0.8em; }
+h1.article-title { font-size: 1.6em; }
+"#;
+
+/// A built-in reading theme, layered over the publisher's stylesheet.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    /// Justified, indented paragraphs in a book serif.
+    Serif,
+    /// Pure black on white for e-ink screens.
+    Eink,
+    /// Bigger type, more leading and ragged-right text.
+    LargePrint,
+}
+
+impl Theme {
+    fn css(self) -> &'static str {
+        match self {
+            Theme::Serif => SERIF_CSS,
+            Theme::Eink => EINK_CSS,
+            Theme::LargePrint => LARGE_PRINT_CSS,
+        }
+    }
+}
+
+/// How `--css` combines with the book stylesheet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CssMode {
+    /// Add the file after everything else, so its rules win.
+    #[default]
+    Append,
+    /// Use the file as the whole stylesheet.
+    Replace,
+}
+
+/// The `[style]` config section.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StyleConfig {
+    pub css: Option<PathBuf>,
+    pub css_mode: Option<CssMode>,
+    pub theme: Option<Theme>,
+    pub publisher_css: Option<bool>,
+    #[serde(default)]
+    pub fonts: Vec<PathBuf>,
+    pub subset_fonts: Option<bool>,
+}
+
+/// What goes into the book's stylesheet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StyleOptions {
+    /// User stylesheet, read when the issue is built.
+    pub css: Option<PathBuf>,
+    pub css_mode: CssMode,
+    pub theme: Option<Theme>,
+    /// Keep the stylesheet scraped from the publisher's site.
+    pub publisher_css: bool,
+}
+
+/// Layers `--css`, `--css-mode`, `--theme` and `--no-publisher-css` over the
+/// publication's `stylesheet` and the config file's `[style]` section.
+pub fn resolve_style(
+    css: Option<&Path>,
+    css_mode: Option<CssMode>,
+    theme: Option<Theme>,
+    no_publisher_css: bool,
+    stylesheet: Option<&Path>,
+    config: Option<&StyleConfig>,
+) -> Result<StyleOptions> {
+    let css = css
+        .or(stylesheet)
+        .or(config.and_then(|c| c.css.as_deref()))
+        .map(Path::to_path_buf);
+    let css_mode = css_mode
+        .or(config.and_then(|c| c.css_mode))
+        .unwrap_or_default();
+    if let Some(path) = &css
+        && !path.is_file()
+    {
+        return Err(anyhow!("Stylesheet {} not found", path.display()));
+    }
+    if css_mode == CssMode::Replace && css.is_none() {
+        return Err(anyhow!("--css-mode replace needs a stylesheet from --css"));
+    }
+    Ok(StyleOptions {
+        css,
+        css_mode,
+        theme: theme.or(config.and_then(|c| c.theme)),
+        publisher_css: !no_publisher_css && config.and_then(|c| c.publisher_css).unwrap_or(true),
+    })
+}
+
+/// The book's stylesheet: `BOOK_CSS`, then the publisher's CSS, the theme,
+/// embedded fonts and the user's file, so later sheets win. `replace` keeps
+/// only the fonts and the user's file.
+pub fn stylesheet(
+    publisher_css: &str,
+    options: &StyleOptions,
+    fonts: &FontOptions,
+) -> Result<String> {
+    let user = match &options.css {
+        Some(path) => Some(
+            std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read stylesheet {}", path.display()))?,
+        ),
+        None => None,
+    };
+    if options.css_mode == CssMode::Replace {
+        return Ok(fonts.css() + &user.unwrap_or_default());
+    }
+
+    let mut sheet = BOOK_CSS.to_string();
+    if options.publisher_css && !publisher_css.trim().is_empty() {
+        sheet.push('\n');
+        sheet.push_str(publisher_css);
+    }
+    if let Some(theme) = options.theme {
+        sheet.push('\n');
+        sheet.push_str(theme.css());
+    }
+    if !fonts.fonts.is_empty() {
+        sheet.push('\n');
+        sheet.push_str(&fonts.css());
+    }
+    if let Some(user) = user {
+        sheet.push('\n');
+        sheet.push_str(&user);
+    }
+    Ok(sheet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_css(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("magaziner-{}-{}.css", name, std::process::id()));
+        std::fs::write(&path, "p { color: red; }").unwrap();
+        path
+    }
+
+    #[test]
+    fn test_stylesheet_layers_publisher_theme_and_user_css() {
+        let path = user_css("append");
+        let options =
+            resolve_style(Some(&path), None, Some(Theme::Eink), false, None, None).unwrap();
+        let sheet = stylesheet(".lrb { color: blue; }", &options, &FontOptions::default()).unwrap();
+
+        let book = sheet.find("h1.article-title").unwrap();
+        let publisher = sheet.find(".lrb").unwrap();
+        let theme = sheet.find("!important").unwrap();
+        let user = sheet.find("color: red").unwrap();
+        assert!(book < publisher && publisher < theme && theme < user);
+
+        let options = StyleOptions {
+            publisher_css: false,
+            ..options
+        };
+        assert!(
+            !stylesheet(".lrb { color: blue; }", &options, &FontOptions::default())
+                .unwrap()
+                .contains(".lrb")
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_replace_keeps_only_user_css() {
+        let path = user_css("replace");
+        let config = StyleConfig {
+            css_mode: Some(CssMode::Replace),
+            ..StyleConfig::default()
+        };
+        let options = resolve_style(
+            None,
+            None,
+            Some(Theme::Serif),
+            false,
+            Some(&path),
+            Some(&config),
+        )
+        .unwrap();
+        assert_eq!(
+            stylesheet(".lrb { color: blue; }", &options, &FontOptions::default()).unwrap(),
+            "p { color: red; }"
+        );
+
+        let err = resolve_style(None, None, None, false, None, Some(&config)).unwrap_err();
+        assert!(err.to_string().contains("--css-mode replace"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
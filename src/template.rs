@@ -0,0 +1,280 @@
+use crate::identity::IssueIdentity;
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use deunicode::deunicode;
+use regex::Regex;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+pub const DEFAULT_TEMPLATE: &str = "{prefix} - {title}";
+
+const FIELDS: &[&str] = &[
+    "publication",
+    "prefix",
+    "key",
+    "volume",
+    "number",
+    "year",
+    "month",
+    "month_name",
+    "season",
+    "day",
+    "date",
+    "title",
+];
+
+/// Which characters may appear in rendered path components.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Charset {
+    /// Keep Unicode; only remove characters that are invalid in file names.
+    #[default]
+    Unicode,
+    /// Transliterate to ASCII (é → e, · → -).
+    Ascii,
+    /// ASCII letters, digits, `.`, `_` and `-` only; everything else becomes `_`.
+    Portable,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TemplateFields {
+    pub publication: String,
+    pub prefix: String,
+    pub key: String,
+    pub title: String,
+    pub identity: IssueIdentity,
+}
+
+impl TemplateFields {
+    fn get(&self, name: &str) -> Option<String> {
+        let identity = &self.identity;
+        match name {
+            "publication" => Some(self.publication.clone()),
+            "prefix" => Some(self.prefix.clone()),
+            "key" => Some(self.key.clone()),
+            "volume" => identity.volume.map(|v| v.to_string()),
+            "number" => identity.number.map(|n| format!("{:02}", n)),
+            "year" => identity.year.map(|y| y.to_string()),
+            "month" => identity.month.map(|m| format!("{:02}", m)),
+            "month_name" => identity.month_name().map(str::to_string),
+            "season" => identity.season.clone(),
+            "day" => identity.day.map(|d| format!("{:02}", d)),
+            "date" => identity.date_string(),
+            "title" => Some(self.title.clone()),
+            _ => None,
+        }
+    }
+}
+
+pub struct RenderOptions {
+    pub slugify: bool,
+    pub charset: Charset,
+}
+
+/// Renders a template such as `{prefix}/{year}/{prefix|slug}-v{volume}-n{number}`
+/// into a relative output path, without the `.epub` extension. `/` in the
+/// template separates directories; field values can never introduce one.
+///
+/// Each placeholder may apply filters: `slug`, `lower`, `upper`, `ascii`.
+pub fn render(template: &str, fields: &TemplateFields, options: &RenderOptions) -> Result<PathBuf> {
+    let placeholder = Regex::new(r"\{([a-z_]+)((?:\|[a-z]+)*)\}").unwrap();
+    let mut path = PathBuf::new();
+
+    for component in template.split('/').filter(|c| !c.is_empty()) {
+        let mut error = None;
+        let rendered = placeholder.replace_all(component, |caps: &regex::Captures| {
+            let name = &caps[1];
+            if !FIELDS.contains(&name) {
+                error.get_or_insert_with(|| {
+                    anyhow!(
+                        "Unknown template field {{{}}}. Available fields: {}",
+                        name,
+                        FIELDS.join(", ")
+                    )
+                });
+                return String::new();
+            }
+            let Some(mut value) = fields.get(name) else {
+                error.get_or_insert_with(|| {
+                    anyhow!(
+                        "Template field {{{}}} is not available for {}",
+                        name,
+                        fields.publication
+                    )
+                });
+                return String::new();
+            };
+            if options.slugify {
+                value = slugify(&value);
+            }
+            for filter in caps[2].split('|').filter(|f| !f.is_empty()) {
+                value = match filter {
+                    "slug" => slugify(&value),
+                    "lower" => value.to_lowercase(),
+                    "upper" => value.to_uppercase(),
+                    "ascii" => deunicode(&value),
+                    other => {
+                        error.get_or_insert_with(|| {
+                            anyhow!("Unknown template filter '{}' on {{{}}}", other, name)
+                        });
+                        value
+                    }
+                };
+            }
+            value.replace(['/', '\\'], "-")
+        });
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        let cleaned = sanitize_component(&rendered, options.charset);
+        if cleaned.is_empty() || cleaned == "." || cleaned == ".." {
+            return Err(anyhow!(
+                "Template '{}' renders an empty or invalid path component",
+                template
+            ));
+        }
+        path.push(cleaned);
+    }
+
+    if path.as_os_str().is_empty() {
+        return Err(anyhow!("Template '{}' renders an empty path", template));
+    }
+    if path.extension().is_some_and(|ext| ext == "epub") {
+        path.set_extension("");
+    }
+    Ok(path)
+}
+
+pub fn slugify(value: &str) -> String {
+    let mut slug = String::new();
+    for c in deunicode(value).to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.ends_with('-') && !slug.is_empty() {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+fn sanitize_component(value: &str, charset: Charset) -> String {
+    let value = match charset {
+        Charset::Unicode => value.to_string(),
+        Charset::Ascii | Charset::Portable => deunicode(value),
+    };
+    let cleaned: String = value
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| match (charset, c) {
+            (_, ':' | '*' | '?' | '"' | '<' | '>' | '|' | '\\') => '_',
+            (Charset::Portable, c) if !(c.is_ascii_alphanumeric() || "._-".contains(c)) => '_',
+            (_, c) => c,
+        })
+        .collect();
+    cleaned.trim().trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lrb_fields() -> TemplateFields {
+        TemplateFields {
+            publication: "London Review of Books".into(),
+            prefix: "LRB".into(),
+            key: "lrb".into(),
+            title: "Vol. 47 No. 6 · 20 March 2025".into(),
+            identity: IssueIdentity {
+                volume: Some(47),
+                number: Some(6),
+                ..IssueIdentity::from_date_text("20 March 2025")
+            },
+        }
+    }
+
+    fn harpers_fields() -> TemplateFields {
+        TemplateFields {
+            publication: "Harper's Magazine".into(),
+            prefix: "Harpers".into(),
+            key: "harpers".into(),
+            title: "February 2026".into(),
+            identity: IssueIdentity::from_date_text("February 2026"),
+        }
+    }
+
+    fn unicode() -> RenderOptions {
+        RenderOptions {
+            slugify: false,
+            charset: Charset::Unicode,
+        }
+    }
+
+    #[test]
+    fn test_fields_from_identity() {
+        let fields = lrb_fields();
+        assert_eq!(fields.get("volume").as_deref(), Some("47"));
+        assert_eq!(fields.get("number").as_deref(), Some("06"));
+        assert_eq!(fields.get("date").as_deref(), Some("2025-03-20"));
+        assert_eq!(fields.get("month_name").as_deref(), Some("March"));
+        assert_eq!(harpers_fields().get("date").as_deref(), Some("2026-02"));
+    }
+
+    #[test]
+    fn test_default_template_matches_previous_names() {
+        let path = render(DEFAULT_TEMPLATE, &lrb_fields(), &unicode()).unwrap();
+        assert_eq!(path, PathBuf::from("LRB - Vol. 47 No. 6 · 20 March 2025"));
+    }
+
+    #[test]
+    fn test_directory_template_with_filters() {
+        let path = render(
+            "{prefix}/{year}/{prefix|lower}-v{volume}-n{number}.epub",
+            &lrb_fields(),
+            &unicode(),
+        )
+        .unwrap();
+        assert_eq!(path, PathBuf::from("LRB/2025/lrb-v47-n06"));
+    }
+
+    #[test]
+    fn test_slugify_and_charsets() {
+        let slugged = render(
+            "{title}",
+            &lrb_fields(),
+            &RenderOptions {
+                slugify: true,
+                charset: Charset::Unicode,
+            },
+        )
+        .unwrap();
+        assert_eq!(slugged, PathBuf::from("vol-47-no-6-20-march-2025"));
+
+        let portable = render(
+            "{title}",
+            &lrb_fields(),
+            &RenderOptions {
+                slugify: false,
+                charset: Charset::Portable,
+            },
+        )
+        .unwrap();
+        assert!(portable.to_str().unwrap().is_ascii());
+        assert!(!portable.to_str().unwrap().contains(' '));
+    }
+
+    #[test]
+    fn test_field_values_cannot_create_directories() {
+        let mut fields = lrb_fields();
+        fields.title = "AC/DC: Live".into();
+        let path = render("{title}", &fields, &unicode()).unwrap();
+        assert_eq!(path, PathBuf::from("AC-DC_ Live"));
+    }
+
+    #[test]
+    fn test_unknown_and_missing_fields_error() {
+        assert!(render("{volum}", &lrb_fields(), &unicode()).is_err());
+        let err = render("{volume}", &harpers_fields(), &unicode()).unwrap_err();
+        assert!(err.to_string().contains("not available"));
+    }
+}
@@ -0,0 +1,310 @@
+use crate::adapter::{ArticleData, IssueData};
+use crate::blocks::{Block, BlockKind, Inline, Notes, blocks};
+use crate::output::{IssueModel, OutputBackend};
+use crate::progress::Progress;
+use crate::template::slugify;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+const DEFAULT_WRAP: usize = 72;
+
+/// The `[text]` config section.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TextConfig {
+    pub wrap: Option<usize>,
+    pub per_article: Option<bool>,
+}
+
+/// Layout of a plain-text build.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextOptions {
+    /// Line width in columns; 0 keeps each paragraph on one line.
+    pub wrap: usize,
+    /// Write a folder with one file per article instead of one file.
+    pub per_article: bool,
+}
+
+impl Default for TextOptions {
+    fn default() -> Self {
+        TextOptions {
+            wrap: DEFAULT_WRAP,
+            per_article: false,
+        }
+    }
+}
+
+/// Layers `--wrap` and `--per-article` over the config file's `[text]` section.
+pub fn resolve_text(
+    wrap: Option<usize>,
+    per_article: bool,
+    config: Option<&TextConfig>,
+) -> TextOptions {
+    TextOptions {
+        wrap: wrap.or(config.and_then(|c| c.wrap)).unwrap_or(DEFAULT_WRAP),
+        per_article: per_article || config.and_then(|c| c.per_article).unwrap_or(false),
+    }
+}
+
+/// Writes a `.txt` file, or a folder of them with `per_article`.
+pub struct TextBackend {
+    pub options: TextOptions,
+}
+
+impl OutputBackend for TextBackend {
+    fn file_name(&self, stem: &str) -> String {
+        if self.options.per_article {
+            stem.to_string()
+        } else {
+            format!("{}.txt", stem)
+        }
+    }
+
+    fn write(&self, model: &IssueModel, path: &Path, progress: &mut Progress) -> Result<()> {
+        progress.next("Converting articles to text…");
+        write_text(path, &model.issue, &model.articles, &self.options, progress)
+    }
+}
+
+/// Writes the issue as plain text: one file at `path`, or with
+/// `per_article` a folder at `path` holding one `NN-title.txt` per article.
+/// Footnotes are read in place, in brackets after their marker.
+pub fn write_text(
+    path: &Path,
+    issue: &IssueData,
+    articles: &[ArticleData],
+    options: &TextOptions,
+    progress: &mut Progress,
+) -> Result<()> {
+    if !options.per_article {
+        let mut text = issue_header(issue);
+        text.push_str("\nContents\n\n");
+        for (i, article) in articles.iter().enumerate() {
+            let number = format!("{}. ", i + 1);
+            let mut entry = format!("{}{}", number, article.title);
+            if let Some(author) = &article.author {
+                entry.push_str(&format!(" — {}", author));
+            }
+            let hang = " ".repeat(number.len());
+            text.push_str(&fill(&[entry], "", &hang, options));
+        }
+        for article in articles {
+            text.push_str("\n\n");
+            text.push_str(&article_text(article, options));
+        }
+        progress.next("Saving text…");
+        return fs::write(path, text)
+            .with_context(|| format!("Failed to write {}", path.display()));
+    }
+
+    let mut files = Vec::new();
+    for (i, article) in articles.iter().enumerate() {
+        let slug = slugify(&article.title);
+        let name = if slug.is_empty() {
+            format!("{:02}.txt", i + 1)
+        } else {
+            format!("{:02}-{}.txt", i + 1, slug)
+        };
+        let text = format!(
+            "{}\n{}",
+            issue_header(issue),
+            article_text(article, options)
+        );
+        files.push((name, text));
+    }
+
+    progress.next("Saving text…");
+    fs::create_dir_all(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    for entry in fs::read_dir(path)? {
+        let file = entry?.path();
+        if file.extension().is_some_and(|ext| ext == "txt") {
+            fs::remove_file(file)?;
+        }
+    }
+    for (name, text) in files {
+        fs::write(path.join(name), text)?;
+    }
+    Ok(())
+}
+
+fn issue_header(issue: &IssueData) -> String {
+    let mut header = format!("{}\n{}\n", issue.publication_name, issue.title);
+    if let Some(date) = issue.identity.long_date()
+        && !issue.title.contains(&date)
+    {
+        header.push_str(&format!("{}\n", date));
+    }
+    header
+}
+
+fn article_text(article: &ArticleData, options: &TextOptions) -> String {
+    let mut text = underline(&article.title, '=');
+    if let Some(author) = &article.author {
+        text.push_str(&format!("\nby {}\n", author));
+    }
+    for block in blocks(&article.body, Notes::Inline) {
+        text.push('\n');
+        match block {
+            Block::Rule => text.push_str("* * *\n"),
+            Block::Text { kind, inlines } => {
+                let lines = lines(&inlines);
+                match kind {
+                    BlockKind::Heading => text.push_str(&underline(&lines.join(" "), '-')),
+                    BlockKind::Quote => text.push_str(&fill(&lines, "    ", "    ", options)),
+                    BlockKind::Item => {
+                        // Continuation lines hang under the text after the bullet.
+                        let marker = lines[0].split(' ').next().unwrap_or_default();
+                        let hang = " ".repeat(marker.chars().count() + 1);
+                        text.push_str(&fill(&lines, "", &hang, options));
+                    }
+                    BlockKind::Body | BlockKind::Caption => {
+                        text.push_str(&fill(&lines, "", "", options))
+                    }
+                }
+            }
+        }
+    }
+    text
+}
+
+/// The block's text, split at `<br>`s.
+fn lines(inlines: &[Inline]) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    for inline in inlines {
+        match inline {
+            Inline::Break => lines.push(String::new()),
+            Inline::Text { text, space, .. } => {
+                let line = lines.last_mut().expect("always one line");
+                if *space {
+                    line.push(' ');
+                }
+                line.push_str(text);
+            }
+        }
+    }
+    lines
+}
+
+/// Wraps each line of a block, the first at `first` indent and the rest,
+/// including lines after a `<br>`, at `rest`.
+fn fill(lines: &[String], first: &str, rest: &str, options: &TextOptions) -> String {
+    let mut out = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        let initial = if i == 0 { first } else { rest };
+        if options.wrap == 0 {
+            out.push_str(initial);
+            out.push_str(line);
+        } else {
+            let wrap = textwrap::Options::new(options.wrap)
+                .initial_indent(initial)
+                .subsequent_indent(rest);
+            out.push_str(&textwrap::fill(line, wrap));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn underline(text: &str, rule: char) -> String {
+    let width = text.chars().count();
+    format!("{}\n{}\n", text, rule.to_string().repeat(width))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IssueIdentity;
+    use crate::progress::Verbosity;
+
+    fn issue() -> IssueData {
+        IssueData {
+            links: vec!["https://www.lrb.co.uk/the-paper/v47/n06/a".into()],
+            title: "Vol. 47 No. 6 · 20 March 2025".into(),
+            identity: IssueIdentity::from_date_text("20 March 2025"),
+            css: String::new(),
+            cover_image_uri: String::new(),
+            publication_name: "London Review of Books".into(),
+        }
+    }
+
+    fn articles() -> Vec<ArticleData> {
+        vec![ArticleData {
+            title: "Courting in lovely England".into(),
+            author: Some("Officer Stabler".into()),
+            body: r##"<h2>Reviewed:</h2>
+                <ul><li><em>Pride and Prejudice</em> by Jane Austen<br>Faber, 265 pp., £4.95</li></ul>
+                <p>It is a truth universally acknowledged<a href="#fn1">*</a> that a single man in
+                possession of a good fortune must be in want of a wife.</p>
+                <p id="fn1"><a href="#fn1-ref">*</a> Or so the novel claims.</p>"##
+                .into(),
+        }]
+    }
+
+    #[test]
+    fn test_issue_file_has_contents_and_wrapped_articles() {
+        let dir = std::env::temp_dir().join(format!("magaziner-txt-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("issue.txt");
+        let options = TextOptions {
+            wrap: 40,
+            per_article: false,
+        };
+        write_text(
+            &path,
+            &issue(),
+            &articles(),
+            &options,
+            &mut Progress::new(Verbosity::Quiet),
+        )
+        .unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with(
+            "London Review of Books\nVol. 47 No. 6 · 20 March 2025\n\nContents\n\n\
+             1. Courting in lovely England — Officer\n   Stabler\n"
+        ));
+        assert!(text.contains(
+            "Courting in lovely England\n==========================\n\nby Officer Stabler\n\n\
+             Reviewed:\n---------\n\n\
+             • Pride and Prejudice by Jane Austen\n  Faber, 265 pp., £4.95\n"
+        ));
+        assert!(text.contains(
+            "\nIt is a truth universally acknowledged\n\
+             [Note: Or so the novel claims.] that\n"
+        ));
+        assert!(!text.contains("\n* Or so"));
+        assert!(text.lines().all(|l| l.chars().count() <= 40));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_per_article_files_without_wrapping() {
+        let dir = std::env::temp_dir().join(format!("magaziner-txt-split-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("09-old.txt"), "old").unwrap();
+        let options = TextOptions {
+            wrap: 0,
+            per_article: true,
+        };
+        write_text(
+            &dir,
+            &issue(),
+            &articles(),
+            &options,
+            &mut Progress::new(Verbosity::Quiet),
+        )
+        .unwrap();
+
+        assert!(!dir.join("09-old.txt").exists());
+        let text = fs::read_to_string(dir.join("01-courting-in-lovely-england.txt")).unwrap();
+        assert!(text.starts_with("London Review of Books\n"));
+        assert!(text.contains(
+            "\nIt is a truth universally acknowledged [Note: Or so the novel claims.] \
+             that a single man in possession of a good fortune must be in want of a wife.\n"
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
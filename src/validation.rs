@@ -1,8 +1,10 @@
+use clap::ValueEnum;
 use regex::Regex;
 use url::Url;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
 pub enum MagazineSource {
+    #[value(name = "lrb")]
     LondonReview,
     Harpers,
 }